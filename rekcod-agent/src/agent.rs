@@ -7,11 +7,20 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use futures::TryStreamExt as _;
+use futures::{StreamExt as _, TryStreamExt as _};
 use http_range::HttpRange;
 use hyper::{HeaderMap, StatusCode};
 use rekcod_core::{
-    api::resp::{ApiJsonResponse, SystemInfoResponse},
+    api::{
+        req::{
+            ExecKillRequest, ImageFetchRequest, ImageLayersRequest, NodeGcRequest,
+            ProcessListRequest, ProcessSignalRequest,
+        },
+        resp::{
+            ApiJsonResponse, GcReportResponse, ImageLayersResponse, ProcessItemResponse,
+            SystemInfoResponse,
+        },
+    },
     auth::token_auth,
     http::ApiError,
 };
@@ -22,7 +31,14 @@ use tokio::{
 };
 use tokio_util::io::{ReaderStream, StreamReader};
 
-use crate::{config, job::sys::sys_info};
+use crate::{
+    config, image,
+    job::{
+        gc::{load_gc_policy, run_gc, GC_TRIGGER_MANUAL},
+        sys::sys_info,
+    },
+    process,
+};
 
 pub fn routers() -> Router {
     Router::new()
@@ -31,6 +47,13 @@ pub fn routers() -> Router {
         .route("/download_range", get(download_range_file))
         .route("/shell", post(shell_stream))
         .route("/sys", get(get_sys_info))
+        .route("/gc", post(gc))
+        .route("/process/list", post(list_process))
+        .route("/process/signal", post(signal_process))
+        .route("/exec/kill", post(kill_exec))
+        .route("/image/layers", post(image_layers))
+        .route("/image/chains", post(image_chains))
+        .route("/image/fetch", post(image_fetch))
         .route("/", get(|| async { "rekcod.agent agent" }))
         .layer(middleware::from_fn(token_auth))
 }
@@ -220,3 +243,64 @@ async fn shell_stream(Json(req): Json<ShellRequest>) -> Result<Response, ApiErro
 async fn get_sys_info() -> Result<Json<ApiJsonResponse<SystemInfoResponse>>, ApiError> {
     Ok(ApiJsonResponse::success(sys_info().into()).into())
 }
+
+async fn gc(
+    Json(req): Json<NodeGcRequest>,
+) -> Result<Json<ApiJsonResponse<GcReportResponse>>, ApiError> {
+    let policy = match req.policy {
+        Some(policy) => policy,
+        None => load_gc_policy().await,
+    };
+    let report = run_gc(&policy, req.dry_run, GC_TRIGGER_MANUAL).await?;
+    Ok(ApiJsonResponse::success(report).into())
+}
+
+async fn list_process(
+    Json(req): Json<ProcessListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ProcessItemResponse>>>, ApiError> {
+    Ok(ApiJsonResponse::success(process::list_process(req).await?).into())
+}
+
+async fn signal_process(
+    Json(req): Json<ProcessSignalRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let signal = match process::check_signal(&req).await {
+        Ok(signal) => signal,
+        Err(e) => return Ok(ApiJsonResponse::empty_error(403, &e.to_string()).into()),
+    };
+    process::signal_process(req.pid, signal)?;
+    Ok(ApiJsonResponse::empty_success().into())
+}
+
+async fn kill_exec(
+    Json(req): Json<ExecKillRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    process::kill_exec(&req.exec_id).await?;
+    Ok(ApiJsonResponse::empty_success().into())
+}
+
+async fn image_layers(
+    Json(req): Json<ImageLayersRequest>,
+) -> Result<Json<ApiJsonResponse<ImageLayersResponse>>, ApiError> {
+    Ok(ApiJsonResponse::success(image::save_image(&req.image).await?).into())
+}
+
+async fn image_chains() -> Result<Json<ApiJsonResponse<Vec<String>>>, ApiError> {
+    Ok(ApiJsonResponse::success(image::local_chain_ids().await?).into())
+}
+
+/// the progress is streamed line by line, a failure is the last line
+async fn image_fetch(Json(req): Json<ImageFetchRequest>) -> Result<Response, ApiError> {
+    let (tx, rx) = futures::channel::mpsc::unbounded::<String>();
+    tokio::spawn(async move {
+        if let Err(e) = image::fetch_image(&req, &tx).await {
+            tracing::error!("fetch image {} error: {:?}", req.id, e);
+            let _ = tx.unbounded_send(format!("error: {}\n", e));
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(axum::http::header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from_stream(rx.map(anyhow::Ok)))?)
+}
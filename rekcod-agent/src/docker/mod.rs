@@ -1,4 +1,5 @@
-use hyper::Uri;
+use axum::body::{Body, Bytes};
+use hyper::{Request, Uri};
 
 #[cfg(unix)]
 pub mod unix;
@@ -20,6 +21,33 @@ impl DockerProxyClient {
         #[cfg(windows)]
         return win::SocketFileClient::new_client();
     }
+
+    /// post to the local docker api directly, for apis bollard does not cover
+    pub(crate) async fn post(&self, path_query: &str) -> anyhow::Result<Bytes> {
+        // only one variant exists on each platform
+        #[allow(clippy::infallible_destructuring_match)]
+        let c = match self {
+            #[cfg(unix)]
+            DockerProxyClient::Unix(c) => c,
+            #[cfg(windows)]
+            DockerProxyClient::Windows(c) => c,
+        };
+
+        let req = Request::post(c.uri(path_query)?).body(Body::empty())?;
+        let res = c.request(req).await?;
+        let status = res.status();
+        let body = axum::body::to_bytes(Body::new(res.into_body()), usize::MAX).await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "docker api {} error: {} {}",
+                path_query,
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+
+        Ok(body)
+    }
 }
 
 pub(crate) trait DockerProxyInterface {
@@ -0,0 +1,503 @@
+use std::{
+    collections::HashSet,
+    io::Read as _,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bollard::image::{ImportImageOptions, ListImagesOptions};
+use futures::{channel::mpsc::UnboundedSender, StreamExt as _};
+use once_cell::sync::Lazy;
+use rekcod_core::{
+    api::{
+        req::ImageFetchRequest,
+        resp::{ImageLayersResponse, ImageTransferFile, ImageTransferLayer},
+    },
+    client::get_client,
+    docker::local_connect,
+};
+use serde::Deserialize;
+use sha2::{Digest as _, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt as _};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+use crate::config;
+
+/// saved and fetched images are staged in this dir of the agent data path
+const TRANSFER_DIR: &str = "image-transfer";
+const TRANSFER_FILE_NAME: &str = "transfer.json";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+/// a saved image is kept for the peers that fetch it, a partly fetched one to resume
+const TRANSFER_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// one save of the same image at a time
+static SAVE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+/// images being fetched
+static FETCHING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// an entry of the manifest.json of docker save
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SaveManifest {
+    config: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    layers: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ImageConfig {
+    rootfs: ImageRootFs,
+}
+
+#[derive(Deserialize, Debug)]
+struct ImageRootFs {
+    diff_ids: Vec<String>,
+}
+
+/// save the image with docker save and unpack it, so peers can fetch single files
+pub(crate) async fn save_image(image: &str) -> anyhow::Result<ImageLayersResponse> {
+    let _lock = SAVE_LOCK.lock().await;
+    let docker = local_connect();
+    let id = docker
+        .inspect_image(image)
+        .await?
+        .id
+        .ok_or_else(|| anyhow::anyhow!("image {} has no id", image))?;
+
+    // the tags of the save follow the reference, so it is part of the key
+    let dir = transfer_dir().join(format!(
+        "export-{}-{}",
+        short_id(&id),
+        &hex::encode(Sha256::digest(image))[..12]
+    ));
+    if let Ok(data) = tokio::fs::read(dir.join(TRANSFER_FILE_NAME)).await {
+        if let Ok(saved) = serde_json::from_slice::<ImageLayersResponse>(&data) {
+            return Ok(saved);
+        }
+    }
+    remove_expired().await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let tar_path = dir.with_extension("tar");
+    let res = async {
+        let mut file = File::create(&tar_path).await?;
+        let mut stream = docker.export_image(image);
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+
+        let (tar_path, root) = (tar_path.clone(), dir.clone());
+        let files = tokio::task::spawn_blocking(move || unpack(&tar_path, &root)).await??;
+        describe(&dir, &id, files).await
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&tar_path).await;
+
+    match res {
+        Ok(saved) => {
+            tokio::fs::write(dir.join(TRANSFER_FILE_NAME), serde_json::to_vec(&saved)?).await?;
+            info!(
+                "saved image {} with {} layers for transfer",
+                image,
+                saved.layers.len()
+            );
+            Ok(saved)
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&dir).await;
+            Err(e)
+        }
+    }
+}
+
+/// the layer chain ids of every local image
+pub(crate) async fn local_chain_ids() -> anyhow::Result<Vec<String>> {
+    let docker = local_connect();
+    let images = docker
+        .list_images(Some(ListImagesOptions::<String> {
+            ..Default::default()
+        }))
+        .await?;
+
+    let mut chains = HashSet::new();
+    for image in images {
+        let Ok(inspect) = docker.inspect_image(&image.id).await else {
+            continue;
+        };
+        if let Some(layers) = inspect.root_fs.and_then(|r| r.layers) {
+            chains.extend(chain_ids(&layers));
+        }
+    }
+    Ok(chains.into_iter().collect())
+}
+
+/// fetch the files of a saved image from the agent of the source node and load it,
+/// partly fetched files are resumed by the next fetch of the same image
+pub(crate) async fn fetch_image(
+    req: &ImageFetchRequest,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<()> {
+    if !FETCHING.lock().unwrap().insert(req.id.clone()) {
+        return Err(anyhow::anyhow!("image {} is already being fetched", req.id));
+    }
+    let res = fetch_and_load(req, tx).await;
+    FETCHING.lock().unwrap().remove(&req.id);
+    res
+}
+
+async fn fetch_and_load(
+    req: &ImageFetchRequest,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<()> {
+    let send = |line: String| {
+        let _ = tx.unbounded_send(format!("{}\n", line));
+    };
+    let dir = transfer_dir().join(format!("import-{}", short_id(&req.id)));
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let client = get_client()?;
+    let mut limit = RateLimit::new(req.bandwidth_limit);
+    for file in &req.files {
+        let path = safe_join(&dir, &file.path)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut offset = tokio::fs::metadata(&path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        if offset == file.size && hash_file(&path).await? == file.digest {
+            send(format!("have {}", file.path));
+            continue;
+        }
+        if offset >= file.size {
+            tokio::fs::remove_file(&path).await?;
+            offset = 0;
+        }
+        if offset > 0 {
+            send(format!("resume {} at {}/{}", file.path, offset, file.size));
+        } else {
+            send(format!("fetch {} ({} bytes)", file.path, file.size));
+        }
+
+        let mut request = client.get(format!("{}/download_range", req.source)).header(
+            "file_path",
+            Path::new(&req.root)
+                .join(&file.path)
+                .to_string_lossy()
+                .as_ref(),
+        );
+        if offset > 0 {
+            request = request.header(hyper::header::RANGE, format!("bytes={}-", offset));
+        }
+        let res = request.send().await?.error_for_status()?;
+        let mut out = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            out.write_all(&chunk).await?;
+            limit.consume(chunk.len() as u64).await;
+        }
+        out.flush().await?;
+
+        if hash_file(&path).await? != file.digest {
+            tokio::fs::remove_file(&path).await?;
+            return Err(anyhow::anyhow!("digest of {} does not match", file.path));
+        }
+    }
+
+    send(format!("load image {}", req.id));
+    let tar_path = dir.with_extension("tar");
+    let res = load(&dir, &tar_path, &req.files, send).await;
+    let _ = tokio::fs::remove_file(&tar_path).await;
+    res?;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    info!("loaded image {} from {}", req.id, req.source);
+    Ok(())
+}
+
+/// docker load skips the layers it already has, so their files are not needed
+async fn load(
+    dir: &Path,
+    tar_path: &Path,
+    files: &[ImageTransferFile],
+    send: impl Fn(String),
+) -> anyhow::Result<()> {
+    let (root, target) = (dir.to_path_buf(), tar_path.to_path_buf());
+    let paths = files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let mut builder = tar::Builder::new(std::fs::File::create(&target)?);
+        for path in paths {
+            builder.append_path_with_name(root.join(&path), &path)?;
+        }
+        builder.finish()?;
+        Ok(())
+    })
+    .await??;
+
+    let file = File::open(tar_path).await?;
+    let body = ReaderStream::new(file)
+        .take_while(|chunk| {
+            if let Err(e) = chunk {
+                warn!("read image archive error: {}", e);
+            }
+            futures::future::ready(chunk.is_ok())
+        })
+        .filter_map(|chunk| futures::future::ready(chunk.ok()));
+    let mut stream = local_connect().import_image_stream(ImportImageOptions::default(), body, None);
+    while let Some(info) = stream.next().await {
+        let info = info?;
+        if let Some(e) = info.error {
+            return Err(anyhow::anyhow!("load image error: {}", e));
+        }
+        if let Some(line) = info.stream.filter(|x| !x.trim().is_empty()) {
+            send(line.trim_end().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// unpack the docker save archive, return the files with their digests
+fn unpack(tar_path: &Path, dir: &Path) -> anyhow::Result<Vec<ImageTransferFile>> {
+    let mut archive = tar::Archive::new(std::fs::File::open(tar_path)?);
+    let mut paths = Vec::new();
+    let mut links = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() {
+            // docker save links the layers an image has more than once
+            if let Some(target) = entry.link_name()? {
+                let target = path.parent().unwrap_or(Path::new("")).join(target);
+                links.push((path, target));
+            }
+        } else if entry_type.is_file() && entry.unpack_in(dir)? {
+            paths.push(path);
+        }
+    }
+    for (path, target) in links {
+        let target = safe_join(dir, normalize(&target))?;
+        std::fs::copy(target, safe_join(dir, &path)?)?;
+        paths.push(path);
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let (size, digest) = hash_file_sync(&dir.join(&path))?;
+            Ok(ImageTransferFile {
+                path: path.to_string_lossy().replace('\\', "/"),
+                size,
+                digest,
+            })
+        })
+        .collect()
+}
+
+async fn describe(
+    dir: &Path,
+    id: &str,
+    files: Vec<ImageTransferFile>,
+) -> anyhow::Result<ImageLayersResponse> {
+    let manifest = tokio::fs::read(dir.join(MANIFEST_FILE_NAME)).await?;
+    let manifest = serde_json::from_slice::<Vec<SaveManifest>>(&manifest)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("manifest of image {} is empty", id))?;
+    let config = tokio::fs::read(safe_join(dir, &manifest.config)?).await?;
+    let config = serde_json::from_slice::<ImageConfig>(&config)?;
+    if config.rootfs.diff_ids.len() != manifest.layers.len() {
+        return Err(anyhow::anyhow!("layers of image {} do not match", id));
+    }
+
+    let layers = manifest
+        .layers
+        .into_iter()
+        .zip(chain_ids(&config.rootfs.diff_ids))
+        .map(|(path, chain_id)| ImageTransferLayer { path, chain_id })
+        .collect();
+    Ok(ImageLayersResponse {
+        id: id.to_string(),
+        root: dir.to_string_lossy().to_string(),
+        repo_tags: manifest.repo_tags.unwrap_or_default(),
+        files,
+        layers,
+    })
+}
+
+/// the chain id of a layer covers every layer below it
+fn chain_ids(diff_ids: &[String]) -> Vec<String> {
+    let mut parent: Option<String> = None;
+    diff_ids
+        .iter()
+        .map(|diff_id| {
+            let chain_id = match &parent {
+                Some(parent) => format!(
+                    "sha256:{}",
+                    hex::encode(Sha256::digest(format!("{} {}", parent, diff_id)))
+                ),
+                None => diff_id.clone(),
+            };
+            parent = Some(chain_id.clone());
+            chain_id
+        })
+        .collect()
+}
+
+struct RateLimit {
+    bytes_per_sec: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl RateLimit {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.filter(|x| *x > 0),
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// sleep until the bytes so far fit the limit
+    async fn consume(&mut self, bytes: u64) {
+        let Some(bytes_per_sec) = self.bytes_per_sec else {
+            return;
+        };
+        self.bytes += bytes;
+        let expect = Duration::from_secs_f64(self.bytes as f64 / bytes_per_sec as f64);
+        let elapsed = self.start.elapsed();
+        if expect > elapsed {
+            tokio::time::sleep(expect - elapsed).await;
+        }
+    }
+}
+
+fn transfer_dir() -> PathBuf {
+    Path::new(&config::rekcod_agent_config().data_path).join(TRANSFER_DIR)
+}
+
+async fn remove_expired() {
+    let Ok(mut entries) = tokio::fs::read_dir(transfer_dir()).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let expired = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .is_some_and(|age| age > TRANSFER_TTL);
+        if expired {
+            info!("remove expired image transfer {}", entry.path().display());
+            let _ = tokio::fs::remove_dir_all(entry.path()).await;
+        }
+    }
+}
+
+fn short_id(id: &str) -> &str {
+    let id = id.strip_prefix("sha256:").unwrap_or(id);
+    &id[..id.len().min(12)]
+}
+
+/// a relative path in the dir, a path out of it is refused
+fn safe_join(dir: &Path, path: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+    let path = path.as_ref();
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(anyhow::anyhow!("invalid path {}", path.display()));
+    }
+    Ok(dir.join(path))
+}
+
+/// resolve `..` of a link target in the archive
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(c) => normalized.push(c),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+async fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let path = path.to_path_buf();
+    Ok(tokio::task::spawn_blocking(move || hash_file_sync(&path))
+        .await??
+        .1)
+}
+
+fn hash_file_sync(path: &Path) -> anyhow::Result<(u64, String)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((size, format!("sha256:{}", hex::encode(hasher.finalize()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_ids() {
+        let diff_ids = vec![
+            "sha256:a".to_string(),
+            "sha256:b".to_string(),
+            "sha256:c".to_string(),
+        ];
+        let chains = chain_ids(&diff_ids);
+        assert_eq!(chains[0], "sha256:a");
+        assert_eq!(
+            chains[1],
+            format!(
+                "sha256:{}",
+                hex::encode(Sha256::digest("sha256:a sha256:b"))
+            )
+        );
+        assert_eq!(chain_ids(&diff_ids[..2]), chains[..2]);
+        assert_ne!(chain_ids(&["sha256:c".to_string()])[0], chains[2]);
+    }
+
+    #[test]
+    fn test_safe_join() {
+        let dir = Path::new("/data");
+        assert_eq!(
+            safe_join(dir, "blobs/sha256/ab").unwrap(),
+            Path::new("/data/blobs/sha256/ab")
+        );
+        assert!(safe_join(dir, "../etc/passwd").is_err());
+        assert!(safe_join(dir, "/etc/passwd").is_err());
+        assert_eq!(
+            normalize(Path::new("abc/../def/layer.tar")),
+            Path::new("def/layer.tar")
+        );
+    }
+}
@@ -1,110 +1,401 @@
 use std::{
-    collections::HashMap,
-    time::{self, SystemTime},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
 use bollard::container::ListContainersOptions;
-use rekcod_core::docker::local_connect;
-use tokio::sync::mpsc;
+use once_cell::sync::Lazy;
+use rekcod_core::{
+    api::req::{ContainerRestartAction, ContainerRestartEventRequest},
+    client::get_client,
+    constants::REKCOD_SERVER_PREFIX_PATH,
+    docker::local_connect,
+    utils::unix_timestamp,
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use crate::{config, job::register::node_name};
+
+/// opt in restart policy label, e.g. `rekcod.restart=on-failure`
+const RESTART_LABEL: &str = "rekcod.restart";
+/// max restart times label, e.g. `rekcod.restart.max=5`
+const RESTART_MAX_LABEL: &str = "rekcod.restart.max";
+
+const BACKOFF_BASE: Duration = Duration::from_secs(5);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// restart history is dropped once the container keeps running this long
+const STABLE_AFTER: Duration = Duration::from_secs(600);
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(300);
+const CRASH_LOOP_THRESHOLD: usize = 5;
+const STOPPED_CONTAINERS_FILE_NAME: &str = "stopped_containers.json";
 
-struct DebounceState {
-    pub timeout: SystemTime,
+/// containers stopped on purpose by `docker stop`, updated by the event monitor and kept
+/// in the data dir so they stay stopped after the agent restarts
+static STOPPED_CONTAINERS: Lazy<Mutex<HashSet<String>>> =
+    Lazy::new(|| Mutex::new(load_stopped_containers()));
+
+pub(crate) fn set_container_stopped(id: &str, stopped: bool) {
+    let mut stopped_containers = STOPPED_CONTAINERS.lock().unwrap();
+    let changed = if stopped {
+        stopped_containers.insert(id.to_string())
+    } else {
+        stopped_containers.remove(id)
+    };
+    if changed {
+        if let Err(e) = save_stopped_containers(&stopped_containers) {
+            warn!("save stopped containers error: {:?}", e);
+        }
+    }
 }
 
-impl DebounceState {
-    fn expire(&self) -> bool {
-        let current = time::SystemTime::now();
-        let exp = match current.duration_since(self.timeout) {
-            Ok(d) => d,
-            Err(_) => {
-                return false;
-            }
-        };
-        exp.as_secs() > 60
+fn stopped_containers_path() -> PathBuf {
+    Path::new(&config::rekcod_agent_config().data_path).join(STOPPED_CONTAINERS_FILE_NAME)
+}
+
+fn load_stopped_containers() -> HashSet<String> {
+    let path = stopped_containers_path();
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashSet::new(),
+        Err(e) => {
+            warn!("read stopped containers {} error: {:?}", path.display(), e);
+            return HashSet::new();
+        }
+    };
+    serde_json::from_slice(&data).unwrap_or_else(|e| {
+        warn!("parse stopped containers {} error: {:?}", path.display(), e);
+        HashSet::new()
+    })
+}
+
+fn save_stopped_containers(stopped_containers: &HashSet<String>) -> anyhow::Result<()> {
+    let path = stopped_containers_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec(stopped_containers)?)?;
+    Ok(())
+}
+
+fn is_container_stopped(id: &str) -> bool {
+    STOPPED_CONTAINERS.lock().unwrap().contains(id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RestartPolicy {
+    No,
+    Always,
+    OnFailure,
+}
+
+impl RestartPolicy {
+    fn parse(policy: &str) -> Option<Self> {
+        match policy.trim() {
+            "no" => Some(RestartPolicy::No),
+            "always" => Some(RestartPolicy::Always),
+            "on-failure" => Some(RestartPolicy::OnFailure),
+            _ => None,
+        }
+    }
+
+    fn should_restart(&self, exit_code: Option<i64>, unhealthy: bool) -> bool {
+        match self {
+            RestartPolicy::No => false,
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => unhealthy || exit_code.is_some_and(|c| c != 0),
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::OnFailure => write!(f, "on-failure"),
+        }
     }
 }
 
 #[derive(Debug)]
-struct ContainerInfo {
-    pub id: String,
-    pub name: String,
+struct RestartSpec {
+    policy: RestartPolicy,
+    max: Option<u32>,
+}
+
+impl RestartSpec {
+    fn from_labels(labels: &HashMap<String, String>) -> Option<Self> {
+        let policy = RestartPolicy::parse(labels.get(RESTART_LABEL)?)?;
+        let max = labels
+            .get(RESTART_MAX_LABEL)
+            .and_then(|m| m.trim().parse().ok());
+        Some(RestartSpec { policy, max })
+    }
+}
+
+/// wait time before the next restart, doubles for every restart already done
+fn backoff(restart_count: u32) -> Duration {
+    if restart_count == 0 {
+        return Duration::ZERO;
+    }
+    BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(restart_count - 1))
+        .min(BACKOFF_MAX)
+}
+
+#[derive(Debug, Default)]
+struct RestartState {
+    restart_count: u32,
+    last_restart: Option<Instant>,
+    recent: VecDeque<Instant>,
+    crash_loop: bool,
+    gave_up: bool,
+}
+
+impl RestartState {
+    fn ready(&self, now: Instant) -> bool {
+        match self.last_restart {
+            Some(last) => now.duration_since(last) >= backoff(self.restart_count),
+            None => true,
+        }
+    }
+
+    fn stable(&self, now: Instant) -> bool {
+        self.last_restart
+            .is_none_or(|last| now.duration_since(last) >= STABLE_AFTER)
+    }
+
+    /// record a restart, return true if the container just went into a crash loop
+    fn record(&mut self, now: Instant) -> bool {
+        self.restart_count += 1;
+        self.last_restart = Some(now);
+        self.recent.push_back(now);
+        while let Some(first) = self.recent.front() {
+            if now.duration_since(*first) > CRASH_LOOP_WINDOW {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let crash_loop = self.recent.len() >= CRASH_LOOP_THRESHOLD;
+        let changed = crash_loop && !self.crash_loop;
+        self.crash_loop = crash_loop;
+        changed
+    }
 }
 
 pub(crate) async fn docker_health_monitor(cancel: CancellationToken) -> anyhow::Result<()> {
-    let docker = local_connect();
-    let mut remaining: HashMap<String, DebounceState> = HashMap::new();
-    let (debounce_tx, mut debounce_rx) = mpsc::channel::<ContainerInfo>(100);
+    let mut states: HashMap<String, RestartState> = HashMap::new();
     loop {
         tokio::select! {
             _ = cancel.cancelled() => {
                 break;
             }
-            Some(container) = debounce_rx.recv() => {
-                match remaining.remove(&container.id) {
-                    Some(mut state) => {
-                        if !state.expire() {
-                            remaining.insert(container.id.clone(), state);
-                            continue;
-                        } else {
-                            state.timeout = time::SystemTime::now();
-                        }
-                        remaining.insert(container.id.clone(), state);
-                    },
-                    None => {
-                        remaining.insert(container.id.clone(), DebounceState {
-                            timeout: time::SystemTime::now(),
-                        });
-                    }
-                };
-
-                // restart docker container
-                info!("restarting container({}): {}", &container.name, &container.id);
-                match docker.restart_container(&container.id, None).await {
-                    Ok(_) => {
-                        info!("restarted container({}): {}", &container.name, &container.id);
-                    },
-                    Err(e) => {
-                        error!("restart container({}): {} error: {}", &container.name, &container.id, e);
-                    }
-                };
-
-                // clear timeout for container
-                remaining.retain(|_, d| !d.expire());
+            _ = tokio::time::sleep(Duration::from_secs(10)) => {
+                if let Err(e) = check_containers(&mut states).await {
+                    error!("restart loop in list containers error: {}", e);
+                }
             }
-            _ = tokio::time::sleep(std::time::Duration::from_secs(10)) => {
-                let mut filters = HashMap::new();
-                // filters.insert("health", vec!["unhealthy"]);
-                filters.insert("status", vec!["exited"]);
-
-                let options = Some(ListContainersOptions {
-                    all: true,
-                    filters,
-                    ..Default::default()
-                });
-                match docker.list_containers(options).await
-                {
-                    Ok(containers) => {
-                        for container in containers {
-                            if let (Some(container_id), Some(names)) = (container.id, container.names) {
-                                let name = names.iter().next().map_or("".to_string(), |n| n.to_string());
-                                debounce_tx.send(
-                                    ContainerInfo {
-                                        id: container_id,
-                                        name: name,
-                                    }
-                                ).await?;
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        error!("restart loop in list containers error: {}", e);
-                    }
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_containers(states: &mut HashMap<String, RestartState>) -> anyhow::Result<()> {
+    let docker = local_connect();
+    let mut filters = HashMap::new();
+    filters.insert("label", vec![RESTART_LABEL]);
+
+    let options = Some(ListContainersOptions {
+        all: true,
+        filters,
+        ..Default::default()
+    });
+    let containers = docker.list_containers(options).await?;
+
+    let now = Instant::now();
+    let mut seen = HashSet::new();
+    for container in containers {
+        let (Some(id), Some(labels)) = (container.id, container.labels) else {
+            continue;
+        };
+        seen.insert(id.clone());
+
+        let Some(spec) = RestartSpec::from_labels(&labels) else {
+            continue;
+        };
+        let name = container
+            .names
+            .and_then(|n| n.into_iter().next())
+            .unwrap_or_default();
+        let unhealthy = container
+            .status
+            .as_deref()
+            .is_some_and(|s| s.contains("(unhealthy)"));
+
+        let reason = match container.state.as_deref() {
+            Some("exited") => "exited",
+            Some("running") if unhealthy => "unhealthy",
+            Some("running") => {
+                if states.get(&id).is_some_and(|s| s.stable(now)) {
+                    states.remove(&id);
                 }
+                continue;
             }
+            _ => continue,
+        };
+
+        if is_container_stopped(&id) {
+            continue;
+        }
+
+        let exit_code = if reason == "exited" {
+            docker
+                .inspect_container(&id, None)
+                .await
+                .ok()
+                .and_then(|c| c.state)
+                .and_then(|s| s.exit_code)
+        } else {
+            None
+        };
+        if !spec.policy.should_restart(exit_code, unhealthy) {
+            continue;
+        }
+
+        let state = states.entry(id.clone()).or_default();
+        if state.gave_up || !state.ready(now) {
+            continue;
         }
+
+        let mut event = ContainerRestartEventRequest {
+            node_name: node_name(),
+            container_id: id.clone(),
+            container_name: name.clone(),
+            policy: spec.policy.to_string(),
+            reason: reason.to_string(),
+            exit_code,
+            restart_count: state.restart_count,
+            time: unix_timestamp(),
+            ..Default::default()
+        };
+
+        if spec.max.is_some_and(|max| state.restart_count >= max) {
+            warn!(
+                "container({}): {} reached max restart times {}, give up",
+                &name, &id, state.restart_count
+            );
+            state.gave_up = true;
+            event.action = ContainerRestartAction::GaveUp;
+            report_restart_event(&event).await;
+            continue;
+        }
+
+        // restart docker container
+        info!("restarting container({}): {}", &name, &id);
+        let result = docker.restart_container(&id, None).await;
+        if state.record(now) {
+            warn!(
+                "container({}): {} is in crash loop, restarted {} times",
+                &name, &id, state.restart_count
+            );
+        }
+        event.restart_count = state.restart_count;
+        event.action = match result {
+            Ok(_) => {
+                info!("restarted container({}): {}", &name, &id);
+                if state.crash_loop {
+                    ContainerRestartAction::CrashLoop
+                } else {
+                    ContainerRestartAction::Restarted
+                }
+            }
+            Err(e) => {
+                error!("restart container({}): {} error: {}", &name, &id, e);
+                ContainerRestartAction::Failed
+            }
+        };
+        report_restart_event(&event).await;
     }
 
+    // clear state for removed containers
+    states.retain(|id, _| seen.contains(id));
     Ok(())
 }
+
+async fn report_restart_event(event: &ContainerRestartEventRequest) {
+    let config = config::rekcod_agent_config();
+    let url = format!(
+        "http://{}{}/node/container/restart/report",
+        config.master_host, REKCOD_SERVER_PREFIX_PATH
+    );
+
+    let client = match get_client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("report restart event error: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = client.post(url).json(event).send().await {
+        error!("report restart event error: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_spec_from_labels() {
+        let labels = HashMap::from([
+            (RESTART_LABEL.to_string(), "on-failure".to_string()),
+            (RESTART_MAX_LABEL.to_string(), "5".to_string()),
+        ]);
+        let spec = RestartSpec::from_labels(&labels).unwrap();
+        assert_eq!(spec.policy, RestartPolicy::OnFailure);
+        assert_eq!(spec.max, Some(5));
+
+        let labels = HashMap::from([(RESTART_LABEL.to_string(), "sometimes".to_string())]);
+        assert!(RestartSpec::from_labels(&labels).is_none());
+        assert!(RestartSpec::from_labels(&HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_should_restart() {
+        assert!(!RestartPolicy::No.should_restart(Some(1), true));
+        assert!(RestartPolicy::Always.should_restart(Some(0), false));
+        assert!(!RestartPolicy::OnFailure.should_restart(Some(0), false));
+        assert!(RestartPolicy::OnFailure.should_restart(Some(137), false));
+        assert!(RestartPolicy::OnFailure.should_restart(None, true));
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0), Duration::ZERO);
+        assert_eq!(backoff(1), Duration::from_secs(5));
+        assert_eq!(backoff(3), Duration::from_secs(20));
+        assert_eq!(backoff(10), BACKOFF_MAX);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_crash_loop() {
+        let start = Instant::now();
+        let mut state = RestartState::default();
+        for i in 0..CRASH_LOOP_THRESHOLD as u64 - 1 {
+            assert!(!state.record(start + Duration::from_secs(i * 10)));
+        }
+        assert!(state.record(start + Duration::from_secs(60)));
+        assert!(state.crash_loop);
+        // restarts outside the window do not count
+        assert!(!state.record(start + CRASH_LOOP_WINDOW * 3));
+        assert!(!state.crash_loop);
+    }
+}
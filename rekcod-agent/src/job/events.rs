@@ -1,9 +1,12 @@
 use std::{collections::HashMap, time::Duration};
 
+use bollard::secret::EventMessage;
 use futures::StreamExt as _;
 use rekcod_core::docker::local_connect;
 use tokio_util::sync::CancellationToken;
 
+use crate::job::container::set_container_stopped;
+
 pub(crate) async fn docker_event_monitor(cancel: CancellationToken) -> anyhow::Result<()> {
     let docker = local_connect();
     let interval = 5;
@@ -18,11 +21,14 @@ pub(crate) async fn docker_event_monitor(cancel: CancellationToken) -> anyhow::R
                         &(chrono::Utc::now() - Duration::from_secs(interval)),
                     )),
                     until: Some(chrono::Utc::now()),
-                    filters: HashMap::new(),
+                    filters: HashMap::from([("type".to_string(), vec!["container".to_string()])]),
                 };
                 let mut events = docker.events(Some(option));
-                while let Some(_event) = events.next().await {
+                while let Some(event) = events.next().await {
                     //info!("event: {:?}", event);
+                    if let Ok(event) = event {
+                        track_container_event(&event);
+                    }
                 }
             }
         }
@@ -30,3 +36,17 @@ pub(crate) async fn docker_event_monitor(cancel: CancellationToken) -> anyhow::R
 
     Ok(())
 }
+
+/// remember containers stopped on purpose, the restart policy will not bring them back
+fn track_container_event(event: &EventMessage) {
+    let id = match event.actor.as_ref().and_then(|a| a.id.as_deref()) {
+        Some(id) => id,
+        None => return,
+    };
+
+    match event.action.as_deref() {
+        Some("stop") => set_container_stopped(id, true),
+        Some("start") | Some("destroy") => set_container_stopped(id, false),
+        _ => {}
+    }
+}
@@ -0,0 +1,455 @@
+use std::{path::Path, time::Duration};
+
+use bollard::{
+    container::RemoveContainerOptions, image::RemoveImageOptions, secret::SystemDataUsageResponse,
+};
+use rekcod_core::{
+    api::{
+        req::GcPolicy,
+        resp::{GcItem, GcReportResponse},
+    },
+    client::get_client,
+    constants::REKCOD_SERVER_PREFIX_PATH,
+    docker::local_connect,
+    utils::unix_timestamp,
+};
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+    config,
+    docker::DockerProxyClient,
+    job::{register::node_name, sys::sys_info},
+};
+
+/// gc policy file in the agent config path, default policy is used if not exists
+const GC_POLICY_FILE_NAME: &str = "gc.json";
+
+pub(crate) const GC_TRIGGER_WATERMARK: &str = "watermark";
+pub(crate) const GC_TRIGGER_MANUAL: &str = "manual";
+
+pub(crate) async fn gc_monitor(cancel: CancellationToken) -> anyhow::Result<()> {
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                break;
+            }
+            _ = tokio::time::sleep(Duration::from_secs(300)) => {
+                let policy = load_gc_policy().await;
+                if !policy.enabled {
+                    continue;
+                }
+
+                let disk_usage = match docker_disk_usage().await {
+                    Ok(usage) => usage,
+                    Err(e) => {
+                        error!("gc get disk usage error: {}", e);
+                        continue;
+                    }
+                };
+                if disk_usage < policy.high_watermark {
+                    continue;
+                }
+
+                info!("disk usage {:.1}% is above high watermark, start gc", disk_usage);
+                match run_gc(&policy, false, GC_TRIGGER_WATERMARK).await {
+                    Ok(report) => report_gc(&report).await,
+                    Err(e) => error!("gc error: {}", e),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn load_gc_policy() -> GcPolicy {
+    let config = config::rekcod_agent_config();
+    let path = Path::new(&config.config_path).join(GC_POLICY_FILE_NAME);
+    let content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(_) => return GcPolicy::default(),
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("load gc policy {:?} error: {}", path, e);
+            GcPolicy::default()
+        }
+    }
+}
+
+/// usage percent of the disk docker root dir is on
+async fn docker_disk_usage() -> anyhow::Result<f32> {
+    let (total, free) = docker_disk_space().await?;
+    Ok((total - free) as f32 / total as f32 * 100.0)
+}
+
+/// total and free bytes of the disk docker root dir is on
+async fn docker_disk_space() -> anyhow::Result<(u64, u64)> {
+    let root = local_connect()
+        .info()
+        .await?
+        .docker_root_dir
+        .unwrap_or("/var/lib/docker".to_string());
+
+    sys_info()
+        .disks
+        .iter()
+        .filter(|d| d.total > 0 && Path::new(&root).starts_with(&d.mount))
+        .max_by_key(|d| d.mount.len())
+        .map(|d| (d.total, d.free))
+        .ok_or_else(|| anyhow::anyhow!("can not find disk of docker root dir {}", root))
+}
+
+struct GcRun {
+    dry_run: bool,
+    min_created: i64,
+    /// bytes to reclaim before gc stops
+    target: u64,
+    reclaimed: u64,
+    items: Vec<GcItem>,
+}
+
+impl GcRun {
+    fn done(&self) -> bool {
+        self.reclaimed >= self.target
+    }
+
+    fn add(&mut self, kind: &str, id: &str, name: &str, size: i64) {
+        let size = size.max(0) as u64;
+        self.reclaimed += size;
+        self.items.push(GcItem {
+            kind: kind.to_string(),
+            id: id.to_string(),
+            name: name.to_string(),
+            size,
+        });
+    }
+}
+
+pub(crate) async fn run_gc(
+    policy: &GcPolicy,
+    dry_run: bool,
+    trigger: &str,
+) -> anyhow::Result<GcReportResponse> {
+    let (total, free) = docker_disk_space().await?;
+    let used = total - free;
+    // manual gc removes everything the policy allows
+    let target = if trigger == GC_TRIGGER_WATERMARK {
+        used.saturating_sub((total as f64 * policy.low_watermark as f64 / 100.0) as u64)
+    } else {
+        u64::MAX
+    };
+
+    let mut run = GcRun {
+        dry_run,
+        min_created: unix_timestamp() as i64 - policy.min_age as i64,
+        target,
+        reclaimed: 0,
+        items: vec![],
+    };
+
+    let df = local_connect().df().await?;
+    if policy.prune_containers {
+        gc_containers(&mut run, &df).await;
+    }
+    if policy.prune_images || policy.prune_unused_images {
+        gc_images(&mut run, &df, policy).await;
+    }
+    if policy.prune_build_cache {
+        if let Err(e) = gc_build_cache(&mut run, &df, policy.min_age).await {
+            warn!("gc build cache error: {}", e);
+        }
+    }
+    if policy.prune_volumes {
+        gc_volumes(&mut run, &df).await;
+    }
+
+    info!(
+        "gc({}) reclaimed {} bytes, {} items, dry run: {}",
+        trigger,
+        run.reclaimed,
+        run.items.len(),
+        dry_run
+    );
+    Ok(GcReportResponse {
+        node_name: node_name(),
+        dry_run,
+        trigger: trigger.to_string(),
+        disk_usage: used as f32 / total as f32 * 100.0,
+        items: run.items,
+        reclaimed: run.reclaimed,
+        time: unix_timestamp(),
+    })
+}
+
+async fn gc_containers(run: &mut GcRun, df: &SystemDataUsageResponse) {
+    let docker = local_connect();
+    let mut containers = df
+        .containers
+        .iter()
+        .flatten()
+        .filter(|c| matches!(c.state.as_deref(), Some("exited" | "created" | "dead")))
+        .filter(|c| c.created.is_some_and(|t| t < run.min_created))
+        .collect::<Vec<_>>();
+    containers.sort_by_key(|c| c.created);
+
+    for container in containers {
+        if run.done() {
+            return;
+        }
+        let Some(id) = container.id.as_deref() else {
+            continue;
+        };
+        let name = container
+            .names
+            .as_ref()
+            .and_then(|n| n.first().cloned())
+            .unwrap_or_default();
+
+        if !run.dry_run {
+            if let Err(e) = docker
+                .remove_container(id, None::<RemoveContainerOptions>)
+                .await
+            {
+                warn!("gc remove container({}): {} error: {}", name, id, e);
+                continue;
+            }
+        }
+        run.add("container", id, &name, container.size_rw.unwrap_or(0));
+    }
+}
+
+async fn gc_images(run: &mut GcRun, df: &SystemDataUsageResponse, policy: &GcPolicy) {
+    let docker = local_connect();
+    let mut images = df
+        .images
+        .iter()
+        .flatten()
+        .filter(|i| i.containers == 0 && i.created < run.min_created)
+        .filter(|i| {
+            if is_dangling_image(&i.repo_tags) {
+                policy.prune_images
+            } else {
+                policy.prune_unused_images && !keep_image(&policy.keep_images, &i.repo_tags)
+            }
+        })
+        .collect::<Vec<_>>();
+    images.sort_by_key(|i| i.created);
+
+    for image in images {
+        if run.done() {
+            return;
+        }
+        let name = image.repo_tags.join(",");
+        if !run.dry_run {
+            // force removes every tag of the image, it has no container as checked above
+            let options = RemoveImageOptions {
+                force: true,
+                ..Default::default()
+            };
+            if let Err(e) = docker.remove_image(&image.id, Some(options), None).await {
+                warn!("gc remove image({}): {} error: {}", name, image.id, e);
+                continue;
+            }
+        }
+        // layers shared with other images stay on the disk
+        run.add(
+            "image",
+            &image.id,
+            &name,
+            image.size - image.shared_size.max(0),
+        );
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase", default)]
+struct BuildPruneResponse {
+    space_reclaimed: u64,
+}
+
+async fn gc_build_cache(
+    run: &mut GcRun,
+    df: &SystemDataUsageResponse,
+    min_age: u64,
+) -> anyhow::Result<()> {
+    if run.done() {
+        return Ok(());
+    }
+
+    let mut caches = df
+        .build_cache
+        .iter()
+        .flatten()
+        .filter(|c| !c.in_use.unwrap_or(false))
+        .filter(|c| {
+            c.last_used_at
+                .or(c.created_at)
+                .is_some_and(|t| t.timestamp() < run.min_created)
+        })
+        .collect::<Vec<_>>();
+    if caches.is_empty() {
+        return Ok(());
+    }
+    // the prune removes the least recently used records first
+    caches.sort_by_key(|c| c.last_used_at.or(c.created_at));
+
+    if run.dry_run {
+        for cache in caches {
+            if run.done() {
+                break;
+            }
+            run.add(
+                "build_cache",
+                cache.id.as_deref().unwrap_or(""),
+                cache.description.as_deref().unwrap_or(""),
+                cache.size.unwrap_or(0),
+            );
+        }
+        return Ok(());
+    }
+
+    // build cache can only be pruned as a whole, keep-storage stops it at the target
+    let filters = format!(r#"{{"until":["{}s"]}}"#, min_age);
+    let mut query = format!("filters={}", encode_query(&filters));
+    let size = df
+        .build_cache
+        .iter()
+        .flatten()
+        .map(|c| c.size.unwrap_or(0).max(0) as u64)
+        .sum::<u64>();
+    if let Some(keep) = keep_storage(size, run.target - run.reclaimed) {
+        query.push_str(&format!("&keep-storage={}", keep));
+    }
+    let body = DockerProxyClient::new()
+        .post(&format!("/build/prune?{}", query))
+        .await?;
+    let res: BuildPruneResponse = serde_json::from_slice(&body)?;
+    run.add(
+        "build_cache",
+        "",
+        &format!("{} records", caches.len()),
+        res.space_reclaimed as i64,
+    );
+    Ok(())
+}
+
+async fn gc_volumes(run: &mut GcRun, df: &SystemDataUsageResponse) {
+    let docker = local_connect();
+    let volumes = df
+        .volumes
+        .iter()
+        .flatten()
+        .filter(|v| v.usage_data.as_ref().is_some_and(|u| u.ref_count == 0))
+        .filter(|v| {
+            v.created_at
+                .is_some_and(|t| t.timestamp() < run.min_created)
+        })
+        .collect::<Vec<_>>();
+
+    for volume in volumes {
+        if run.done() {
+            return;
+        }
+        if !run.dry_run {
+            if let Err(e) = docker.remove_volume(&volume.name, None).await {
+                warn!("gc remove volume {} error: {}", volume.name, e);
+                continue;
+            }
+        }
+        let size = volume.usage_data.as_ref().map_or(0, |u| u.size);
+        run.add("volume", &volume.name, &volume.name, size);
+    }
+}
+
+async fn report_gc(report: &GcReportResponse) {
+    let config = config::rekcod_agent_config();
+    let url = format!(
+        "http://{}{}/node/gc/report",
+        config.master_host, REKCOD_SERVER_PREFIX_PATH
+    );
+
+    let client = match get_client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("report gc error: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = client.post(url).json(report).send().await {
+        error!("report gc error: {:?}", e);
+    }
+}
+
+fn is_dangling_image(repo_tags: &[String]) -> bool {
+    repo_tags.iter().all(|t| t == "<none>:<none>")
+}
+
+fn keep_image(patterns: &[String], repo_tags: &[String]) -> bool {
+    patterns.iter().any(|p| {
+        repo_tags.iter().any(|t| match p.strip_suffix('*') {
+            Some(prefix) => t.starts_with(prefix),
+            None => t == p,
+        })
+    })
+}
+
+/// bytes of build cache to keep so the prune reclaims `wanted`, none to prune all
+fn keep_storage(size: u64, wanted: u64) -> Option<u64> {
+    size.checked_sub(wanted).filter(|keep| *keep > 0)
+}
+
+fn encode_query(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_image() {
+        let patterns = vec!["redis:7".to_string(), "registry.local/*".to_string()];
+        assert!(keep_image(&patterns, &["redis:7".to_string()]));
+        assert!(!keep_image(&patterns, &["redis:6".to_string()]));
+        assert!(keep_image(
+            &patterns,
+            &["nginx:1".to_string(), "registry.local/app:1".to_string()]
+        ));
+        assert!(!keep_image(&[], &["redis:7".to_string()]));
+    }
+
+    #[test]
+    fn test_is_dangling_image() {
+        assert!(is_dangling_image(&[]));
+        assert!(is_dangling_image(&["<none>:<none>".to_string()]));
+        assert!(!is_dangling_image(&["redis:7".to_string()]));
+    }
+
+    #[test]
+    fn test_keep_storage() {
+        assert_eq!(keep_storage(100, 30), Some(70));
+        assert_eq!(keep_storage(100, 100), None);
+        assert_eq!(keep_storage(100, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_encode_query() {
+        assert_eq!(
+            encode_query(r#"{"until":["60s"]}"#),
+            "%7B%22until%22%3A%5B%2260s%22%5D%7D"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod container;
 pub(crate) mod events;
+pub(crate) mod gc;
 pub(crate) mod register;
 pub(crate) mod sys;
@@ -21,7 +21,7 @@ pub(crate) async fn register_node(cancel: CancellationToken) -> anyhow::Result<(
                     config.master_host, REKCOD_SERVER_PREFIX_PATH
                 );
 
-                let my_local_ip = local_ip_address::local_ip().map(|s| s.to_string()).unwrap_or("127.0.0.1".to_string());
+                let my_local_ip = node_name();
                 let sys = crate::job::sys::sys_info_global();
                 let req = RegisterNodeRequest {
                     name: my_local_ip.clone(),
@@ -47,3 +47,10 @@ pub(crate) async fn register_node(cancel: CancellationToken) -> anyhow::Result<(
 
     Ok(())
 }
+
+/// node name registered to the server, it is the local ip for now
+pub(crate) fn node_name() -> String {
+    local_ip_address::local_ip()
+        .map(|s| s.to_string())
+        .unwrap_or("127.0.0.1".to_string())
+}
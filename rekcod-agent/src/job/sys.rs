@@ -1,10 +1,17 @@
-use std::sync::Mutex;
+use std::{sync::Mutex, time::Instant};
 
 use once_cell::sync::Lazy;
-use rekcod_core::api::resp::{SystemDiskInfo, SystemInfoResponse, SystemNetworkInfo};
-use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use rekcod_core::api::resp::{
+    SystemDiskInfo, SystemInfoResponse, SystemNetworkInfo, SystemTemperatureInfo,
+};
+use sysinfo::{
+    CpuRefreshKind, MemoryRefreshKind, ProcessRefreshKind, ProcessesToUpdate, RefreshKind, System,
+};
 use tokio_util::sync::CancellationToken;
 
+/// scanning every process is expensive, the count is refreshed every 30 samples
+const PROCESS_REFRESH_SAMPLES: u32 = 30;
+
 static SYS_INFO: Lazy<Mutex<SysInfo>> = Lazy::new(|| Mutex::new(SysInfo::default()));
 static SYS_INFO_GLOBAL: Lazy<GlobalSysInfo> = Lazy::new(|| GlobalSysInfo {
     system_name: System::name(),
@@ -34,8 +41,18 @@ pub(crate) struct SysInfo {
     pub mem_usage: f32,
     pub mem_free: u64,
     pub mem_used: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+    pub swap_used: u64,
+    pub load_one: f64,
+    pub load_five: f64,
+    pub load_fifteen: f64,
+    pub uptime: u64,
+    pub boot_time: u64,
+    pub process_count: u32,
     pub disks: Vec<SysDisk>,
     pub networks: Vec<SysNetwork>,
+    pub temperatures: Vec<SysTemperature>,
 }
 
 pub(crate) struct GlobalSysInfo {
@@ -54,6 +71,11 @@ pub(crate) struct SysDisk {
     pub free: u64,
     pub mount: String,
     pub removable: bool,
+    pub file_system: String,
+    pub read_rate: u64,
+    pub write_rate: u64,
+    pub total_read: u64,
+    pub total_written: u64,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -63,6 +85,16 @@ pub(crate) struct SysNetwork {
     pub mac: String,
     pub total_out: u64,
     pub total_in: u64,
+    pub rate_out: u64,
+    pub rate_in: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SysTemperature {
+    pub label: String,
+    pub temperature: Option<f32>,
+    pub max: Option<f32>,
+    pub critical: Option<f32>,
 }
 
 impl Into<SystemInfoResponse> for SysInfo {
@@ -76,6 +108,15 @@ impl Into<SystemInfoResponse> for SysInfo {
             mem_usage: self.mem_usage,
             mem_free: self.mem_free,
             mem_used: self.mem_used,
+            swap_total: self.swap_total,
+            swap_free: self.swap_free,
+            swap_used: self.swap_used,
+            load_one: self.load_one,
+            load_five: self.load_five,
+            load_fifteen: self.load_fifteen,
+            uptime: self.uptime,
+            boot_time: self.boot_time,
+            process_count: self.process_count,
             cpu_count: self.cpu_count,
             system_name: global.system_name.clone(),
             kernel_version: global.kernel_version.clone(),
@@ -85,6 +126,7 @@ impl Into<SystemInfoResponse> for SysInfo {
             cpu_arch: global.cpu_arch.clone(),
             disks: self.disks.iter().map(|x| x.into()).collect(),
             networks: self.networks.iter().map(|x| x.into()).collect(),
+            temperatures: self.temperatures.iter().map(|x| x.into()).collect(),
         }
     }
 }
@@ -97,6 +139,11 @@ impl Into<SystemDiskInfo> for &SysDisk {
             total: self.total,
             mount: self.mount.clone(),
             removeable: self.removable,
+            file_system: self.file_system.clone(),
+            read_rate: self.read_rate,
+            write_rate: self.write_rate,
+            total_read: self.total_read,
+            total_written: self.total_written,
         }
     }
 }
@@ -109,6 +156,19 @@ impl Into<SystemNetworkInfo> for &SysNetwork {
             mac: self.mac.clone(),
             total_out: self.total_out,
             total_in: self.total_in,
+            rate_out: self.rate_out,
+            rate_in: self.rate_in,
+        }
+    }
+}
+
+impl From<&SysTemperature> for SystemTemperatureInfo {
+    fn from(t: &SysTemperature) -> Self {
+        SystemTemperatureInfo {
+            label: t.label.clone(),
+            temperature: t.temperature,
+            max: t.max,
+            critical: t.critical,
         }
     }
 }
@@ -117,9 +177,14 @@ pub(crate) async fn sys_monitor(cancel: CancellationToken) -> anyhow::Result<()>
     let mut s = sysinfo::System::new();
     let mut disks = sysinfo::Disks::new();
     let mut networks = sysinfo::Networks::new();
+    let mut components = sysinfo::Components::new_with_refreshed_list();
     let rk = RefreshKind::nothing()
         .with_cpu(CpuRefreshKind::everything())
         .with_memory(MemoryRefreshKind::everything());
+    // disk and network counters since the last refresh are turned into rates,
+    // the first sample has no baseline so no rate is reported for it
+    let mut last_sample: Option<Instant> = None;
+    let mut samples: u32 = 0;
     loop {
         tokio::select! {
             _ = cancel.cancelled() => {
@@ -127,6 +192,17 @@ pub(crate) async fn sys_monitor(cancel: CancellationToken) -> anyhow::Result<()>
             }
             _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
                 s.refresh_specifics(rk);
+                if samples.is_multiple_of(PROCESS_REFRESH_SAMPLES) {
+                    s.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+                }
+                samples = samples.wrapping_add(1);
+                disks.refresh(true);
+                networks.refresh(true);
+                components.refresh(true);
+
+                let now = Instant::now();
+                let elapsed = last_sample.map(|last| now.duration_since(last).as_secs_f64());
+                last_sample = Some(now);
 
                 let mut sys_info = SYS_INFO.lock().unwrap();
                 // cpu
@@ -138,22 +214,36 @@ pub(crate) async fn sys_monitor(cancel: CancellationToken) -> anyhow::Result<()>
                 sys_info.mem_usage = s.used_memory() as f32 / s.total_memory() as f32 * 100.0;
                 sys_info.mem_free = s.free_memory();
                 sys_info.mem_used = s.used_memory();
+                sys_info.swap_total = s.total_swap();
+                sys_info.swap_free = s.free_swap();
+                sys_info.swap_used = s.used_swap();
+                // load and process
+                let load = System::load_average();
+                sys_info.load_one = load.one;
+                sys_info.load_five = load.five;
+                sys_info.load_fifteen = load.fifteen;
+                sys_info.uptime = System::uptime();
+                sys_info.boot_time = System::boot_time();
+                sys_info.process_count = s.processes().len() as u32;
 
-                if cfg!(target_os = "linux") {
-                    disks.refresh(true);
-                    sys_info.disks = disks.list().iter().map(|x| {
-                        SysDisk {
-                            name:x.name().to_string_lossy().to_string(),
-                            total: x.total_space(),
-                            free: x.available_space(),
-                            mount: x.mount_point().to_string_lossy().to_string(),
-                            removable: x.is_removable()
-                        }
-                    }).collect::<Vec<_>>();
-                }
-
+                // sysinfo lists disks on every supported platform, the list is
+                // just empty where it can not read them
+                sys_info.disks = disks.list().iter().map(|x| {
+                    let usage = x.usage();
+                    SysDisk {
+                        name: x.name().to_string_lossy().to_string(),
+                        total: x.total_space(),
+                        free: x.available_space(),
+                        mount: x.mount_point().to_string_lossy().to_string(),
+                        removable: x.is_removable(),
+                        file_system: x.file_system().to_string_lossy().to_string(),
+                        read_rate: rate(usage.read_bytes, elapsed),
+                        write_rate: rate(usage.written_bytes, elapsed),
+                        total_read: usage.total_read_bytes,
+                        total_written: usage.total_written_bytes,
+                    }
+                }).collect::<Vec<_>>();
 
-                networks.refresh(true);
                 sys_info.networks = networks.list().iter().map(|(x, d)| {
                     SysNetwork {
                         name: x.to_string(),
@@ -161,6 +251,17 @@ pub(crate) async fn sys_monitor(cancel: CancellationToken) -> anyhow::Result<()>
                         mac: d.mac_address().to_string(),
                         total_out: d.total_transmitted(),
                         total_in: d.total_received(),
+                        rate_out: rate(d.transmitted(), elapsed),
+                        rate_in: rate(d.received(), elapsed),
+                    }
+                }).collect::<Vec<_>>();
+
+                sys_info.temperatures = components.list().iter().map(|x| {
+                    SysTemperature {
+                        label: x.label().to_string(),
+                        temperature: x.temperature(),
+                        max: x.max(),
+                        critical: x.critical(),
                     }
                 }).collect::<Vec<_>>();
             }
@@ -169,3 +270,23 @@ pub(crate) async fn sys_monitor(cancel: CancellationToken) -> anyhow::Result<()>
 
     Ok(())
 }
+
+/// bytes per second of the bytes counted in `elapsed` seconds
+fn rate(bytes: u64, elapsed: Option<f64>) -> u64 {
+    match elapsed {
+        Some(elapsed) if elapsed > 0.0 => (bytes as f64 / elapsed) as u64,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate() {
+        assert_eq!(rate(1000, None), 0);
+        assert_eq!(rate(1000, Some(0.0)), 0);
+        assert_eq!(rate(1000, Some(2.0)), 500);
+    }
+}
@@ -14,17 +14,28 @@ use axum::{
 use pin_project_lite::pin_project;
 use rekcod_core::constants::{DOCKER_PROXY_PATH, REKCOD_AGENT_PREFIX_PATH};
 
-use tokio::io::{copy_bidirectional, AsyncRead, AsyncWrite, ReadBuf};
-use tokio_util::sync::CancellationToken;
+use tokio::io::{copy_bidirectional, AsyncRead, AsyncWrite, AsyncWriteExt as _, ReadBuf};
+use tokio_util::{io::ReaderStream, sync::CancellationToken};
 
 use crate::docker::DockerProxyInterface;
+#[cfg(unix)]
+use docker::unix::SocketFileClient;
+#[cfg(windows)]
+use docker::win::SocketFileClient;
 use docker::DockerProxyClient;
-use hyper::{upgrade::Upgraded, StatusCode};
+use hyper::{
+    header::{self, HeaderValue},
+    upgrade::Upgraded,
+    HeaderMap, StatusCode, Uri, Version,
+};
+use tracing::{debug, warn};
 
 mod agent;
 pub mod config;
 mod docker;
+mod image;
 mod job;
+mod process;
 
 pub fn routers() -> Router {
     let client = DockerProxyClient::new();
@@ -59,47 +70,149 @@ async fn docker_proxy_handler(
         DockerProxyClient::Windows(c) => c,
     };
 
-    // Check for WebSocket upgrade request
-    let is_websocket = req
-        .headers()
-        .get(hyper::header::CONNECTION)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_lowercase().contains("upgrade"))
-        .unwrap_or(false);
-
     let uri = c
         .uri(path_query)
         .map_err(|_| StatusCode::BAD_REQUEST)?
         .clone();
-    if is_websocket {
-        let (mut parts, body) = req.into_parts();
-        let cache_req = Request::from_parts(parts.clone(), Body::empty());
-        parts.uri = uri;
-        let proxy_req = Request::from_parts(parts, body);
-
-        let response = c
-            .request(proxy_req)
-            .await
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
-        if response.status() == StatusCode::SWITCHING_PROTOCOLS {
-            tokio::spawn(async move {
-                let mut out = AsyncUpgraded::new(hyper::upgrade::on(cache_req).await.unwrap());
-                let mut inv = AsyncUpgraded::new(hyper::upgrade::on(response).await.unwrap());
-                let _ = copy_bidirectional(&mut inv, &mut out).await;
-            });
-            Ok(StatusCode::SWITCHING_PROTOCOLS.into_response())
-        } else {
-            Ok(response.into_response())
+    if is_upgrade(req.headers()) {
+        return proxy_upgrade(&c, uri, req).await;
+    }
+    if is_hijack(uri.path()) {
+        return proxy_hijack(&c, uri, req).await;
+    }
+
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+    // the docker socket only speaks http/1.1, h2c clients are proxied to it
+    parts.version = Version::HTTP_11;
+    let proxy_req = Request::from_parts(parts, body);
+    Ok(c.request(proxy_req)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .into_response())
+}
+
+/// websocket, `tcp` for attach and exec and `h2c` for the buildkit `/session` and
+/// `/grpc`, docker only looks at the upgrade header so the connection header is optional
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::UPGRADE)
+}
+
+/// the apis docker hijacks the connection of to stream raw data, with or without an
+/// upgrade request
+fn is_hijack(path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    // the api version prefix is optional, `/v1.45/exec/<id>/start`
+    let path = match path.strip_prefix("/v") {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+            rest.find('/').map(|i| &rest[i..]).unwrap_or_default()
+        }
+        _ => path,
+    };
+    match path.split('/').skip(1).collect::<Vec<_>>().as_slice() {
+        ["containers", id, "attach"] | ["exec", id, "start"] => !id.is_empty(),
+        ["session"] | ["grpc"] => true,
+        _ => false,
+    }
+}
+
+/// forward the upgrade to docker and copy the upgraded streams both ways, docker's
+/// response is returned as it is so the client sees the protocol it switched to
+async fn proxy_upgrade(
+    c: &SocketFileClient,
+    uri: Uri,
+    mut req: Request,
+) -> Result<Response, StatusCode> {
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+    parts.version = Version::HTTP_11;
+    parts
+        .headers
+        .insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    let upgrade = parts
+        .headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let mut response = c
+        .request(Request::from_parts(parts, body))
+        .await
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Ok(response.into_response());
+    }
+
+    let upstream = hyper::upgrade::on(&mut response);
+    let (parts, _) = response.into_parts();
+    tokio::spawn(async move {
+        let (client, docker) = match tokio::try_join!(on_upgrade, upstream) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("docker proxy upgrade to {} error: {}", upgrade, e);
+                return;
+            }
+        };
+        let mut client = AsyncUpgraded::new(client);
+        let mut docker = AsyncUpgraded::new(docker);
+        // the write half is shut down when the other side reaches eof, the stdin eof of
+        // `docker exec` reaches the process while its output still comes back
+        match copy_bidirectional(&mut client, &mut docker).await {
+            Ok((sent, received)) => debug!(
+                "docker proxy {} stream closed, {} bytes sent, {} bytes received",
+                upgrade, sent, received
+            ),
+            Err(e) => debug!("docker proxy {} stream closed: {}", upgrade, e),
         }
-    } else {
-        let (mut parts, body) = req.into_parts();
-        parts.uri = uri;
-        let proxy_req = Request::from_parts(parts, body);
-        Ok(c.request(proxy_req)
-            .await
-            .map_err(|_| StatusCode::BAD_REQUEST)?
-            .into_response())
+    });
+
+    Ok(Response::from_parts(parts, Body::empty()))
+}
+
+/// a hijack api called without an upgrade, like docker does for such a client the
+/// raw stream is the response body and stdin is closed
+async fn proxy_hijack(
+    c: &SocketFileClient,
+    uri: Uri,
+    req: Request,
+) -> Result<Response, StatusCode> {
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+    parts.version = Version::HTTP_11;
+    parts
+        .headers
+        .insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    parts
+        .headers
+        .insert(header::UPGRADE, HeaderValue::from_static("tcp"));
+
+    let mut response = c
+        .request(Request::from_parts(parts, body))
+        .await
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Ok(response.into_response());
+    }
+
+    let upstream = hyper::upgrade::on(&mut response).await.map_err(|e| {
+        warn!("docker proxy hijack error: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let mut docker = AsyncUpgraded::new(upstream);
+    if let Err(e) = docker.shutdown().await {
+        debug!("docker proxy hijack close stdin error: {}", e);
     }
+
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::OK;
+    parts.headers.remove(header::CONNECTION);
+    parts.headers.remove(header::UPGRADE);
+    Ok(Response::from_parts(
+        parts,
+        Body::from_stream(ReaderStream::new(docker)),
+    ))
 }
 
 pub async fn init(cancel: CancellationToken) -> anyhow::Result<()> {
@@ -120,6 +233,7 @@ pub async fn init(cancel: CancellationToken) -> anyhow::Result<()> {
     start_init!(job::sys::sys_monitor);
     start_init!(job::events::docker_event_monitor);
     start_init!(job::container::docker_health_monitor);
+    start_init!(job::gc::gc_monitor);
     Ok(())
 }
 
@@ -176,3 +290,31 @@ impl AsyncWrite for AsyncUpgraded {
         hyper::rt::Write::poll_shutdown(self.project().inner, cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_upgrade() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+        assert!(!is_upgrade(&headers));
+        headers.insert(header::UPGRADE, HeaderValue::from_static("h2c"));
+        assert!(is_upgrade(&headers));
+        headers.remove(header::CONNECTION);
+        assert!(is_upgrade(&headers));
+    }
+
+    #[test]
+    fn test_is_hijack() {
+        assert!(is_hijack("/containers/abc/attach"));
+        assert!(is_hijack("/v1.45/exec/abc/start"));
+        assert!(is_hijack("/v1.45/session"));
+        assert!(is_hijack("/grpc"));
+        assert!(!is_hijack("/v1.45/exec/abc/resize"));
+        assert!(!is_hijack("/containers/abc/attach/ws"));
+        assert!(!is_hijack("/containers//attach"));
+        assert!(!is_hijack("/version"));
+    }
+}
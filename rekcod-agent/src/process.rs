@@ -0,0 +1,305 @@
+use std::path::Path;
+
+use rekcod_core::{
+    api::{
+        req::{ProcessListRequest, ProcessSignalRequest},
+        resp::ProcessItemResponse,
+    },
+    docker::local_connect,
+};
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System, UpdateKind, Users};
+use tracing::{error, info};
+
+use crate::config;
+
+/// process policy file in the agent config path, signals are denied if not exists
+const PROCESS_POLICY_FILE_NAME: &str = "process.json";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub(crate) struct ProcessPolicy {
+    /// allow signalling host processes through the api
+    pub allow_signal: bool,
+    /// signals allowed to send, without the `SIG` prefix
+    pub signals: Vec<String>,
+}
+
+impl Default for ProcessPolicy {
+    fn default() -> Self {
+        Self {
+            allow_signal: false,
+            signals: ["TERM", "INT", "HUP", "KILL"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+async fn load_process_policy() -> ProcessPolicy {
+    let config = config::rekcod_agent_config();
+    let path = Path::new(&config.config_path).join(PROCESS_POLICY_FILE_NAME);
+    let content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(_) => return ProcessPolicy::default(),
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("load process policy {:?} error: {}", path, e);
+            ProcessPolicy::default()
+        }
+    }
+}
+
+pub(crate) async fn list_process(
+    req: ProcessListRequest,
+) -> anyhow::Result<Vec<ProcessItemResponse>> {
+    let mut processes = tokio::task::spawn_blocking(collect_process).await?;
+
+    let keyword = req.keyword.as_deref().filter(|k| !k.is_empty());
+    let container_id = req.container_id.as_deref().filter(|c| !c.is_empty());
+    processes.retain(|p| {
+        keyword.is_none_or(|k| p.name.contains(k) || p.cmd.contains(k))
+            && req.user.as_deref().is_none_or(|u| p.user == u)
+            && container_id.is_none_or(|c| p.container_id.starts_with(c))
+            && (!req.container_only || !p.container_id.is_empty())
+    });
+
+    sort_process(
+        &mut processes,
+        req.sort.as_deref().unwrap_or("cpu"),
+        req.asc,
+    )?;
+    if let Some(limit) = req.limit {
+        processes.truncate(limit);
+    }
+
+    Ok(processes)
+}
+
+fn collect_process() -> Vec<ProcessItemResponse> {
+    let refresh_kind = ProcessRefreshKind::nothing()
+        .with_cpu()
+        .with_memory()
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+        .with_user(UpdateKind::OnlyIfNotSet);
+
+    // cpu usage is computed between two refreshes
+    let mut s = System::new();
+    s.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh_kind);
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    s.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh_kind);
+
+    let users = Users::new_with_refreshed_list();
+    s.processes()
+        .iter()
+        .map(|(pid, p)| ProcessItemResponse {
+            pid: pid.as_u32(),
+            ppid: p.parent().map(|x| x.as_u32()).unwrap_or(0),
+            user: p
+                .user_id()
+                .and_then(|uid| users.get_user_by_id(uid))
+                .map(|u| u.name().to_string())
+                .unwrap_or_default(),
+            cpu_usage: p.cpu_usage(),
+            mem: p.memory(),
+            container_id: process_container_id(pid.as_u32()).unwrap_or_default(),
+            name: p.name().to_string_lossy().to_string(),
+            cmd: p
+                .cmd()
+                .iter()
+                .map(|x| x.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+        })
+        .collect()
+}
+
+fn sort_process(
+    processes: &mut [ProcessItemResponse],
+    sort: &str,
+    asc: bool,
+) -> anyhow::Result<()> {
+    match sort {
+        "cpu" => processes.sort_by(|a, b| a.cpu_usage.total_cmp(&b.cpu_usage)),
+        "mem" => processes.sort_by_key(|p| p.mem),
+        "pid" => processes.sort_by_key(|p| p.pid),
+        "name" => processes.sort_by(|a, b| a.name.cmp(&b.name)),
+        _ => return Err(anyhow::anyhow!("unsupported sort: {}", sort)),
+    }
+    if !asc {
+        processes.reverse();
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn process_container_id(pid: u32) -> Option<String> {
+    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    container_id_from_cgroup(&cgroup)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_container_id(_pid: u32) -> Option<String> {
+    None
+}
+
+/// find the container id in a `/proc/<pid>/cgroup` file, e.g.
+/// `0::/system.slice/docker-<id>.scope` or `12:memory:/docker/<id>`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn container_id_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup.lines().find_map(|line| {
+        let path = line.splitn(3, ':').nth(2)?;
+        path.split('/').rev().find_map(|seg| {
+            let seg = seg.strip_suffix(".scope").unwrap_or(seg);
+            let id = seg.rsplit('-').next()?;
+            (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_string())
+        })
+    })
+}
+
+/// check the request against the process policy, return the signal to send
+pub(crate) async fn check_signal(req: &ProcessSignalRequest) -> anyhow::Result<Signal> {
+    let policy = load_process_policy().await;
+    if !policy.allow_signal {
+        return Err(anyhow::anyhow!(
+            "signal process is not allowed on this node"
+        ));
+    }
+
+    let name = signal_name(&req.signal);
+    if !policy.signals.iter().any(|s| signal_name(s) == name) {
+        return Err(anyhow::anyhow!("signal {} is not allowed", req.signal));
+    }
+    if req.pid <= 1 || req.pid == std::process::id() {
+        return Err(anyhow::anyhow!("can not signal process {}", req.pid));
+    }
+    parse_signal(&name)
+}
+
+/// kill the process of a running exec, docker reports it so it is no arbitrary host process
+pub(crate) async fn kill_exec(exec_id: &str) -> anyhow::Result<()> {
+    let exec = local_connect().inspect_exec(exec_id).await?;
+    if exec.running != Some(true) {
+        return Ok(());
+    }
+    let pid = exec
+        .pid
+        .filter(|pid| *pid > 0)
+        .ok_or_else(|| anyhow::anyhow!("exec {} has no process", exec_id))?;
+    signal_process(pid as u32, Signal::Kill)
+}
+
+pub(crate) fn signal_process(pid: u32, signal: Signal) -> anyhow::Result<()> {
+    let sys_pid = Pid::from_u32(pid);
+    let mut s = System::new();
+    s.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[sys_pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    let process = s
+        .process(sys_pid)
+        .ok_or(anyhow::anyhow!("process {} not found", pid))?;
+
+    match process.kill_with(signal) {
+        Some(true) => {
+            info!("sent signal {} to process {}", signal, pid);
+            Ok(())
+        }
+        Some(false) => Err(anyhow::anyhow!(
+            "send signal {} to process {} failed",
+            signal,
+            pid
+        )),
+        None => Err(anyhow::anyhow!(
+            "signal {} is not supported on this platform",
+            signal
+        )),
+    }
+}
+
+fn signal_name(signal: &str) -> String {
+    let signal = signal.trim().to_uppercase();
+    match signal.strip_prefix("SIG") {
+        Some(name) => name.to_string(),
+        None => signal,
+    }
+}
+
+fn parse_signal(name: &str) -> anyhow::Result<Signal> {
+    let signal = match name {
+        "HUP" => Signal::Hangup,
+        "INT" => Signal::Interrupt,
+        "QUIT" => Signal::Quit,
+        "KILL" => Signal::Kill,
+        "USR1" => Signal::User1,
+        "USR2" => Signal::User2,
+        "TERM" => Signal::Term,
+        "CONT" => Signal::Continue,
+        "STOP" => Signal::Stop,
+        _ => return Err(anyhow::anyhow!("unsupported signal: {}", name)),
+    };
+    Ok(signal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f2a1b7c9d0e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a";
+
+    #[test]
+    fn test_container_id_from_cgroup() {
+        let v1 = format!("12:memory:/docker/{}\n11:cpu:/docker/{}", ID, ID);
+        assert_eq!(container_id_from_cgroup(&v1).as_deref(), Some(ID));
+
+        let v2 = format!("0::/system.slice/docker-{}.scope", ID);
+        assert_eq!(container_id_from_cgroup(&v2).as_deref(), Some(ID));
+
+        let cri = format!(
+            "0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-{}.scope",
+            ID
+        );
+        assert_eq!(container_id_from_cgroup(&cri).as_deref(), Some(ID));
+
+        assert!(container_id_from_cgroup("0::/user.slice/user-1000.slice").is_none());
+        assert!(container_id_from_cgroup("0::/").is_none());
+    }
+
+    #[test]
+    fn test_signal_name() {
+        assert_eq!(signal_name("SIGTERM"), "TERM");
+        assert_eq!(signal_name(" kill "), "KILL");
+        assert!(parse_signal(&signal_name("sighup")).is_ok());
+        assert!(parse_signal(&signal_name("SEGV")).is_err());
+    }
+
+    #[test]
+    fn test_sort_process() {
+        let mut processes = vec![
+            ProcessItemResponse {
+                pid: 2,
+                cpu_usage: 1.0,
+                mem: 30,
+                ..Default::default()
+            },
+            ProcessItemResponse {
+                pid: 1,
+                cpu_usage: 5.0,
+                mem: 10,
+                ..Default::default()
+            },
+        ];
+        sort_process(&mut processes, "cpu", false).unwrap();
+        assert_eq!(processes[0].pid, 1);
+        sort_process(&mut processes, "mem", false).unwrap();
+        assert_eq!(processes[0].pid, 2);
+        sort_process(&mut processes, "pid", true).unwrap();
+        assert_eq!(processes[0].pid, 1);
+        assert!(sort_process(&mut processes, "disk", true).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Default, Debug)]
@@ -79,6 +81,11 @@ pub struct AppDeployRequest {
 pub struct DockerImagePullAutoRequest {
     pub node_name: String,
     pub image_name: String,
+    /// how an image is copied from a peer node, `layer` sends only the layers the node
+    /// does not have and is the default, `full` sends the whole image
+    pub transfer: Option<String>,
+    /// bytes per second of a layer transfer, unlimited if not set
+    pub bandwidth_limit: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -90,3 +97,724 @@ pub struct EnvRequest {
 pub struct AppDeployDeleteRequest {
     pub app_name: String,
 }
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRestartAction {
+    /// container was restarted by the agent
+    #[default]
+    Restarted,
+    /// restart was tried but docker returned an error
+    Failed,
+    /// `rekcod.restart.max` was reached, agent will not restart it anymore
+    GaveUp,
+    /// container was restarted too often in a short time
+    CrashLoop,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerRestartEventRequest {
+    pub node_name: String,
+    pub container_id: String,
+    pub container_name: String,
+    /// restart policy from `rekcod.restart` label
+    pub policy: String,
+    pub action: ContainerRestartAction,
+    /// why the container was picked up, `exited` or `unhealthy`
+    pub reason: String,
+    pub exit_code: Option<i64>,
+    /// restart times since the container was last stable
+    pub restart_count: u32,
+    /// unix timestamp in seconds
+    pub time: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ContainerRestartListRequest {
+    pub node_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GcPolicy {
+    /// run gc automatically when disk usage is above the high watermark
+    pub enabled: bool,
+    /// disk usage percent of docker root dir to start gc
+    pub high_watermark: f32,
+    /// gc stops once disk usage percent is below it
+    pub low_watermark: f32,
+    /// images, containers, volumes and build cache younger than it are kept, in seconds
+    pub min_age: u64,
+    /// image references never removed, a trailing `*` matches any suffix
+    /// example:
+    ///    - redis:7
+    ///    - registry.local/*
+    pub keep_images: Vec<String>,
+    /// remove dangling images
+    pub prune_images: bool,
+    /// remove tagged images not used by any container
+    pub prune_unused_images: bool,
+    /// remove exited or created containers
+    pub prune_containers: bool,
+    pub prune_build_cache: bool,
+    /// remove volumes not used by any container, default: false
+    pub prune_volumes: bool,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            high_watermark: 85.0,
+            low_watermark: 70.0,
+            min_age: 24 * 60 * 60,
+            keep_images: vec![],
+            prune_images: true,
+            prune_unused_images: false,
+            prune_containers: true,
+            prune_build_cache: true,
+            prune_volumes: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct NodeGcRequest {
+    pub node_name: String,
+    /// only list what would be removed
+    pub dry_run: bool,
+    /// use the agent policy if not set
+    pub policy: Option<GcPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct NodeGcReportListRequest {
+    pub node_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ProcessListRequest {
+    pub node_name: String,
+    /// sort by `cpu`, `mem`, `pid` or `name`, default is `cpu`
+    pub sort: Option<String>,
+    /// ascending order, descending by default
+    pub asc: bool,
+    /// match process name or command line
+    pub keyword: Option<String>,
+    pub user: Option<String>,
+    /// only processes of the container, an id prefix is allowed
+    pub container_id: Option<String>,
+    /// only processes running in containers
+    pub container_only: bool,
+    pub limit: Option<usize>,
+}
+
+/// kill a running exec, its process is owned by docker so no process policy applies
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ExecKillRequest {
+    pub exec_id: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ProcessSignalRequest {
+    pub node_name: String,
+    pub pid: u32,
+    /// signal name, e.g. `TERM`, `SIGKILL`
+    pub signal: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ClusterOverviewRequest {
+    /// timeout of every node in seconds, default is 5
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DockerContainerCreateRequest {
+    pub node_name: String,
+    pub image: String,
+    pub name: Option<String>,
+    pub cmd: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub env: HashMap<String, String>,
+    pub ports: Vec<ContainerPortSpec>,
+    pub mounts: Vec<ContainerMountSpec>,
+    /// the first network is used at create, the others are connected after it
+    pub networks: Vec<String>,
+    /// `no`, `always`, `unless-stopped`, `on-failure` or `on-failure:<max retry>`
+    pub restart_policy: Option<String>,
+    /// cpu limit in cores, e.g. 1.5
+    pub cpus: Option<f64>,
+    /// memory limit in bytes
+    pub memory: Option<i64>,
+    pub labels: HashMap<String, String>,
+    /// `missing`, `always` or `never`, default is `missing`
+    pub pull: String,
+    /// start the container after it is created
+    pub start: bool,
+}
+
+impl Default for DockerContainerCreateRequest {
+    fn default() -> Self {
+        Self {
+            node_name: "".to_string(),
+            image: "".to_string(),
+            name: None,
+            cmd: None,
+            entrypoint: None,
+            env: HashMap::new(),
+            ports: vec![],
+            mounts: vec![],
+            networks: vec![],
+            restart_policy: None,
+            cpus: None,
+            memory: None,
+            labels: HashMap::new(),
+            pull: "missing".to_string(),
+            start: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerPortSpec {
+    /// host ip to bind, all interfaces if not set
+    pub host_ip: Option<String>,
+    /// host port, a random port if not set
+    pub host_port: Option<u16>,
+    pub container_port: u16,
+    /// `tcp`, `udp` or `sctp`
+    pub protocol: String,
+}
+
+impl Default for ContainerPortSpec {
+    fn default() -> Self {
+        Self {
+            host_ip: None,
+            host_port: None,
+            container_port: 0,
+            protocol: "tcp".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerMountSpec {
+    /// `bind`, `volume` or `tmpfs`
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// host path for bind, volume name for volume, empty for tmpfs
+    pub source: String,
+    /// path in the container
+    pub target: String,
+    pub read_only: bool,
+}
+
+impl Default for ContainerMountSpec {
+    fn default() -> Self {
+        Self {
+            typ: "volume".to_string(),
+            source: "".to_string(),
+            target: "".to_string(),
+            read_only: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImageQueryRequest {
+    pub node_name: String,
+    /// image name or id
+    pub image: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImageRemoveRequest {
+    pub node_name: String,
+    /// image names or ids
+    pub images: Vec<String>,
+    pub force: bool,
+    /// do not delete untagged parent images
+    pub noprune: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImageTagRequest {
+    pub node_name: String,
+    /// source image name or id
+    pub image: String,
+    /// target repository, e.g. `registry.local/app`
+    pub repo: String,
+    /// target tag, default is `latest`
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImagePushRequest {
+    pub node_name: String,
+    /// image to push, e.g. `registry.local/app:v1`
+    pub image: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImagePruneRequest {
+    pub node_name: String,
+    /// prune all unused images, only dangling images by default
+    pub all: bool,
+    /// only images created before it, e.g. `24h` or a timestamp
+    pub until: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerNetworkCreateRequest {
+    pub node_name: String,
+    pub name: String,
+    /// default is `bridge`
+    pub driver: Option<String>,
+    /// e.g. `172.28.0.0/16`
+    pub subnet: Option<String>,
+    pub gateway: Option<String>,
+    pub internal: bool,
+    pub attachable: bool,
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerNetworkQueryRequest {
+    pub node_name: String,
+    /// network name or id
+    pub network: String,
+    /// remove: disconnect the containers using the network first
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerNetworkContainerRequest {
+    pub node_name: String,
+    /// network name or id
+    pub network: String,
+    /// container name or id
+    pub container: String,
+    /// connect: static ipv4 address in the network
+    pub ipv4_address: Option<String>,
+    /// connect: network scoped aliases
+    pub aliases: Vec<String>,
+    /// disconnect: force the container to disconnect
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerVolumeCreateRequest {
+    pub node_name: String,
+    /// a random name is generated if empty
+    pub name: String,
+    /// default is `local`
+    pub driver: Option<String>,
+    pub driver_opts: HashMap<String, String>,
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerVolumeQueryRequest {
+    pub node_name: String,
+    pub volume: String,
+    /// remove: remove the volume even if it is in use
+    pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerPruneRequest {
+    pub node_name: String,
+    /// only resources created before it, e.g. `24h` or a timestamp
+    pub until: Option<String>,
+    /// volumes: prune named volumes too, only anonymous volumes by default
+    pub all: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VolumeBackupRequest {
+    pub node_name: String,
+    pub volume: String,
+    /// stream the archive to the caller instead of keeping it on the server
+    pub download: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VolumeRestoreRequest {
+    pub node_name: String,
+    /// target volume, created if it does not exist
+    pub volume: String,
+    /// backup kept on the server, the request body is the archive when restoring an upload
+    pub backup_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VolumeBackupListRequest {
+    pub node_name: Option<String>,
+    pub volume: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VolumeBackupDeleteRequest {
+    pub backup_id: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VolumeBackupScheduleRequest {
+    pub node_name: String,
+    pub volume: String,
+    /// seconds between two backups, 0 removes the schedule
+    pub interval: u64,
+    /// scheduled backups to keep, 0 keeps all of them
+    pub keep: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerArchiveRequest {
+    pub node_name: String,
+    /// container name or id
+    pub container: String,
+    /// path in the container, upload extracts into this directory
+    pub path: String,
+    /// upload: the body is this single file instead of a tar
+    pub file_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerLogsRequest {
+    pub node_name: String,
+    /// unix timestamp, rfc3339 time or a duration before now such as `30m`
+    pub since: Option<String>,
+    /// same formats as `since`
+    pub until: Option<String>,
+    /// lines from the end or `all`, default is 100 when following, otherwise all
+    pub tail: Option<String>,
+    /// prefix the plain text lines with their time, ndjson lines always carry it
+    pub timestamps: bool,
+    /// default is true
+    pub stdout: Option<bool>,
+    /// default is true
+    pub stderr: Option<bool>,
+    /// default is true, always off for downloads
+    pub follow: Option<bool>,
+    /// `raw` (default), `ndjson` or `download`
+    pub format: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerExecRequest {
+    pub node_name: String,
+    /// container name or id
+    pub container: String,
+    pub cmd: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub user: Option<String>,
+    pub workdir: Option<String>,
+    /// seconds, the command is killed after it if the agent allows signals
+    pub timeout: Option<u64>,
+    /// stream ndjson events instead of returning the buffered output
+    pub stream: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ClusterSearchRequest {
+    /// part of the container name, image tag or volume name, case insensitive
+    pub name: Option<String>,
+    /// part of the image of containers, or of the tags of images
+    pub image: Option<String>,
+    /// `key` or `key=value`
+    pub label: Option<String>,
+    /// container state, e.g. `running`; images and volumes ignore it
+    pub status: Option<String>,
+    /// compose project label
+    pub project: Option<String>,
+    /// node, name, created and per kind image, status, size or driver; default is node then name
+    pub sort: Option<String>,
+    pub desc: bool,
+    /// starts at 1
+    pub page: Option<usize>,
+    /// default is 50
+    pub page_size: Option<usize>,
+    /// timeout of every node in seconds, default is 5
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerRenameRequest {
+    pub node_name: String,
+    pub name: String,
+}
+
+/// only the given fields are changed
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerUpdateRequest {
+    /// cpu limit in cores, e.g. 1.5
+    pub cpus: Option<f64>,
+    /// memory limit in bytes
+    pub memory: Option<i64>,
+    /// memory plus swap limit in bytes, -1 is unlimited swap
+    pub memory_swap: Option<i64>,
+    /// `no`, `always`, `unless-stopped`, `on-failure` or `on-failure:<max retry>`
+    pub restart_policy: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerKillRequest {
+    pub node_name: String,
+    /// e.g. `SIGTERM`, `HUP` or `9`, default is `SIGKILL`
+    pub signal: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerTopRequest {
+    pub node_name: String,
+    /// arguments of `ps`, default is `-ef`
+    pub ps_args: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct SystemDfRequest {
+    /// every online node matching the selector if empty
+    pub node_names: Vec<String>,
+    /// comma separated `key=value` like the image distribute selector
+    pub selector: Option<String>,
+    /// timeout of every node in seconds, default is 60
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SystemPruneRequest {
+    /// every online node matching the selector if empty
+    pub node_names: Vec<String>,
+    /// comma separated `key=value` like the image distribute selector
+    pub selector: Option<String>,
+    /// only list what would be removed
+    pub dry_run: bool,
+    /// remove stopped containers
+    pub containers: bool,
+    /// remove dangling images
+    pub images: bool,
+    /// remove tagged images not used by any container too
+    pub all_images: bool,
+    /// remove volumes not used by any container, named ones too, default: false
+    pub volumes: bool,
+    /// remove build cache not in use
+    pub build_cache: bool,
+    /// timeout of every node in seconds, default is 600
+    pub timeout: Option<u64>,
+}
+
+impl Default for SystemPruneRequest {
+    fn default() -> Self {
+        Self {
+            node_names: vec![],
+            selector: None,
+            dry_run: false,
+            containers: true,
+            images: true,
+            all_images: false,
+            volumes: false,
+            build_cache: true,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerContainerMigrateRequest {
+    /// the source node
+    pub node_name: String,
+    pub container: String,
+    pub target_node: String,
+    /// move the container filesystem as a committed image instead of its image
+    pub commit: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ContainerMigrationListRequest {
+    /// the source node
+    pub node_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ContainerMigrationConfirmRequest {
+    pub id: String,
+}
+
+/// the build context is the tar request body
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DockerImageBuildRequest {
+    pub node_name: String,
+    /// path of the Dockerfile in the context, default is `Dockerfile`
+    pub dockerfile: Option<String>,
+    /// comma separated, the image is built with the first tag and tagged with the others
+    pub tags: String,
+    /// a json object, like `buildargs` of the docker api
+    pub build_args: Option<String>,
+    pub target: Option<String>,
+    pub nocache: bool,
+    /// always pull the base images
+    pub pull: bool,
+    /// comma separated nodes the image is copied to after it is built
+    pub distribute: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ImageBuildListRequest {
+    pub node_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ImageBuildLogRequest {
+    pub id: String,
+}
+
+/// a login for a registry, with either a password or a token
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCredentialRequest {
+    /// registry host, like `docker.io` or `registry.example.com:5000`
+    pub registry: String,
+    pub username: String,
+    pub password: Option<String>,
+    /// access token sent as the password, like a github or docker hub personal access token
+    pub token: Option<String>,
+    /// oauth refresh token docker sends as `identitytoken`
+    pub identity_token: Option<String>,
+    /// test the login on a node before it is saved
+    pub verify: bool,
+    /// node the login is tested on, the first online node if not set
+    pub node_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCredentialDeleteRequest {
+    pub registry: String,
+}
+
+/// test the saved login of the registry, or the given one if a username is set
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCredentialTestRequest {
+    pub registry: String,
+    pub node_name: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+    pub identity_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ImageLayersRequest {
+    pub image: String,
+}
+
+/// sent to the agent of the target node, it fetches the files from the agent of the
+/// source node and loads the image
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ImageFetchRequest {
+    /// image id
+    pub id: String,
+    /// agent api of the source node
+    pub source: String,
+    /// dir of the saved image on the source node
+    pub root: String,
+    pub files: Vec<crate::api::resp::ImageTransferFile>,
+    /// bytes per second, unlimited if not set
+    pub bandwidth_limit: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryCacheConfigRequest {
+    /// serve the registry api under `/v2`
+    pub enabled: bool,
+    /// bytes, the least recently pulled blobs are evicted above it, unlimited if 0
+    pub max_size: u64,
+    /// upstream registries the cache pulls through, only `docker.io` if empty
+    pub registries: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCacheListRequest {
+    /// like `docker.io/library/nginx`
+    pub repository: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCacheDeleteRequest {
+    pub repository: String,
+    /// every reference of the repository if not set
+    pub reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RegistryCacheSeedRequest {
+    pub image: String,
+}
+
+/// make sure the image exists on every selected node
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ImageDistributeRequest {
+    pub image: String,
+    pub node_names: Vec<String>,
+    /// comma separated `key=value` of the node name, host_name, ip, arch or os, a value
+    /// ending with `*` is a prefix, used if no node is named
+    pub selector: Option<String>,
+    /// nodes copied to at the same time, 3 if not set
+    pub concurrency: Option<usize>,
+    /// `layer` or `full`, like the image pull
+    pub transfer: Option<String>,
+    /// bytes per second of each layer transfer
+    pub bandwidth_limit: Option<u64>,
+}
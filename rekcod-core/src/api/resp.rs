@@ -1,3 +1,4 @@
+use bollard::secret::{Network, Volume};
 use serde::{Deserialize, Serialize};
 use tabled::Tabled;
 
@@ -69,7 +70,8 @@ where
     }
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
 pub struct SystemInfoResponse {
     /// cpu usage in percent
     pub cpu_usage: f32,
@@ -85,9 +87,29 @@ pub struct SystemInfoResponse {
     pub mem_free: u64,
     /// memory used bytes
     pub mem_used: u64,
+    /// total swap bytes
+    pub swap_total: u64,
+    /// swap free bytes
+    pub swap_free: u64,
+    /// swap used bytes
+    pub swap_used: u64,
+
+    /// load average within one minute, always 0 on windows
+    pub load_one: f64,
+    /// load average within five minutes
+    pub load_five: f64,
+    /// load average within fifteen minutes
+    pub load_fifteen: f64,
+    /// system uptime in seconds
+    pub uptime: u64,
+    /// system boot time in seconds since unix epoch
+    pub boot_time: u64,
+    /// number of processes on the host, refreshed every 30 seconds
+    pub process_count: u32,
 
     pub disks: Vec<SystemDiskInfo>,
     pub networks: Vec<SystemNetworkInfo>,
+    pub temperatures: Vec<SystemTemperatureInfo>,
 
     pub system_name: Option<String>,
     pub kernel_version: Option<String>,
@@ -97,7 +119,8 @@ pub struct SystemInfoResponse {
     pub cpu_arch: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
 pub struct SystemDiskInfo {
     pub name: String,
     /// disk free bytes
@@ -106,15 +129,41 @@ pub struct SystemDiskInfo {
     pub total: u64,
     pub mount: String,
     pub removeable: bool,
+    pub file_system: String,
+    /// read bytes per second
+    pub read_rate: u64,
+    /// written bytes per second
+    pub write_rate: u64,
+    /// total read bytes
+    pub total_read: u64,
+    /// total written bytes
+    pub total_written: u64,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
 pub struct SystemNetworkInfo {
     pub name: String,
     pub ips: Vec<String>,
     pub mac: String,
     pub total_out: u64,
     pub total_in: u64,
+    /// transmitted bytes per second
+    pub rate_out: u64,
+    /// received bytes per second
+    pub rate_in: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct SystemTemperatureInfo {
+    pub label: String,
+    /// temperature in celsius
+    pub temperature: Option<f32>,
+    /// highest temperature seen in celsius
+    pub max: Option<f32>,
+    /// critical temperature in celsius
+    pub critical: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Default, Tabled, Debug, Clone)]
@@ -130,6 +179,10 @@ pub struct NodeItemResponse {
     pub os_version: String,
     pub os_kernel: String,
     pub status: bool,
+    /// latest host telemetry, only filled by node info
+    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sys: Option<SystemInfoResponse>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -152,3 +205,480 @@ pub struct RenderTmplResponse {
 pub struct EnvResponse {
     pub values: String,
 }
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GcReportResponse {
+    pub node_name: String,
+    pub dry_run: bool,
+    /// `watermark` or `manual`
+    pub trigger: String,
+    /// disk usage percent of docker root dir before gc
+    pub disk_usage: f32,
+    pub items: Vec<GcItem>,
+    /// reclaimed bytes, estimated if dry run
+    pub reclaimed: u64,
+    /// unix timestamp in seconds
+    pub time: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GcItem {
+    /// `container`, `image`, `volume` or `build_cache`
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Tabled, Debug, Clone)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct ProcessItemResponse {
+    pub pid: u32,
+    pub ppid: u32,
+    pub user: String,
+    /// cpu usage in percent, may exceed 100 on multi core hosts
+    #[tabled(rename = "CPU%", display_with = "display_percent")]
+    pub cpu_usage: f32,
+    /// resident memory bytes
+    #[tabled(display_with = "display_bytes")]
+    pub mem: u64,
+    /// id of the container the process runs in, empty if not in a container
+    #[tabled(rename = "CONTAINER", display_with = "display_short_id")]
+    pub container_id: String,
+    pub name: String,
+    #[tabled(rename = "COMMAND")]
+    pub cmd: String,
+}
+
+/// disk usage of a kind of resource on a node, like a row of `docker system df`
+#[derive(Serialize, Deserialize, Default, Tabled, Debug, Clone)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct DiskUsageItem {
+    #[tabled(rename = "NODE")]
+    pub node_name: String,
+    /// `images`, `containers`, `volumes` or `build_cache`
+    #[tabled(rename = "TYPE")]
+    pub kind: String,
+    pub total: u64,
+    /// used by a container, or running for containers
+    pub active: u64,
+    #[tabled(display_with = "display_bytes")]
+    pub size: u64,
+    /// freed by a prune of everything not active
+    #[tabled(display_with = "display_bytes")]
+    pub reclaimable: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterDiskUsageResponse {
+    pub items: Vec<DiskUsageItem>,
+    pub errors: Vec<ClusterNodeError>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct NodePruneReport {
+    pub node_name: String,
+    pub dry_run: bool,
+    /// removed, or would be removed if dry run
+    pub items: Vec<GcItem>,
+    /// reclaimed bytes, estimated if dry run
+    pub reclaimed: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterPruneResponse {
+    pub nodes: Vec<NodePruneReport>,
+    pub errors: Vec<ClusterNodeError>,
+}
+
+fn display_percent(v: &f32) -> String {
+    format!("{:.1}", v)
+}
+
+pub fn display_bytes(v: &u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = *v as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", v, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+fn display_short_id(v: &str) -> String {
+    v.chars().take(12).collect()
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterOverviewResponse {
+    pub nodes: ClusterNodeCount,
+    /// total cpu cores of reachable nodes
+    pub cpu_count: u32,
+    /// cpu cores in use of reachable nodes
+    pub cpu_used: f32,
+    /// memory total bytes
+    pub mem_total: u64,
+    /// memory used bytes
+    pub mem_used: u64,
+    /// disk total bytes
+    pub disk_total: u64,
+    /// disk used bytes
+    pub disk_used: u64,
+    pub containers: ClusterContainerCount,
+    pub image_count: u64,
+    /// image disk usage bytes
+    pub image_size: u64,
+    pub apps: ClusterAppCount,
+    /// nodes failed or timed out, their numbers are not counted
+    pub errors: Vec<ClusterNodeError>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterNodeCount {
+    pub total: u32,
+    pub online: u32,
+    pub offline: u32,
+    /// online but failed or timed out
+    pub unreachable: u32,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterContainerCount {
+    pub total: u64,
+    pub running: u64,
+    pub paused: u64,
+    pub restarting: u64,
+    pub exited: u64,
+    pub created: u64,
+    pub dead: u64,
+    /// running containers with a failing health check
+    pub unhealthy: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterAppCount {
+    pub total: u32,
+    /// app container is running and not unhealthy
+    pub healthy: u32,
+    pub unhealthy: u32,
+    /// app container exists but is not running
+    pub stopped: u32,
+    /// app container not found on its node
+    pub missing: u32,
+    /// node of the app is offline or unreachable
+    pub unknown: u32,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterNodeError {
+    pub node_name: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageRemoveResult {
+    pub image: String,
+    pub untagged: Vec<String>,
+    pub deleted: Vec<String>,
+    /// error of this image, other images are still removed
+    pub error: Option<String>,
+}
+
+/// a container using a network or volume
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ResourceContainer {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct NetworkDetailResponse {
+    pub network: Network,
+    pub containers: Vec<ResourceContainer>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct VolumeDetailResponse {
+    pub volume: Volume,
+    pub containers: Vec<ResourceContainer>,
+}
+
+/// a volume archive (tar.gz) kept in the server data directory
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct VolumeBackupResponse {
+    pub id: String,
+    pub node_name: String,
+    pub volume: String,
+    pub size: u64,
+    /// sha256 of the archive, checked before restoring it
+    pub sha256: String,
+    pub created_at: u64,
+    pub scheduled: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct VolumeBackupScheduleResponse {
+    pub node_name: String,
+    pub volume: String,
+    pub interval: u64,
+    pub keep: usize,
+    pub last_run: u64,
+    pub last_error: Option<String>,
+}
+
+/// stat of a path in a container, as reported by the docker archive api
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerPathStat {
+    pub name: String,
+    pub size: i64,
+    /// go `os.FileMode` bits
+    pub mode: u32,
+    pub mtime: String,
+    #[serde(alias = "linkTarget")]
+    pub link_target: String,
+}
+
+impl ContainerPathStat {
+    pub fn is_dir(&self) -> bool {
+        self.mode & (1 << 31) != 0
+    }
+}
+
+/// a line of the ndjson container log
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContainerLogLine {
+    /// stdout, stderr, stdin or console
+    pub stream: String,
+    /// rfc3339 time docker received the line
+    pub timestamp: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerExecResponse {
+    /// none if the command timed out or its state is unknown
+    pub exit_code: Option<i64>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+    /// the buffered output is cut at a size limit, stream it to get all of it
+    pub truncated: bool,
+    /// the timed out command could not be killed
+    pub error: Option<String>,
+}
+
+/// a line of the streamed exec output, the last one carries the result
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerExecEvent {
+    /// stdout or stderr, empty on the last event
+    pub stream: String,
+    pub data: String,
+    pub exit_code: Option<i64>,
+    pub timed_out: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ClusterSearchResponse<T> {
+    pub items: Vec<ClusterSearchItem<T>>,
+    /// matched items of all pages
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    /// nodes offline, failed or timed out, their items are missing
+    pub errors: Vec<ClusterNodeError>,
+}
+
+/// a container, image or volume tagged with its node
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ClusterSearchItem<T> {
+    pub node_name: String,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ContainerMigrationResponse {
+    pub id: String,
+    /// container name, the same on both nodes
+    pub name: String,
+    pub source_node: String,
+    pub source_container: String,
+    pub target_node: String,
+    pub target_container: String,
+    pub image: String,
+    /// the image was committed from the source container
+    pub committed: bool,
+    pub volumes: Vec<String>,
+    /// `migrated` until the source container is removed, then `confirmed`
+    pub status: String,
+    /// unix timestamp in seconds
+    pub created_at: u64,
+}
+
+/// a line of the ndjson build output, also kept as the build log
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageBuildEvent {
+    /// `build`, `distribute` or `done`
+    pub step: String,
+    pub node_name: String,
+    pub message: String,
+    pub error: Option<String>,
+    /// set once the image is built
+    pub image_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageBuildResponse {
+    pub id: String,
+    pub node_name: String,
+    pub tags: Vec<String>,
+    pub dockerfile: String,
+    pub target: Option<String>,
+    /// `running`, `success` or `failed`
+    pub status: String,
+    pub image_id: Option<String>,
+    pub error: Option<String>,
+    /// nodes the image was copied to
+    pub distributed: Vec<String>,
+    /// unix timestamp in seconds
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+/// a saved registry login, the password or token is never returned
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryCredentialResponse {
+    pub registry: String,
+    pub username: String,
+    /// `password` or `token`
+    pub kind: String,
+    /// unix timestamp in seconds
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryLoginResponse {
+    pub registry: String,
+    /// node the login was tested on
+    pub node_name: String,
+    /// the status message of the registry
+    pub status: String,
+}
+
+/// a file of a saved image, the path is relative to the saved image dir
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ImageTransferFile {
+    pub path: String,
+    pub size: u64,
+    /// `sha256:<hex>` of the content
+    pub digest: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageTransferLayer {
+    /// path of the layer in the saved image
+    pub path: String,
+    /// the layer and every layer below it, a node with the same chain id has the layer
+    pub chain_id: String,
+}
+
+/// an image saved by the agent of the source node
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageLayersResponse {
+    /// image id
+    pub id: String,
+    /// dir of the saved image
+    pub root: String,
+    pub repo_tags: Vec<String>,
+    pub files: Vec<ImageTransferFile>,
+    /// bottom layer first
+    pub layers: Vec<ImageTransferLayer>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryCacheStatusResponse {
+    pub enabled: bool,
+    pub max_size: u64,
+    /// upstream registries the cache pulls through
+    pub registries: Vec<String>,
+    /// bytes of the cached blobs
+    pub size: u64,
+    pub blobs: usize,
+    pub manifests: usize,
+}
+
+/// a manifest in the registry cache
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryCacheItem {
+    /// like `docker.io/library/nginx`
+    pub repository: String,
+    /// tag or digest
+    pub reference: String,
+    pub digest: String,
+    pub media_type: String,
+    /// bytes of the manifest and the blobs it lists
+    pub size: u64,
+    /// unix timestamp in seconds
+    pub cached_at: u64,
+    pub last_access: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RegistryCacheSeedResponse {
+    pub image: String,
+    pub manifests: usize,
+    pub blobs: usize,
+    /// bytes fetched from the upstream registry
+    pub fetched: u64,
+}
+
+/// a line of the ndjson distribute output
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct ImageDistributeEvent {
+    /// empty for the `finished` event
+    pub node_name: String,
+    /// `pending`, `progress`, `exists`, `done`, `failed` or `finished`
+    pub status: String,
+    pub message: String,
+    /// the node the image was copied from, none if it was pulled from the registry
+    pub source: Option<String>,
+    pub error: Option<String>,
+}
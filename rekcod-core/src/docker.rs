@@ -136,8 +136,6 @@ impl DockerComposeCli {
             "DOCKER_CUSTOM_HEADERS",
             format!("{}={}", TOEKN_HEADER_KEY, get_token()),
         );
-        // disable buildkit
-        cmd.env("DOCKER_BUILDKIT", "0");
         cmd.args(args);
 
         if let Some(current_dir) = current_dir {
@@ -146,6 +144,12 @@ impl DockerComposeCli {
         return Ok(DockerComposeCli(cmd));
     }
 
+    /// read the registry logins from the docker config in the dir
+    pub fn docker_config<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.0.env("DOCKER_CONFIG", dir.as_ref());
+        self
+    }
+
     pub async fn run(&mut self) -> anyhow::Result<()> {
         let mut out = self.0.spawn()?;
         out.wait().await?;
@@ -8,6 +8,14 @@ pub fn encode_base64(input: &[u8]) -> String {
     BASE64_STANDARD.encode(input)
 }
 
+/// current unix timestamp in seconds
+pub fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
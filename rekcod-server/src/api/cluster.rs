@@ -0,0 +1,129 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use rekcod_core::api::resp::ClusterNodeError;
+
+use crate::node::manager::{Node, NodeState};
+
+/// seconds a node gets to answer a cluster wide query
+pub(crate) const DEFAULT_NODE_TIMEOUT: u64 = 5;
+const SELECTOR_KEYS: [&str; 5] = ["name", "host_name", "ip", "arch", "os"];
+
+/// run `f` on every node concurrently, a node failing or timing out only fails its own result
+pub(crate) async fn query_nodes<T, F, Fut>(
+    nodes: Vec<Arc<NodeState>>,
+    timeout: Duration,
+    f: F,
+) -> Vec<(String, anyhow::Result<T>)>
+where
+    F: Fn(Arc<NodeState>) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    futures::future::join_all(nodes.into_iter().map(|node| {
+        let node_name = node.node.name.clone();
+        let res = tokio::time::timeout(timeout, f(node));
+        async move {
+            let res = match res.await {
+                Ok(res) => res,
+                Err(_) => Err(anyhow::anyhow!("timeout after {}s", timeout.as_secs())),
+            };
+            (node_name, res)
+        }
+    }))
+    .await
+}
+
+/// the named nodes, or the online nodes matching the selector if none is named, a named
+/// node that is offline or unknown is an error
+pub(crate) fn select_nodes(
+    all: &[Arc<NodeState>],
+    node_names: &[String],
+    selector: &[(String, String)],
+) -> (Vec<Arc<NodeState>>, Vec<ClusterNodeError>) {
+    let mut nodes = Vec::new();
+    let mut errors = Vec::new();
+    if node_names.is_empty() {
+        nodes.extend(
+            all.iter()
+                .filter(|n| n.online() && node_matches(&n.node, selector))
+                .cloned(),
+        );
+        return (nodes, errors);
+    }
+
+    for name in node_names {
+        match all.iter().find(|n| &n.node.name == name) {
+            Some(node) if node.online() => nodes.push(node.clone()),
+            Some(_) => errors.push(ClusterNodeError {
+                node_name: name.clone(),
+                error: "node is offline".to_string(),
+            }),
+            None => errors.push(ClusterNodeError {
+                node_name: name.clone(),
+                error: "node not found".to_string(),
+            }),
+        }
+    }
+    (nodes, errors)
+}
+
+/// `key=value` pairs separated by commas, a value ending with `*` matches a prefix
+pub(crate) fn parse_selector(selector: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+    selector
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            let (key, value) = x
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("selector {} is not key=value", x))?;
+            let key = key.trim();
+            if !SELECTOR_KEYS.contains(&key) {
+                return Err(anyhow::anyhow!(
+                    "selector key must be one of {}",
+                    SELECTOR_KEYS.join(", ")
+                ));
+            }
+            Ok((key.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn node_matches(node: &Node, selector: &[(String, String)]) -> bool {
+    selector.iter().all(|(key, value)| {
+        let field = match key.as_str() {
+            "name" => &node.name,
+            "host_name" => &node.host_name,
+            "ip" => &node.ip,
+            "arch" => &node.arch,
+            _ => &node.os,
+        };
+        match value.strip_suffix('*') {
+            Some(prefix) => field.starts_with(prefix),
+            None => field == value,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selector() {
+        let node = Node {
+            name: "edge-01".to_string(),
+            arch: "aarch64".to_string(),
+            os: "Ubuntu".to_string(),
+            ..Default::default()
+        };
+        let selector = parse_selector(Some("name=edge-*, arch=aarch64")).unwrap();
+        assert!(node_matches(&node, &selector));
+        let selector = parse_selector(Some("name=edge-*,arch=x86_64")).unwrap();
+        assert!(!node_matches(&node, &selector));
+        assert!(node_matches(&node, &parse_selector(None).unwrap()));
+
+        assert!(parse_selector(Some("zone=a")).is_err());
+        assert!(parse_selector(Some("arch")).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{body::Body, response::Response, Json};
+use bollard::{
+    container::{Config, CreateContainerOptions},
+    network::ConnectNetworkOptions,
+    secret::{HostConfig, Mount, MountTypeEnum, PortBinding},
+};
+use futures::StreamExt as _;
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::req::{ContainerMountSpec, ContainerPortSpec, DockerContainerCreateRequest},
+    http::ApiError,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, info};
+
+use crate::{
+    api::{
+        image::pull_image_auto,
+        util::{parse_restart_policy, MIN_MEMORY},
+    },
+    node::manager::{node_manager, NodeState},
+};
+
+/// create and optionally start a container, the pull progress and
+/// create result are streamed back line by line
+pub async fn docker_container_create_by_node(
+    Json(req): Json<DockerContainerCreateRequest>,
+) -> Result<Response, ApiError> {
+    if let Err(e) = validate_spec(&req) {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!("invalid container spec: {}\n", e)))?);
+    }
+
+    let state = get_state!(req.node_name);
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        if let Err(e) = create_container(&state, &req, &tx).await {
+            error!("create container on node {} error: {:?}", req.node_name, e);
+            let _ = tx.send(format!("error: {}\n", e));
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
+}
+
+async fn create_container(
+    state: &Arc<NodeState>,
+    req: &DockerContainerCreateRequest,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<()> {
+    let docker = &state.docker;
+
+    let pull = match req.pull.as_str() {
+        "always" => true,
+        "never" => false,
+        _ => docker.inspect_image(&req.image).await.is_err(),
+    };
+    if pull {
+        let nodes = node_manager().get_all_nodes(false).await?;
+        let send = |line: String| {
+            let _ = tx.send(format!("{}\n", line));
+        };
+        pull_image_auto(state, &nodes, &req.image, None, None, send).await?;
+    }
+
+    let options = req.name.as_ref().map(|name| CreateContainerOptions {
+        name: name.clone(),
+        platform: None,
+    });
+    let res = docker
+        .create_container(options, container_config(req))
+        .await?;
+    for warning in res.warnings.iter() {
+        let _ = tx.send(format!("warning: {}\n", warning));
+    }
+    info!(
+        "created container {} of image {} on node {}",
+        res.id, req.image, req.node_name
+    );
+    let _ = tx.send(format!("created container {}\n", res.id));
+
+    for network in req.networks.iter().skip(1) {
+        docker
+            .connect_network(
+                network,
+                ConnectNetworkOptions {
+                    container: res.id.clone(),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let _ = tx.send(format!("connected network {}\n", network));
+    }
+
+    if req.start {
+        docker.start_container::<String>(&res.id, None).await?;
+        let _ = tx.send(format!("started container {}\n", res.id));
+    }
+
+    Ok(())
+}
+
+fn container_config(req: &DockerContainerCreateRequest) -> Config<String> {
+    let mut exposed_ports = HashMap::new();
+    let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
+    for port in req.ports.iter() {
+        let key = format!("{}/{}", port.container_port, port.protocol);
+        exposed_ports.insert(key.clone(), HashMap::new());
+        port_bindings
+            .entry(key)
+            .or_default()
+            .get_or_insert_with(Vec::new)
+            .push(PortBinding {
+                host_ip: port.host_ip.clone(),
+                host_port: port.host_port.map(|p| p.to_string()),
+            });
+    }
+
+    let host_config = HostConfig {
+        port_bindings: Some(port_bindings),
+        mounts: Some(req.mounts.iter().map(container_mount).collect()),
+        restart_policy: req
+            .restart_policy
+            .as_deref()
+            .and_then(|p| parse_restart_policy(p).ok()),
+        nano_cpus: req.cpus.map(|c| (c * 1e9) as i64),
+        memory: req.memory,
+        network_mode: req.networks.first().cloned(),
+        ..Default::default()
+    };
+
+    Config {
+        image: Some(req.image.clone()),
+        cmd: req.cmd.clone(),
+        entrypoint: req.entrypoint.clone(),
+        env: Some(
+            req.env
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect(),
+        ),
+        labels: Some(req.labels.clone()),
+        exposed_ports: Some(exposed_ports),
+        host_config: Some(host_config),
+        ..Default::default()
+    }
+}
+
+fn container_mount(mount: &ContainerMountSpec) -> Mount {
+    let typ = match mount.typ.as_str() {
+        "bind" => MountTypeEnum::BIND,
+        "tmpfs" => MountTypeEnum::TMPFS,
+        _ => MountTypeEnum::VOLUME,
+    };
+    Mount {
+        typ: Some(typ),
+        source: Some(mount.source.clone()).filter(|s| !s.is_empty()),
+        target: Some(mount.target.clone()),
+        read_only: Some(mount.read_only),
+        ..Default::default()
+    }
+}
+
+fn validate_spec(req: &DockerContainerCreateRequest) -> anyhow::Result<()> {
+    if req.node_name.is_empty() {
+        return Err(anyhow::anyhow!("node_name is required"));
+    }
+    if req.image.is_empty() || req.image.contains(char::is_whitespace) {
+        return Err(anyhow::anyhow!("invalid image {:?}", req.image));
+    }
+    if let Some(name) = &req.name {
+        if !valid_container_name(name) {
+            return Err(anyhow::anyhow!("invalid container name {:?}", name));
+        }
+    }
+    if !matches!(req.pull.as_str(), "missing" | "always" | "never") {
+        return Err(anyhow::anyhow!("invalid pull policy {}", req.pull));
+    }
+
+    if let Some(key) = req.env.keys().find(|k| k.is_empty() || k.contains('=')) {
+        return Err(anyhow::anyhow!("invalid env name {:?}", key));
+    }
+    if req.labels.keys().any(|k| k.is_empty()) {
+        return Err(anyhow::anyhow!("label name can not be empty"));
+    }
+
+    validate_ports(&req.ports)?;
+    for mount in req.mounts.iter() {
+        validate_mount(mount)?;
+    }
+    if req.networks.iter().any(|n| n.is_empty()) {
+        return Err(anyhow::anyhow!("network name can not be empty"));
+    }
+
+    if let Some(policy) = &req.restart_policy {
+        parse_restart_policy(policy)?;
+    }
+    if req.cpus.is_some_and(|c| !c.is_finite() || c <= 0.0) {
+        return Err(anyhow::anyhow!("cpus must be greater than 0"));
+    }
+    if req.memory.is_some_and(|m| m < MIN_MEMORY) {
+        return Err(anyhow::anyhow!(
+            "memory must be at least {} bytes",
+            MIN_MEMORY
+        ));
+    }
+
+    Ok(())
+}
+
+fn valid_container_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+fn validate_ports(ports: &[ContainerPortSpec]) -> anyhow::Result<()> {
+    let mut host_ports = HashMap::new();
+    for port in ports {
+        if port.container_port == 0 {
+            return Err(anyhow::anyhow!("container port can not be 0"));
+        }
+        if !matches!(port.protocol.as_str(), "tcp" | "udp" | "sctp") {
+            return Err(anyhow::anyhow!("invalid port protocol {}", port.protocol));
+        }
+        if let Some(host_ip) = &port.host_ip {
+            if host_ip.parse::<std::net::IpAddr>().is_err() {
+                return Err(anyhow::anyhow!("invalid host ip {}", host_ip));
+            }
+        }
+
+        if let Some(host_port) = port.host_port.filter(|p| *p != 0) {
+            let key = (host_port, port.protocol.as_str(), port.host_ip.as_deref());
+            if let Some(other) = host_ports.insert(key, port.container_port) {
+                return Err(anyhow::anyhow!(
+                    "host port {}/{} is bound to both {} and {}",
+                    host_port,
+                    port.protocol,
+                    other,
+                    port.container_port
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_mount(mount: &ContainerMountSpec) -> anyhow::Result<()> {
+    if !mount.target.starts_with('/') {
+        return Err(anyhow::anyhow!(
+            "mount target {:?} must be an absolute path",
+            mount.target
+        ));
+    }
+    match mount.typ.as_str() {
+        "bind" if !mount.source.starts_with('/') => Err(anyhow::anyhow!(
+            "bind mount source {:?} must be an absolute path",
+            mount.source
+        )),
+        "tmpfs" if !mount.source.is_empty() => {
+            Err(anyhow::anyhow!("tmpfs mount can not have a source"))
+        }
+        "bind" | "volume" | "tmpfs" => Ok(()),
+        typ => Err(anyhow::anyhow!("invalid mount type {}", typ)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> DockerContainerCreateRequest {
+        DockerContainerCreateRequest {
+            node_name: "n1".to_string(),
+            image: "nginx:1.27".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_spec() {
+        assert!(validate_spec(&spec()).is_ok());
+
+        let mut req = spec();
+        req.name = Some("-web".to_string());
+        assert!(validate_spec(&req).is_err());
+
+        let mut req = spec();
+        req.ports = vec![
+            ContainerPortSpec {
+                host_port: Some(8080),
+                container_port: 80,
+                ..Default::default()
+            },
+            ContainerPortSpec {
+                host_port: Some(8080),
+                container_port: 81,
+                ..Default::default()
+            },
+        ];
+        assert!(validate_spec(&req).is_err());
+        req.ports[1].protocol = "udp".to_string();
+        assert!(validate_spec(&req).is_ok());
+
+        let mut req = spec();
+        req.mounts = vec![ContainerMountSpec {
+            typ: "bind".to_string(),
+            source: "data".to_string(),
+            target: "/data".to_string(),
+            read_only: false,
+        }];
+        assert!(validate_spec(&req).is_err());
+
+        let mut req = spec();
+        req.memory = Some(1024);
+        assert!(validate_spec(&req).is_err());
+
+        let mut req = spec();
+        req.env.insert("A=B".to_string(), "1".to_string());
+        assert!(validate_spec(&req).is_err());
+    }
+}
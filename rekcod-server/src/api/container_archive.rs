@@ -0,0 +1,232 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Query,
+    response::Response,
+    Json,
+};
+use base64::Engine as _;
+use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
+use futures::{StreamExt as _, TryStreamExt as _};
+use hyper::{header, HeaderMap, StatusCode};
+use rekcod_core::{
+    api::{
+        req::DockerContainerArchiveRequest,
+        resp::{ApiJsonResponse, ContainerPathStat},
+    },
+    client::get_client,
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use tracing::info;
+
+use crate::{api::util::until_error, node::manager::NodeState};
+
+const PATH_STAT_HEADER: &str = "X-Docker-Container-Path-Stat";
+const TAR_BLOCK_SIZE: usize = 512;
+/// the largest size an octal ustar size field holds
+const TAR_MAX_FILE_SIZE: u64 = 0o77777777777;
+
+/// stat a path in a container, bollard has no api for the archive HEAD request
+pub async fn docker_container_archive_stat_by_node(
+    Json(req): Json<DockerContainerArchiveRequest>,
+) -> Result<Json<ApiJsonResponse<ContainerPathStat>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let headers = stat_path(&state, &req.container, &req.path)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no {} in container {}", req.path, req.container))?;
+    let stat = headers
+        .get(PATH_STAT_HEADER)
+        .ok_or_else(|| anyhow::anyhow!("no {} in response", PATH_STAT_HEADER))?;
+    let stat = base64::engine::general_purpose::STANDARD.decode(stat.as_bytes())?;
+    Ok(ApiJsonResponse::success(serde_json::from_slice::<ContainerPathStat>(&stat)?).into())
+}
+
+/// whether the path exists in the container, it needs no program in the container
+pub(crate) async fn container_path_exists(
+    state: &NodeState,
+    container: &str,
+    path: &str,
+) -> anyhow::Result<bool> {
+    Ok(stat_path(state, container, path).await?.is_some())
+}
+
+/// the headers of the archive HEAD request, none if the path does not exist
+async fn stat_path(
+    state: &NodeState,
+    container: &str,
+    path: &str,
+) -> anyhow::Result<Option<HeaderMap>> {
+    let resp = get_client()?
+        .head(format!(
+            "{}/containers/{}/archive",
+            state.get_docker_proxy(),
+            container
+        ))
+        .query(&[("path", path)])
+        .send()
+        .await?;
+    match resp.status() {
+        StatusCode::NOT_FOUND => Ok(None),
+        status if status.is_success() => Ok(Some(resp.headers().clone())),
+        status => Err(anyhow::anyhow!(
+            "stat {} in container {} error: {}",
+            path,
+            container,
+            status
+        )),
+    }
+}
+
+/// a file or directory of a container as a tar stream
+pub async fn docker_container_archive_download_by_node(
+    Json(req): Json<DockerContainerArchiveRequest>,
+) -> Result<Response, ApiError> {
+    let state = get_state!(req.node_name);
+    info!(
+        "download {} from container {} on node {}",
+        req.path, req.container, req.node_name
+    );
+
+    let mut stream = Box::pin(
+        state
+            .docker
+            .download_from_container(
+                &req.container,
+                Some(DownloadFromContainerOptions {
+                    path: req.path.clone(),
+                }),
+            )
+            .map_err(std::io::Error::other)
+            .peekable(),
+    );
+    // fail with an error response instead of an empty archive, e.g. no such path
+    if let Some(Err(e)) = stream.as_mut().peek().await {
+        return Err(anyhow::anyhow!("{}", e).into());
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-tar")
+        .body(Body::from_stream(stream))?)
+}
+
+/// extract the tar body into a container directory, or write the body as a single
+/// file into it when `file_name` is set
+pub async fn docker_container_archive_upload_by_node(
+    Query(req): Query<DockerContainerArchiveRequest>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+    let options = Some(UploadToContainerOptions {
+        path: req.path.as_str(),
+        ..Default::default()
+    });
+
+    match &req.file_name {
+        Some(file_name) => {
+            let size = headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| anyhow::anyhow!("content-length is required for a single file"))?;
+            let header = tar_file_header(file_name, size, unix_timestamp())?;
+
+            let tar = futures::stream::once(async move { Ok(Bytes::from(header.to_vec())) })
+                .chain(body.into_data_stream())
+                .chain(futures::stream::once(async move {
+                    Ok(Bytes::from(vec![0; tar_trailer_size(size)]))
+                }));
+            state
+                .docker
+                .upload_to_container_streaming(&req.container, options, until_error(tar))
+                .await?;
+        }
+        None => {
+            state
+                .docker
+                .upload_to_container_streaming(
+                    &req.container,
+                    options,
+                    until_error(body.into_data_stream()),
+                )
+                .await?;
+        }
+    }
+
+    info!(
+        "upload into {} of container {} on node {}",
+        req.path, req.container, req.node_name
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+/// ustar header of a regular file
+fn tar_file_header(name: &str, size: u64, mtime: u64) -> anyhow::Result<[u8; TAR_BLOCK_SIZE]> {
+    if name.is_empty() || name.len() > 100 || name.contains('/') || name == "." || name == ".." {
+        return Err(anyhow::anyhow!("invalid file name {}", name));
+    }
+    if size > TAR_MAX_FILE_SIZE {
+        return Err(anyhow::anyhow!(
+            "file is too large for a single file upload"
+        ));
+    }
+
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    let mut put = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    put(0, name.as_bytes());
+    put(100, b"0000644\0");
+    put(108, b"0000000\0");
+    put(116, b"0000000\0");
+    put(124, format!("{:011o}\0", size).as_bytes());
+    put(136, format!("{:011o}\0", mtime).as_bytes());
+    put(148, b"        ");
+    put(156, b"0");
+    put(257, b"ustar\0");
+    put(263, b"00");
+
+    let checksum = header.iter().map(|b| *b as u32).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+/// padding of the file to a whole block, then the two empty end blocks
+fn tar_trailer_size(size: u64) -> usize {
+    let padding = (TAR_BLOCK_SIZE - (size % TAR_BLOCK_SIZE as u64) as usize) % TAR_BLOCK_SIZE;
+    padding + 2 * TAR_BLOCK_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_file_header() {
+        let header = tar_file_header("app.conf", 1025, 1700000000).unwrap();
+        assert_eq!(&header[0..8], b"app.conf");
+        assert_eq!(&header[124..136], b"00000002001\0");
+        assert_eq!(&header[257..262], b"ustar");
+
+        let checksum = std::str::from_utf8(&header[148..154]).unwrap();
+        let expected = header
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u32)
+            .sum::<u32>();
+        assert_eq!(u32::from_str_radix(checksum, 8).unwrap(), expected);
+
+        assert!(tar_file_header("etc/app.conf", 1, 0).is_err());
+        assert!(tar_file_header("", 1, 0).is_err());
+        assert!(tar_file_header("big", TAR_MAX_FILE_SIZE + 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_tar_trailer_size() {
+        assert_eq!(tar_trailer_size(0), 1024);
+        assert_eq!(tar_trailer_size(1), 511 + 1024);
+        assert_eq!(tar_trailer_size(512), 1024);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{pin::Pin, time::Duration};
+
+use axum::{
+    body::Body,
+    response::{IntoResponse as _, Response},
+    Json,
+};
+use bollard::{
+    container::LogOutput,
+    errors::Error,
+    exec::{CreateExecOptions, StartExecOptions, StartExecResults},
+};
+use futures::{Stream, StreamExt as _};
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::{DockerContainerExecRequest, ExecKillRequest},
+        resp::{ApiJsonResponse, ContainerExecEvent, ContainerExecResponse},
+    },
+    http::ApiError,
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{info, warn};
+
+use crate::node::manager::NodeState;
+
+/// buffered output of each stream is cut at this size
+const MAX_BUFFERED_OUTPUT: usize = 8 * 1024 * 1024;
+/// how long to wait for docker to record the exit code once the output is closed
+const EXIT_CODE_RETRIES: u32 = 10;
+const EXIT_CODE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+type ExecOutput = Pin<Box<dyn Stream<Item = Result<LogOutput, Error>> + Send>>;
+
+/// run a command in a container without a terminal, then report its exit code
+pub async fn docker_container_exec_by_node(
+    Json(req): Json<DockerContainerExecRequest>,
+) -> Result<Response, ApiError> {
+    if req.cmd.is_empty() || req.container.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("container and cmd are required\n"))?);
+    }
+    let state = get_state!(req.node_name);
+
+    let env = req
+        .env
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>();
+    let config = CreateExecOptions {
+        cmd: Some(req.cmd.clone()),
+        env: Some(env),
+        user: req.user.clone(),
+        working_dir: req.workdir.clone(),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        tty: Some(false),
+        ..Default::default()
+    };
+    let exec_id = state.docker.create_exec(&req.container, config).await?.id;
+    let output = match state
+        .docker
+        .start_exec(&exec_id, None::<StartExecOptions>)
+        .await?
+    {
+        StartExecResults::Attached { output, .. } => output,
+        StartExecResults::Detached => {
+            return Err(anyhow::anyhow!("exec {} is not attached", exec_id).into())
+        }
+    };
+    info!(
+        "exec {:?} in container {} on node {}",
+        req.cmd, req.container, req.node_name
+    );
+    let timeout = req.timeout.map(Duration::from_secs);
+
+    if !req.stream {
+        let mut res = ContainerExecResponse::default();
+        let read = read_output(output, timeout, |stream, data| {
+            let buffer = if stream == "stderr" {
+                &mut res.stderr
+            } else {
+                &mut res.stdout
+            };
+            if buffer.len() + data.len() > MAX_BUFFERED_OUTPUT {
+                res.truncated = true;
+                return;
+            }
+            buffer.push_str(&data);
+        })
+        .await;
+        res.timed_out = read?;
+        if res.timed_out {
+            res.error = kill_exec(&state, &exec_id)
+                .await
+                .err()
+                .map(|e| e.to_string());
+        } else {
+            res.exit_code = exit_code(&state, &exec_id).await?;
+        }
+        return Ok(Json(ApiJsonResponse::success(res)).into_response());
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let send = |event: &ContainerExecEvent| {
+            if let Ok(line) = serde_json::to_string(event) {
+                let _ = tx.send(format!("{}\n", line));
+            }
+        };
+
+        let read = read_output(output, timeout, |stream, data| {
+            send(&ContainerExecEvent {
+                stream: stream.to_string(),
+                data,
+                ..Default::default()
+            })
+        })
+        .await;
+        let mut last = ContainerExecEvent::default();
+        match read {
+            Ok(true) => {
+                last.timed_out = true;
+                last.error = kill_exec(&state, &exec_id)
+                    .await
+                    .err()
+                    .map(|e| e.to_string());
+            }
+            Ok(false) => match exit_code(&state, &exec_id).await {
+                Ok(code) => last.exit_code = code,
+                Err(e) => last.error = Some(e.to_string()),
+            },
+            Err(e) => last.error = Some(e.to_string()),
+        }
+        send(&last);
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
+}
+
+/// pass the output to `on_output` until it closes, return true if it timed out first
+async fn read_output(
+    mut output: ExecOutput,
+    timeout: Option<Duration>,
+    mut on_output: impl FnMut(&str, String),
+) -> anyhow::Result<bool> {
+    let read = async {
+        while let Some(chunk) = output.next().await {
+            let (stream, message) = match chunk? {
+                LogOutput::StdErr { message } => ("stderr", message),
+                LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                    ("stdout", message)
+                }
+                LogOutput::StdIn { .. } => continue,
+            };
+            on_output(stream, String::from_utf8_lossy(&message).to_string());
+        }
+        anyhow::Ok(())
+    };
+
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, read).await {
+            Ok(res) => res.map(|_| false),
+            Err(_) => Ok(true),
+        },
+        None => read.await.map(|_| false),
+    }
+}
+
+/// the exit code of a finished exec
+async fn exit_code(state: &NodeState, exec_id: &str) -> anyhow::Result<Option<i64>> {
+    for _ in 0..EXIT_CODE_RETRIES {
+        let exec = state.docker.inspect_exec(exec_id).await?;
+        if exec.running != Some(true) {
+            return Ok(exec.exit_code);
+        }
+        tokio::time::sleep(EXIT_CODE_RETRY_INTERVAL).await;
+    }
+    Ok(None)
+}
+
+/// kill a timed out exec through the agent, docker has no api for it
+async fn kill_exec(state: &NodeState, exec_id: &str) -> anyhow::Result<()> {
+    let req = ExecKillRequest {
+        exec_id: exec_id.to_string(),
+    };
+    let res = state.agent_post::<_, ()>("/exec/kill", &req).await?;
+    if res.code() != 0 {
+        warn!("kill timed out exec {} error: {}", exec_id, res.msg());
+        return Err(anyhow::anyhow!(
+            "kill timed out exec {} error: {}",
+            exec_id,
+            res.msg()
+        ));
+    }
+    info!("killed timed out exec {}", exec_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(chunks: Vec<LogOutput>) -> ExecOutput {
+        Box::pin(futures::stream::iter(chunks.into_iter().map(Ok)))
+    }
+
+    #[tokio::test]
+    async fn test_read_output() {
+        let chunks = vec![
+            LogOutput::StdOut {
+                message: "a".into(),
+            },
+            LogOutput::StdErr {
+                message: "b".into(),
+            },
+            LogOutput::StdOut {
+                message: "c".into(),
+            },
+        ];
+        let mut lines = Vec::new();
+        let timed_out = read_output(output(chunks), None, |stream, data| {
+            lines.push(format!("{}:{}", stream, data))
+        })
+        .await
+        .unwrap();
+        assert!(!timed_out);
+        assert_eq!(lines, vec!["stdout:a", "stderr:b", "stdout:c"]);
+
+        let pending = Box::pin(futures::stream::pending()) as ExecOutput;
+        let timed_out = read_output(pending, Some(Duration::from_millis(10)), |_, _| {})
+            .await
+            .unwrap();
+        assert!(timed_out);
+    }
+}
@@ -0,0 +1,244 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    response::Response,
+};
+use bollard::container::{LogOutput, LogsOptions};
+use futures::StreamExt as _;
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{req::DockerContainerLogsRequest, resp::ContainerLogLine},
+    http::ApiError,
+    utils::unix_timestamp,
+};
+
+const LOG_FORMAT_RAW: &str = "raw";
+const LOG_FORMAT_NDJSON: &str = "ndjson";
+const LOG_FORMAT_DOWNLOAD: &str = "download";
+const DEFAULT_FOLLOW_TAIL: &str = "100";
+
+pub async fn docker_container_logs_by_node(
+    Query(query): Query<DockerContainerLogsRequest>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let format = query.format.as_deref().unwrap_or(LOG_FORMAT_RAW);
+    let options = match logs_options(&query, format, unix_timestamp() as i64) {
+        Ok(options) => options,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("invalid log query: {}\n", e)))?);
+        }
+    };
+
+    let state = get_state!(query.node_name);
+    let logs = state.docker.logs(&id, Some(options));
+
+    let response = Response::builder().status(StatusCode::OK);
+    Ok(match format {
+        LOG_FORMAT_NDJSON => {
+            let stream = logs.map(|res| match res {
+                Ok(output) => Ok(log_lines(&output)
+                    .iter()
+                    .filter_map(|line| serde_json::to_string(line).ok())
+                    .map(|line| format!("{}\n", line))
+                    .collect::<String>()
+                    .into_bytes()),
+                Err(e) => Err(std::io::Error::other(e)),
+            });
+            response
+                .header(header::CONTENT_TYPE, "application/x-ndjson")
+                .body(Body::from_stream(stream))?
+        }
+        _ => {
+            let stream = logs.map(|res| match res {
+                Ok(output) => Ok(output.into_bytes()),
+                Err(e) => Err(std::io::Error::other(e)),
+            });
+            let response = if format == LOG_FORMAT_DOWNLOAD {
+                response
+                    .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .header(
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", download_name(&id, &query)),
+                    )
+            } else {
+                response.header(header::CONTENT_TYPE, "application/octet-stream")
+            };
+            response.body(Body::from_stream(stream))?
+        }
+    })
+}
+
+fn logs_options(
+    query: &DockerContainerLogsRequest,
+    format: &str,
+    now: i64,
+) -> anyhow::Result<LogsOptions<String>> {
+    if ![LOG_FORMAT_RAW, LOG_FORMAT_NDJSON, LOG_FORMAT_DOWNLOAD].contains(&format) {
+        return Err(anyhow::anyhow!("unknown format {}", format));
+    }
+
+    // a download is a time range, never an endless stream
+    let follow = format != LOG_FORMAT_DOWNLOAD && query.follow.unwrap_or(true);
+    let tail = match &query.tail {
+        Some(tail) if tail == "all" || tail.parse::<u64>().is_ok() => tail.clone(),
+        Some(tail) => return Err(anyhow::anyhow!("invalid tail {}", tail)),
+        None if follow && query.since.is_none() => DEFAULT_FOLLOW_TAIL.to_string(),
+        None => "all".to_string(),
+    };
+    let since = query
+        .since
+        .as_deref()
+        .map(|x| parse_log_time(x, now))
+        .transpose()?
+        .unwrap_or(0);
+    let until = query
+        .until
+        .as_deref()
+        .map(|x| parse_log_time(x, now))
+        .transpose()?
+        .unwrap_or(0);
+    if until > 0 && since > until {
+        return Err(anyhow::anyhow!("since is after until"));
+    }
+
+    let stdout = query.stdout.unwrap_or(true);
+    let stderr = query.stderr.unwrap_or(true);
+    if !stdout && !stderr {
+        return Err(anyhow::anyhow!(
+            "at least one of stdout and stderr is required"
+        ));
+    }
+
+    Ok(LogsOptions {
+        follow,
+        stdout,
+        stderr,
+        since,
+        until,
+        // the ndjson lines carry the timestamp in its own field
+        timestamps: query.timestamps || format == LOG_FORMAT_NDJSON,
+        tail,
+    })
+}
+
+/// a unix timestamp, an rfc3339 time, or a duration before now such as `90s`, `30m`, `2h`, `7d`
+fn parse_log_time(value: &str, now: i64) -> anyhow::Result<i64> {
+    if let Ok(ts) = value.parse::<i64>() {
+        return Ok(ts);
+    }
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp());
+    }
+
+    let invalid = || anyhow::anyhow!("invalid time {}", value);
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let amount = value[..value.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .map_err(|_| invalid())?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(now - amount * seconds)
+}
+
+/// split a log chunk into lines, the timestamp docker prefixes goes into its own field
+fn log_lines(output: &LogOutput) -> Vec<ContainerLogLine> {
+    let (stream, message) = match output {
+        LogOutput::StdOut { message } => ("stdout", message),
+        LogOutput::StdErr { message } => ("stderr", message),
+        LogOutput::StdIn { message } => ("stdin", message),
+        LogOutput::Console { message } => ("console", message),
+    };
+
+    String::from_utf8_lossy(message)
+        .lines()
+        .map(|line| {
+            let (timestamp, message) = match line.split_once(' ') {
+                Some((ts, message)) if chrono::DateTime::parse_from_rfc3339(ts).is_ok() => {
+                    (Some(ts.to_string()), message)
+                }
+                _ => (None, line),
+            };
+            ContainerLogLine {
+                stream: stream.to_string(),
+                timestamp,
+                message: message.to_string(),
+            }
+        })
+        .collect()
+}
+
+fn download_name(id: &str, query: &DockerContainerLogsRequest) -> String {
+    let mut name = id.chars().take(12).collect::<String>();
+    for time in [&query.since, &query.until].into_iter().flatten() {
+        name.push('_');
+        name.extend(time.chars().filter(|c| c.is_ascii_alphanumeric()));
+    }
+    format!("{}.log", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_time() {
+        let now = 1_700_000_000;
+        assert_eq!(parse_log_time("1690000000", now).unwrap(), 1_690_000_000);
+        assert_eq!(parse_log_time("30m", now).unwrap(), now - 1800);
+        assert_eq!(parse_log_time("2d", now).unwrap(), now - 2 * 86400);
+        assert_eq!(
+            parse_log_time("2023-11-14T22:13:20Z", now).unwrap(),
+            1_700_000_000
+        );
+        assert!(parse_log_time("yesterday", now).is_err());
+        assert!(parse_log_time("", now).is_err());
+    }
+
+    #[test]
+    fn test_logs_options() {
+        let mut query = DockerContainerLogsRequest::default();
+        let options = logs_options(&query, LOG_FORMAT_RAW, 0).unwrap();
+        assert!(options.follow);
+        assert_eq!(options.tail, DEFAULT_FOLLOW_TAIL);
+
+        query.follow = Some(true);
+        let options = logs_options(&query, LOG_FORMAT_DOWNLOAD, 0).unwrap();
+        assert!(!options.follow);
+        assert_eq!(options.tail, "all");
+
+        query.stdout = Some(false);
+        query.stderr = Some(false);
+        assert!(logs_options(&query, LOG_FORMAT_RAW, 0).is_err());
+        assert!(logs_options(&DockerContainerLogsRequest::default(), "xml", 0).is_err());
+    }
+
+    #[test]
+    fn test_log_lines() {
+        let output = LogOutput::StdErr {
+            message: "2024-01-02T03:04:05.123456789Z oops\n2024-01-02T03:04:06Z again\n".into(),
+        };
+        let lines = log_lines(&output);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].stream, "stderr");
+        assert_eq!(
+            lines[0].timestamp.as_deref(),
+            Some("2024-01-02T03:04:05.123456789Z")
+        );
+        assert_eq!(lines[0].message, "oops");
+        assert_eq!(lines[1].timestamp.as_deref(), Some("2024-01-02T03:04:06Z"));
+
+        let output = LogOutput::StdOut {
+            message: "plain line".into(),
+        };
+        let lines = log_lines(&output);
+        assert_eq!(lines[0].message, "plain line");
+        assert_eq!(lines[0].timestamp, None);
+    }
+}
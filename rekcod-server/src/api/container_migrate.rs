@@ -0,0 +1,798 @@
+use std::collections::HashMap;
+
+use axum::{body::Body, response::Response, Json};
+use bollard::{
+    container::{
+        Config, CreateContainerOptions, InspectContainerOptions, ListContainersOptions,
+        NetworkingConfig, RemoveContainerOptions, StopContainerOptions,
+    },
+    image::{CommitContainerOptions, RemoveImageOptions},
+    network::ConnectNetworkOptions,
+    secret::{ContainerInspectResponse, ContainerSummary, EndpointSettings, MountPointTypeEnum},
+    volume::{CreateVolumeOptions, RemoveVolumeOptions},
+};
+use futures::StreamExt as _;
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::{
+            ContainerMigrationConfirmRequest, ContainerMigrationListRequest,
+            DockerContainerMigrateRequest,
+        },
+        resp::{ApiJsonResponse, ContainerMigrationResponse},
+    },
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        image::{copy_image, Platform},
+        volume_backup::copy_volume,
+    },
+    db,
+    node::manager::NodeState,
+};
+
+const MIGRATION_MODULE: &str = "container_migration";
+const STATUS_MIGRATED: &str = "migrated";
+const STATUS_CONFIRMED: &str = "confirmed";
+const COMMIT_REPO_PREFIX: &str = "rekcod-migrate";
+/// networks every docker host has
+const DEFAULT_NETWORKS: [&str; 4] = ["bridge", "host", "none", "default"];
+
+/// what was created on the way, removed again if the migration fails
+#[derive(Default)]
+struct Created {
+    committed_image: Option<String>,
+    image_on_target: bool,
+    volumes: Vec<String>,
+    container: Option<String>,
+}
+
+/// stop a container, move its image and named volumes to another node and recreate
+/// it there; the progress is streamed back line by line and the source container is
+/// kept until the migration is confirmed
+pub async fn docker_container_migrate_by_node(
+    Json(req): Json<DockerContainerMigrateRequest>,
+) -> Result<Response, ApiError> {
+    if req.container.is_empty() || req.target_node.is_empty() || req.target_node == req.node_name {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(
+                "container and a target node other than the source are required\n",
+            ))?);
+    }
+    let source = get_state!(req.node_name);
+    let target = get_state!(req.target_node);
+    if !target.online() {
+        return Err(anyhow::anyhow!("node {} is offline", req.target_node).into());
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        match migrate(&source, &target, &req, &tx).await {
+            Ok(migration) => {
+                let _ = tx.send(format!(
+                    "migrated container {} to node {}, confirm migration {} to remove the source container\n",
+                    migration.name, migration.target_node, migration.id
+                ));
+            }
+            Err(e) => {
+                error!(
+                    "migrate container {} from node {} to {} error: {:?}",
+                    req.container, req.node_name, req.target_node, e
+                );
+                let _ = tx.send(format!("error: {}\n", e));
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
+}
+
+pub async fn list_container_migration(
+    Json(req): Json<ContainerMigrationListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ContainerMigrationResponse>>>, ApiError> {
+    let mut migrations = db::repository()
+        .await
+        .kvs
+        .select(MIGRATION_MODULE, req.node_name.as_deref(), None, None)
+        .await?
+        .iter()
+        .filter_map(|x| serde_json::from_str::<ContainerMigrationResponse>(&x.value).ok())
+        .collect::<Vec<_>>();
+    migrations.sort_by_key(|x| std::cmp::Reverse(x.created_at));
+    Ok(ApiJsonResponse::success(migrations).into())
+}
+
+/// remove the source container, and the image committed for it, of a migration
+pub async fn confirm_container_migration(
+    Json(req): Json<ContainerMigrationConfirmRequest>,
+) -> Result<Json<ApiJsonResponse<ContainerMigrationResponse>>, ApiError> {
+    let mut migration = db::repository()
+        .await
+        .kvs
+        .select_one(MIGRATION_MODULE, None, None, Some(&req.id))
+        .await?
+        .map(|x| serde_json::from_str::<ContainerMigrationResponse>(&x.value))
+        .transpose()?
+        .ok_or_else(|| anyhow::anyhow!("migration {} not found", req.id))?;
+    if migration.status != STATUS_MIGRATED {
+        return Err(anyhow::anyhow!("migration {} is already {}", req.id, migration.status).into());
+    }
+
+    let source = get_state!(migration.source_node);
+    let options = Some(RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    });
+    source
+        .docker
+        .remove_container(&migration.source_container, options)
+        .await?;
+    if migration.committed {
+        if let Err(e) = source
+            .docker
+            .remove_image(&migration.image, None::<RemoveImageOptions>, None)
+            .await
+        {
+            warn!(
+                "remove committed image {} on node {} error: {}",
+                migration.image, migration.source_node, e
+            );
+        }
+    }
+
+    migration.status = STATUS_CONFIRMED.to_string();
+    save_migration(&migration).await?;
+    info!(
+        "removed source container {} of migration {} on node {}",
+        migration.name, migration.id, migration.source_node
+    );
+    Ok(ApiJsonResponse::success(migration).into())
+}
+
+async fn migrate(
+    source: &NodeState,
+    target: &NodeState,
+    req: &DockerContainerMigrateRequest,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<ContainerMigrationResponse> {
+    let inspect = source
+        .docker
+        .inspect_container(&req.container, None::<InspectContainerOptions>)
+        .await?;
+    let id = inspect.id.clone().unwrap_or_default();
+    let name = container_name(&inspect);
+    let running = is_running(&inspect);
+
+    // nothing is touched until the target is known to accept the container
+    let (volumes, binds) = container_volumes(&inspect);
+    if target
+        .docker
+        .inspect_container(&name, None::<InspectContainerOptions>)
+        .await
+        .is_ok()
+    {
+        return Err(anyhow::anyhow!(
+            "container {} already exists on node {}",
+            name,
+            target.node.name
+        ));
+    }
+    for volume in volumes.iter() {
+        if target.docker.inspect_volume(volume).await.is_ok() {
+            return Err(anyhow::anyhow!(
+                "volume {} already exists on node {}",
+                volume,
+                target.node.name
+            ));
+        }
+    }
+    for network in container_networks(&inspect).keys() {
+        if target
+            .docker
+            .inspect_network::<String>(network, None)
+            .await
+            .is_err()
+        {
+            return Err(anyhow::anyhow!(
+                "network {} not found on node {}",
+                network,
+                target.node.name
+            ));
+        }
+    }
+    let clashes = port_clashes(
+        &inspect,
+        &target
+            .docker
+            .list_containers(None::<ListContainersOptions<String>>)
+            .await?,
+    );
+    if !clashes.is_empty() {
+        return Err(anyhow::anyhow!(
+            "host ports {} are used by running containers on node {}",
+            clashes.join(", "),
+            target.node.name
+        ));
+    }
+    // the image of the container must run on the target
+    let platform = Platform::of_node(&target.node);
+    let image_id = inspect.image.as_deref().unwrap_or_default();
+    let image = source.docker.inspect_image(image_id).await?;
+    if !platform.matches(&image) {
+        return Err(anyhow::anyhow!(
+            "image of container {} is {}/{}, node {} is {}",
+            name,
+            image.os.unwrap_or_default(),
+            image.architecture.unwrap_or_default(),
+            target.node.name,
+            platform
+        ));
+    }
+    // the target recreates the container from the tag, it must still be the same image
+    if !req.commit {
+        if let Some(tag) = inspect.config.as_ref().and_then(|c| c.image.as_deref()) {
+            let tagged = source.docker.inspect_image(tag).await?;
+            if tagged.id.as_deref() != Some(image_id) {
+                return Err(anyhow::anyhow!(
+                    "image {} no longer is the image of container {}, migrate it with commit",
+                    tag,
+                    name
+                ));
+            }
+        }
+    }
+    for bind in binds.iter() {
+        let _ = tx.send(format!("warning: bind mount {} is not copied\n", bind));
+    }
+    for volume in volumes.iter().filter(|v| is_anonymous_volume(v)) {
+        let _ = tx.send(format!(
+            "volume {} is anonymous, it is copied and mounted by its name\n",
+            volume
+        ));
+    }
+
+    if running {
+        source
+            .docker
+            .stop_container(&id, None::<StopContainerOptions>)
+            .await?;
+        let _ = tx.send(format!(
+            "stopped container {} on node {}\n",
+            name, source.node.name
+        ));
+    }
+
+    let mut created = Created::default();
+    match move_container(source, target, req, &inspect, &volumes, &mut created, tx).await {
+        Ok(migration) => {
+            save_migration(&migration).await?;
+            info!(
+                "migrated container {} from node {} to {}",
+                name, source.node.name, target.node.name
+            );
+            Ok(migration)
+        }
+        Err(e) => {
+            rollback(source, target, &created, tx).await;
+            if running {
+                match source.docker.start_container::<String>(&id, None).await {
+                    Ok(_) => {
+                        let _ = tx.send(format!(
+                            "restarted container {} on node {}\n",
+                            name, source.node.name
+                        ));
+                    }
+                    Err(e) => {
+                        let _ =
+                            tx.send(format!("warning: restart source container error: {}\n", e));
+                    }
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn move_container(
+    source: &NodeState,
+    target: &NodeState,
+    req: &DockerContainerMigrateRequest,
+    inspect: &ContainerInspectResponse,
+    volumes: &[String],
+    created: &mut Created,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<ContainerMigrationResponse> {
+    let id = inspect.id.clone().unwrap_or_default();
+    let name = container_name(inspect);
+    let name = name.as_str();
+    let running = is_running(inspect);
+
+    let image = if req.commit {
+        let repo = format!("{}/{}", COMMIT_REPO_PREFIX, name.to_lowercase());
+        let tag = unix_timestamp().to_string();
+        let options = CommitContainerOptions {
+            container: id.clone(),
+            repo: repo.clone(),
+            tag: tag.clone(),
+            comment: format!("migrated from node {}", source.node.name),
+            ..Default::default()
+        };
+        source
+            .docker
+            .commit_container(options, Config::<String>::default())
+            .await?;
+        let image = format!("{}:{}", repo, tag);
+        created.committed_image = Some(image.clone());
+        let _ = tx.send(format!("committed container {} as {}\n", name, image));
+        image
+    } else {
+        inspect
+            .config
+            .as_ref()
+            .and_then(|c| c.image.clone())
+            .or_else(|| inspect.image.clone())
+            .ok_or_else(|| anyhow::anyhow!("container {} has no image", name))?
+    };
+    transfer_image(source, target, &image, created, tx).await?;
+
+    for volume in volumes.iter() {
+        let info = source.docker.inspect_volume(volume).await?;
+        target
+            .docker
+            .create_volume(CreateVolumeOptions {
+                name: volume.clone(),
+                driver: info.driver,
+                driver_opts: info.options,
+                labels: info.labels,
+            })
+            .await?;
+        created.volumes.push(volume.clone());
+        copy_volume(source, target, volume).await?;
+        let _ = tx.send(format!("copied volume {}\n", volume));
+    }
+
+    let networks = container_networks(inspect);
+    let res = target
+        .docker
+        .create_container(
+            Some(CreateContainerOptions {
+                name: name.to_string(),
+                platform: None,
+            }),
+            container_config(inspect, &image),
+        )
+        .await?;
+    created.container = Some(res.id.clone());
+    for warning in res.warnings.iter() {
+        let _ = tx.send(format!("warning: {}\n", warning));
+    }
+    let _ = tx.send(format!(
+        "created container {} on node {}\n",
+        res.id, target.node.name
+    ));
+
+    let primary = primary_network(inspect);
+    for (network, endpoint) in networks {
+        if primary.as_deref() == Some(network.as_str()) {
+            continue;
+        }
+        target
+            .docker
+            .connect_network(
+                &network,
+                ConnectNetworkOptions {
+                    container: res.id.clone(),
+                    endpoint_config: endpoint,
+                },
+            )
+            .await?;
+        let _ = tx.send(format!("connected network {}\n", network));
+    }
+
+    if running {
+        target
+            .docker
+            .start_container::<String>(&res.id, None)
+            .await?;
+        let _ = tx.send(format!(
+            "started container {} on node {}\n",
+            name, target.node.name
+        ));
+    }
+
+    Ok(ContainerMigrationResponse {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        source_node: source.node.name.clone(),
+        source_container: id,
+        target_node: target.node.name.clone(),
+        target_container: res.id,
+        image,
+        committed: req.commit,
+        volumes: volumes.to_vec(),
+        status: STATUS_MIGRATED.to_string(),
+        created_at: unix_timestamp(),
+    })
+}
+
+/// load the image of the source node into the target node unless it already has it
+async fn transfer_image(
+    source: &NodeState,
+    target: &NodeState,
+    image: &str,
+    created: &mut Created,
+    tx: &UnboundedSender<String>,
+) -> anyhow::Result<()> {
+    let copied = copy_image(source, target, image, |line| {
+        let _ = tx.send(format!("{}\n", line));
+    })
+    .await?;
+    // a committed image exists only for the migration, other images may have been shared
+    created.image_on_target = copied && created.committed_image.is_some();
+    Ok(())
+}
+
+/// undo what a failed migration created, best effort
+async fn rollback(
+    source: &NodeState,
+    target: &NodeState,
+    created: &Created,
+    tx: &UnboundedSender<String>,
+) {
+    let mut errors = Vec::new();
+    if let Some(container) = &created.container {
+        let options = Some(RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        });
+        if let Err(e) = target.docker.remove_container(container, options).await {
+            errors.push(format!("remove container {}: {}", container, e));
+        }
+    }
+    for volume in created.volumes.iter() {
+        let options = Some(RemoveVolumeOptions { force: true });
+        if let Err(e) = target.docker.remove_volume(volume, options).await {
+            errors.push(format!("remove volume {}: {}", volume, e));
+        }
+    }
+    if let Some(image) = &created.committed_image {
+        if created.image_on_target {
+            if let Err(e) = target
+                .docker
+                .remove_image(image, None::<RemoveImageOptions>, None)
+                .await
+            {
+                errors.push(format!("remove image {} on target: {}", image, e));
+            }
+        }
+        if let Err(e) = source
+            .docker
+            .remove_image(image, None::<RemoveImageOptions>, None)
+            .await
+        {
+            errors.push(format!("remove image {} on source: {}", image, e));
+        }
+    }
+
+    for e in errors {
+        warn!("migration rollback error: {}", e);
+        let _ = tx.send(format!("warning: rollback {}\n", e));
+    }
+}
+
+async fn save_migration(migration: &ContainerMigrationResponse) -> anyhow::Result<()> {
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: MIGRATION_MODULE.to_string(),
+            key: migration.source_node.clone(),
+            sub_key: migration.name.clone(),
+            third_key: migration.id.clone(),
+            value: serde_json::to_string(migration)?,
+            ..Default::default()
+        })
+        .await
+}
+
+fn container_name(inspect: &ContainerInspectResponse) -> String {
+    inspect
+        .name
+        .as_deref()
+        .unwrap_or_default()
+        .trim_start_matches('/')
+        .to_string()
+}
+
+fn is_running(inspect: &ContainerInspectResponse) -> bool {
+    inspect
+        .state
+        .as_ref()
+        .and_then(|s| s.running)
+        .unwrap_or(false)
+}
+
+/// the volumes, which are copied, and the bind mount sources, which are not
+fn container_volumes(inspect: &ContainerInspectResponse) -> (Vec<String>, Vec<String>) {
+    let mut volumes = Vec::new();
+    let mut binds = Vec::new();
+    for mount in inspect.mounts.iter().flatten() {
+        match (&mount.typ, &mount.name, &mount.source) {
+            (Some(MountPointTypeEnum::VOLUME), Some(name), _) => volumes.push(name.clone()),
+            (Some(MountPointTypeEnum::BIND), _, Some(source)) => binds.push(source.clone()),
+            _ => {}
+        }
+    }
+    (volumes, binds)
+}
+
+/// docker names anonymous volumes with 64 hex characters
+fn is_anonymous_volume(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// binds of the anonymous volumes, the created container would get new empty ones otherwise
+fn anonymous_volume_binds(inspect: &ContainerInspectResponse) -> Vec<String> {
+    inspect
+        .mounts
+        .iter()
+        .flatten()
+        .filter(|m| m.typ == Some(MountPointTypeEnum::VOLUME))
+        .filter_map(|m| match (&m.name, &m.destination) {
+            (Some(name), Some(destination)) if is_anonymous_volume(name) => {
+                let mode = if m.rw == Some(false) { ":ro" } else { "" };
+                Some(format!("{}:{}{}", name, destination, mode))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// user defined networks and their endpoints, aliases docker adds itself are dropped
+fn container_networks(inspect: &ContainerInspectResponse) -> HashMap<String, EndpointSettings> {
+    let short_id = inspect
+        .id
+        .as_deref()
+        .unwrap_or_default()
+        .chars()
+        .take(12)
+        .collect::<String>();
+    inspect
+        .network_settings
+        .as_ref()
+        .and_then(|n| n.networks.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| !DEFAULT_NETWORKS.contains(&name.as_str()))
+        .map(|(name, endpoint)| {
+            let aliases = endpoint
+                .aliases
+                .map(|a| a.into_iter().filter(|x| x != &short_id).collect());
+            let endpoint = EndpointSettings {
+                aliases,
+                ..Default::default()
+            };
+            (name, endpoint)
+        })
+        .collect()
+}
+
+/// the network the container is created in, others are connected after
+fn primary_network(inspect: &ContainerInspectResponse) -> Option<String> {
+    inspect
+        .host_config
+        .as_ref()
+        .and_then(|h| h.network_mode.clone())
+        .filter(|n| !DEFAULT_NETWORKS.contains(&n.as_str()) && !n.starts_with("container:"))
+}
+
+/// the config the container was created with, on the image on the target
+fn container_config(inspect: &ContainerInspectResponse, image: &str) -> Config<String> {
+    let mut config = inspect.config.clone().map(Config::from).unwrap_or_default();
+    config.image = Some(image.to_string());
+    // docker sets the hostname to the short id unless one was given
+    let short_id = inspect
+        .id
+        .as_deref()
+        .unwrap_or_default()
+        .chars()
+        .take(12)
+        .collect::<String>();
+    if config.hostname.as_deref() == Some(short_id.as_str()) {
+        config.hostname = None;
+    }
+    config.host_config = inspect.host_config.clone();
+    let anonymous = anonymous_volume_binds(inspect);
+    if !anonymous.is_empty() {
+        let host_config = config.host_config.get_or_insert_with(Default::default);
+        host_config
+            .binds
+            .get_or_insert_with(Vec::new)
+            .extend(anonymous);
+    }
+    config.networking_config = primary_network(inspect).map(|network| {
+        let endpoint = container_networks(inspect)
+            .remove(&network)
+            .unwrap_or_default();
+        NetworkingConfig {
+            endpoints_config: HashMap::from([(network, endpoint)]),
+        }
+    });
+    config
+}
+
+/// host ports the container publishes that a running container already publishes,
+/// like `8080/tcp`
+fn port_clashes(inspect: &ContainerInspectResponse, running: &[ContainerSummary]) -> Vec<String> {
+    let is_any = |ip: &str| matches!(ip, "" | "0.0.0.0" | "::");
+    let bindings = inspect
+        .host_config
+        .as_ref()
+        .and_then(|c| c.port_bindings.as_ref());
+    let mut clashes = Vec::new();
+    for (port, bindings) in bindings.into_iter().flatten() {
+        let protocol = port.split_once('/').map_or("tcp", |(_, p)| p);
+        for binding in bindings.iter().flatten() {
+            // ranges and random ports can not clash
+            let Some(host_port) = binding
+                .host_port
+                .as_deref()
+                .and_then(|p| p.parse::<u16>().ok())
+                .filter(|p| *p != 0)
+            else {
+                continue;
+            };
+            let host_ip = binding.host_ip.as_deref().unwrap_or_default();
+            let used = running
+                .iter()
+                .flat_map(|c| c.ports.iter().flatten())
+                .any(|p| {
+                    let ip = p.ip.as_deref().unwrap_or_default();
+                    p.public_port == Some(host_port)
+                        && p.typ.is_some_and(|t| t.to_string() == protocol)
+                        && (is_any(host_ip) || is_any(ip) || host_ip == ip)
+                });
+            if used {
+                clashes.push(format!("{}/{}", host_port, protocol));
+            }
+        }
+    }
+    clashes.sort();
+    clashes.dedup();
+    clashes
+}
+
+#[cfg(test)]
+mod tests {
+    use bollard::secret::{
+        ContainerConfig, HostConfig, MountPoint, NetworkSettings, Port, PortBinding, PortTypeEnum,
+    };
+
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn inspect() -> ContainerInspectResponse {
+        let mount = |typ, name: Option<&str>, source: &str| MountPoint {
+            typ: Some(typ),
+            name: name.map(|x| x.to_string()),
+            source: Some(source.to_string()),
+            destination: Some(format!("/mnt/{}", name.unwrap_or("bind"))),
+            ..Default::default()
+        };
+        let endpoint = |aliases: Vec<&str>| EndpointSettings {
+            aliases: Some(aliases.into_iter().map(|x| x.to_string()).collect()),
+            ip_address: Some("172.18.0.2".to_string()),
+            ..Default::default()
+        };
+        ContainerInspectResponse {
+            id: Some(ID.to_string()),
+            name: Some("/db".to_string()),
+            config: Some(ContainerConfig {
+                hostname: Some(ID[..12].to_string()),
+                image: Some("postgres:16".to_string()),
+                ..Default::default()
+            }),
+            host_config: Some(HostConfig {
+                network_mode: Some("backend".to_string()),
+                ..Default::default()
+            }),
+            mounts: Some(vec![
+                mount(
+                    MountPointTypeEnum::VOLUME,
+                    Some("pgdata"),
+                    "/var/lib/docker/volumes/pgdata/_data",
+                ),
+                mount(
+                    MountPointTypeEnum::VOLUME,
+                    Some(ID),
+                    "/var/lib/docker/volumes/anon/_data",
+                ),
+                mount(MountPointTypeEnum::BIND, None, "/etc/pg"),
+            ]),
+            network_settings: Some(NetworkSettings {
+                networks: Some(HashMap::from([
+                    ("backend".to_string(), endpoint(vec!["db", &ID[..12]])),
+                    ("monitor".to_string(), endpoint(vec![])),
+                    ("bridge".to_string(), endpoint(vec![])),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_container_volumes() {
+        let (volumes, binds) = container_volumes(&inspect());
+        assert_eq!(volumes, vec!["pgdata", ID]);
+        assert_eq!(binds, vec!["/etc/pg"]);
+    }
+
+    #[test]
+    fn test_container_config() {
+        let inspect = inspect();
+        let networks = container_networks(&inspect);
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks["backend"].aliases, Some(vec!["db".to_string()]));
+        assert_eq!(networks["backend"].ip_address, None);
+
+        let config = container_config(&inspect, "rekcod-migrate/db:1");
+        assert_eq!(config.image.as_deref(), Some("rekcod-migrate/db:1"));
+        assert_eq!(config.hostname, None);
+        assert_eq!(
+            config.host_config.as_ref().unwrap().binds,
+            Some(vec![format!("{}:/mnt/{}", ID, ID)])
+        );
+        let endpoints = config.networking_config.unwrap().endpoints_config;
+        assert_eq!(endpoints.keys().collect::<Vec<_>>(), vec!["backend"]);
+    }
+
+    #[test]
+    fn test_port_clashes() {
+        let binding = |ip: &str, port: &str| PortBinding {
+            host_ip: Some(ip.to_string()),
+            host_port: Some(port.to_string()),
+        };
+        let mut inspect = inspect();
+        inspect.host_config.as_mut().unwrap().port_bindings = Some(HashMap::from([
+            ("5432/tcp".to_string(), Some(vec![binding("", "5432")])),
+            ("53/udp".to_string(), Some(vec![binding("127.0.0.1", "53")])),
+            ("80/tcp".to_string(), Some(vec![binding("", "")])),
+        ]));
+        let running = |ip: &str, port: u16, typ| ContainerSummary {
+            ports: Some(vec![Port {
+                ip: Some(ip.to_string()),
+                private_port: port,
+                public_port: Some(port),
+                typ: Some(typ),
+            }]),
+            ..Default::default()
+        };
+        assert!(port_clashes(&inspect, &[]).is_empty());
+        assert_eq!(
+            port_clashes(
+                &inspect,
+                &[
+                    running("0.0.0.0", 5432, PortTypeEnum::TCP),
+                    running("10.0.0.2", 53, PortTypeEnum::UDP),
+                ]
+            ),
+            vec!["5432/tcp"]
+        );
+        assert!(port_clashes(&inspect, &[running("0.0.0.0", 5432, PortTypeEnum::UDP)]).is_empty());
+    }
+}
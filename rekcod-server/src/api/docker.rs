@@ -1,5 +1,3 @@
-use std::{collections::HashMap, sync::Arc};
-
 use axum::{
     body::Body,
     extract::{Path, Query},
@@ -8,38 +6,40 @@ use axum::{
 };
 use bollard::{
     container::{
-        InspectContainerOptions, ListContainersOptions, LogsOptions, RemoveContainerOptions,
+        InspectContainerOptions, KillContainerOptions, ListContainersOptions,
+        RemoveContainerOptions, RenameContainerOptions, TopOptions, UpdateContainerOptions,
     },
-    image::{CreateImageOptions, ImportImageOptions, ListImagesOptions},
+    image::ListImagesOptions,
     network::ListNetworksOptions,
     secret::{
-        ContainerInspectResponse, ContainerSummary, ImageSummary, Network, SystemInfo,
-        VolumeListResponse,
+        ContainerInspectResponse, ContainerSummary, ContainerTopResponse, FilesystemChange,
+        ImageSummary, Network, SystemInfo, VolumeListResponse,
     },
     volume::ListVolumesOptions,
-    Docker,
 };
-use futures::{Stream, StreamExt as _};
+use futures::StreamExt as _;
 use hyper::{header, StatusCode};
 use rekcod_core::{
     api::{
-        req::{DockerImagePullAutoRequest, NodeDockerQueryRequest},
+        req::{
+            DockerContainerKillRequest, DockerContainerRenameRequest, DockerContainerTopRequest,
+            DockerContainerUpdateRequest, DockerImagePullAutoRequest, NodeDockerQueryRequest,
+        },
         resp::ApiJsonResponse,
     },
     http::ApiError,
 };
-use tracing::info;
-
-use crate::node::manager::{node_manager, NodeState};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, info};
 
-macro_rules! get_state {
-    ($name:expr) => {
-        node_manager()
-            .get_node(&$name)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("node {} not found", &$name))?
-    };
-}
+use crate::{
+    api::{
+        image::pull_image_auto,
+        util::{parse_restart_policy, MIN_MEMORY},
+    },
+    node::manager::node_manager,
+};
 
 macro_rules! docker_exec {
     ($exec:expr) => {
@@ -83,33 +83,6 @@ pub async fn docker_container_stop_by_node(
     docker_exec!(state.docker.stop_container(&id, None).await)
 }
 
-pub async fn docker_container_logs_by_node(
-    Query(query): Query<NodeDockerQueryRequest>,
-    Path(id): Path<String>,
-) -> Result<Response, ApiError> {
-    let state = get_state!(query.node_name);
-    let stream = state
-        .docker
-        .logs(
-            &id,
-            Some(LogsOptions {
-                follow: true,
-                stdout: true,
-                stderr: true,
-                tail: "100".to_string(),
-                ..Default::default()
-            }),
-        )
-        .map(|res| match res {
-            Ok(info) => Ok(info.into_bytes()),
-            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
-        });
-    return Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/octet-stream")
-        .body(Body::from_stream(stream))?);
-}
-
 pub async fn docker_container_info_by_node(
     Query(query): Query<NodeDockerQueryRequest>,
     Path(id): Path<String>,
@@ -142,6 +115,94 @@ pub async fn docker_container_restart_by_node(
     docker_exec!(state.docker.restart_container(&id, None).await)
 }
 
+pub async fn docker_container_rename_by_node(
+    Query(query): Query<DockerContainerRenameRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(query.node_name);
+    let name = query.name.trim_start_matches('/');
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("new container name is required").into());
+    }
+    info!("rename container {} to {}", id, name);
+    docker_exec!(
+        state
+            .docker
+            .rename_container(&id, RenameContainerOptions { name })
+            .await
+    )
+}
+
+/// change the resource limits and restart policy of a container in place
+pub async fn docker_container_update_by_node(
+    Query(query): Query<NodeDockerQueryRequest>,
+    Path(id): Path<String>,
+    Json(req): Json<DockerContainerUpdateRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(query.node_name);
+    let options = update_options(&req)?;
+    info!("update container {}: {:?}", id, req);
+    docker_exec!(state.docker.update_container(&id, options).await)
+}
+
+pub async fn docker_container_pause_by_node(
+    Query(query): Query<NodeDockerQueryRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(query.node_name);
+    docker_exec!(state.docker.pause_container(&id).await)
+}
+
+pub async fn docker_container_unpause_by_node(
+    Query(query): Query<NodeDockerQueryRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(query.node_name);
+    docker_exec!(state.docker.unpause_container(&id).await)
+}
+
+pub async fn docker_container_kill_by_node(
+    Query(query): Query<DockerContainerKillRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(query.node_name);
+    let signal = query.signal.as_deref().unwrap_or("SIGKILL");
+    info!("kill container {} with {}", id, signal);
+    docker_exec!(
+        state
+            .docker
+            .kill_container(&id, Some(KillContainerOptions { signal }))
+            .await
+    )
+}
+
+pub async fn docker_container_top_by_node(
+    Query(query): Query<DockerContainerTopRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<ContainerTopResponse>>, ApiError> {
+    let state = get_state!(query.node_name);
+    let ps_args = query.ps_args.as_deref().unwrap_or("-ef");
+    docker_exec!(
+        state
+            .docker
+            .top_processes(&id, Some(TopOptions { ps_args }))
+            .await
+    )
+}
+
+/// files added, changed or deleted in the container since it was created
+pub async fn docker_container_diff_by_node(
+    Query(query): Query<NodeDockerQueryRequest>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiJsonResponse<Vec<FilesystemChange>>>, ApiError> {
+    let state = get_state!(query.node_name);
+    docker_exec!(state
+        .docker
+        .container_changes(&id)
+        .await
+        .map(|changes| changes.unwrap_or_default()))
+}
+
 pub async fn docker_container_list_by_node(
     Query(query): Query<NodeDockerQueryRequest>,
 ) -> Result<Json<ApiJsonResponse<Vec<ContainerSummary>>>, ApiError> {
@@ -173,115 +234,118 @@ pub async fn docker_volume_list_by_node(
     docker_exec!(state.docker.list_volumes(options).await)
 }
 
+/// pull the image on the node, copied from a node that has it for the same platform
+/// or pulled from the registry, the progress is streamed back line by line
 pub async fn docker_image_pull_auto(
     Json(req): Json<DockerImagePullAutoRequest>,
 ) -> Result<Response, ApiError> {
     info!("docker image pull: {}", &req.node_name);
+    let state = get_state!(req.node_name);
+    let nodes = node_manager().get_all_nodes(false).await?;
 
-    // first need check image exists
-    // if some docker server has the image, will use it
-    // if not, will pull from docker hub or registry server
-    let all = node_manager().get_all_nodes(false).await?;
-    let n = node_manager().get_node(&req.node_name).await?;
-    let src = select_has_docker_image_node(all, &req.image_name, &req.node_name).await;
-    if let Some(n) = n {
-        if let Some(src) = src {
-            let result =
-                docker_pull_image_from_other_server(&n.docker, &req.image_name, &src.docker)
-                    .await?;
-            let body = axum::body::Body::from_stream(result);
-            return Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header(axum::http::header::CONTENT_TYPE, "application/octet-stream")
-                .body(body)?);
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        let send = |line: String| {
+            let _ = tx.send(format!("{}\n", line));
+        };
+        let res = pull_image_auto(
+            &state,
+            &nodes,
+            &req.image_name,
+            req.transfer.as_deref(),
+            req.bandwidth_limit,
+            send,
+        )
+        .await;
+        if let Err(e) = res {
+            error!(
+                "pull image {} on node {} error: {:?}",
+                req.image_name, req.node_name, e
+            );
+            let _ = tx.send(format!("error: {}\n", e));
         }
-
-        let docker = Arc::clone(&n).docker.clone();
-        let result = docker_pull_image_from_hub(docker, req.image_name.clone()).await?;
-        let body = axum::body::Body::from_stream(result);
-        return Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(axum::http::header::CONTENT_TYPE, "application/octet-stream")
-            .body(body)?);
-    }
+    });
 
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .header(axum::http::header::CONTENT_TYPE, "application/octet-stream")
-        .body(axum::body::Body::empty())?)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
 }
 
-async fn select_has_docker_image_node(
-    nodes: Vec<Arc<NodeState>>,
-    image_name: &str,
-    expect_name: &str,
-) -> Option<Arc<NodeState>> {
-    for x in nodes.iter().filter(|x| x.node.name != expect_name) {
-        let list_options = ListImagesOptions {
-            all: true,
-            filters: HashMap::from([("reference", vec![image_name])]),
-            ..Default::default()
-        };
-        let images = x.docker.list_images(Some(list_options)).await;
-
-        if images.is_ok() && images.unwrap().len() > 0 {
-            info!("select node {} has image {}", x.node.name, image_name);
-            return Some(x.clone());
+fn update_options(
+    req: &DockerContainerUpdateRequest,
+) -> anyhow::Result<UpdateContainerOptions<String>> {
+    if req.cpus.is_none()
+        && req.memory.is_none()
+        && req.memory_swap.is_none()
+        && req.restart_policy.is_none()
+    {
+        return Err(anyhow::anyhow!("nothing to update"));
+    }
+    if req.cpus.is_some_and(|c| !c.is_finite() || c <= 0.0) {
+        return Err(anyhow::anyhow!("cpus must be greater than 0"));
+    }
+    if req.memory.is_some_and(|m| m < MIN_MEMORY) {
+        return Err(anyhow::anyhow!(
+            "memory must be at least {} bytes",
+            MIN_MEMORY
+        ));
+    }
+    if let (Some(memory), Some(swap)) = (req.memory, req.memory_swap) {
+        if swap != -1 && swap < memory {
+            return Err(anyhow::anyhow!(
+                "memory swap must be at least the memory limit"
+            ));
         }
     }
 
-    None
-}
-
-async fn docker_pull_image_from_hub<'a>(
-    docker: Docker,
-    image_name: String,
-) -> anyhow::Result<impl Stream<Item = Result<Vec<u8>, std::io::Error>> + 'a> {
-    info!("docker image pull");
-    let options = Some(CreateImageOptions {
-        from_image: image_name,
+    Ok(UpdateContainerOptions {
+        nano_cpus: req.cpus.map(|c| (c * 1e9) as i64),
+        memory: req.memory,
+        memory_swap: req.memory_swap,
+        restart_policy: req
+            .restart_policy
+            .as_deref()
+            .map(parse_restart_policy)
+            .transpose()?,
         ..Default::default()
-    });
+    })
+}
 
-    let res = docker
-        .create_image(options, None, None)
-        .map(|res| match res {
-            Ok(info) => Ok(info.progress.unwrap_or("".to_string()).into_bytes()),
-            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
-        });
+#[cfg(test)]
+mod tests {
+    use bollard::secret::RestartPolicyNameEnum;
 
-    Ok(res)
-}
+    use super::*;
 
-async fn docker_pull_image_from_other_server(
-    docker: &Docker,
-    image_name: &str,
-    src_docker: &Docker,
-) -> anyhow::Result<impl Stream<Item = Result<Vec<u8>, std::io::Error>>> {
-    // let export_docker_client = rekcod_connect(
-    //     Some(format!("http://{}:{}", "39.100.74.178", 6734)),
-    //     rekcod_core::constants::DOCKER_PROXY_PATH,
-    //     4,
-    // )?;
-    let stream = src_docker
-        .export_image(&image_name)
-        .filter_map(|item| async {
-            match item {
-                Ok(bytes) => Some(bytes),
-                Err(_) => None,
-            }
-        });
+    #[test]
+    fn test_update_options() {
+        assert!(update_options(&DockerContainerUpdateRequest::default()).is_err());
 
-    // import image
-    let options = ImportImageOptions {
-        ..Default::default()
-    };
-    let result = docker
-        .import_image_stream(options, stream, None)
-        .map(|res| match res {
-            Ok(info) => Ok(format!("{}\n", info.progress.unwrap_or("".to_string())).into_bytes()),
-            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
-        });
+        let req = DockerContainerUpdateRequest {
+            cpus: Some(0.5),
+            restart_policy: Some("on-failure:3".to_string()),
+            ..Default::default()
+        };
+        let options = update_options(&req).unwrap();
+        assert_eq!(options.nano_cpus, Some(500_000_000));
+        assert_eq!(options.memory, None);
+        let policy = options.restart_policy.unwrap();
+        assert_eq!(policy.name, Some(RestartPolicyNameEnum::ON_FAILURE));
+        assert_eq!(policy.maximum_retry_count, Some(3));
 
-    Ok(result)
+        let req = DockerContainerUpdateRequest {
+            memory: Some(512 * 1024 * 1024),
+            memory_swap: Some(256 * 1024 * 1024),
+            ..Default::default()
+        };
+        assert!(update_options(&req).is_err());
+        let req = DockerContainerUpdateRequest {
+            restart_policy: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+        assert!(update_options(&req).is_err());
+    }
 }
@@ -0,0 +1,80 @@
+use axum::Json;
+use rekcod_core::{
+    api::{
+        req::{NodeGcReportListRequest, NodeGcRequest},
+        resp::{ApiJsonResponse, GcReportResponse},
+    },
+    http::ApiError,
+};
+use tracing::info;
+
+use crate::db;
+
+pub async fn node_gc(
+    Json(req): Json<NodeGcRequest>,
+) -> Result<Json<ApiJsonResponse<GcReportResponse>>, ApiError> {
+    let node = get_state!(req.node_name);
+
+    let res = node.agent_post::<_, GcReportResponse>("/gc", &req).await?;
+    if res.code() != 0 {
+        return Err(anyhow::anyhow!("node {} gc error: {}", req.node_name, res.msg()).into());
+    }
+
+    let report = res
+        .data()
+        .cloned()
+        .ok_or(anyhow::anyhow!("node {} gc has no report", req.node_name))?;
+    if !report.dry_run {
+        save_gc_report(&report).await?;
+    }
+
+    Ok(ApiJsonResponse::success(report).into())
+}
+
+pub async fn report_gc(
+    Json(req): Json<GcReportResponse>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    info!(
+        "node {} gc({}) reclaimed {} bytes, {} items",
+        req.node_name,
+        req.trigger,
+        req.reclaimed,
+        req.items.len()
+    );
+    save_gc_report(&req).await?;
+
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn list_gc_report(
+    Json(req): Json<NodeGcReportListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<GcReportResponse>>>, ApiError> {
+    let db = db::repository().await;
+    let reports = db
+        .kvs
+        .select("gc", req.node_name.as_deref(), None, None)
+        .await?;
+
+    Ok(ApiJsonResponse::success(
+        reports
+            .iter()
+            .filter_map(|x| serde_json::from_str(&x.value).ok())
+            .collect::<Vec<_>>(),
+    )
+    .into())
+}
+
+/// keep the latest gc report of every node
+async fn save_gc_report(report: &GcReportResponse) -> anyhow::Result<()> {
+    let repositry = db::repository().await;
+    repositry
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: "gc".to_string(),
+            key: report.node_name.clone(),
+            value: serde_json::to_string(report)?,
+            ..Default::default()
+        })
+        .await?;
+    Ok(())
+}
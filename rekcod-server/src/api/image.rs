@@ -0,0 +1,480 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{body::Body, response::Response, Json};
+use bollard::{
+    image::{
+        CreateImageOptions, ImportImageOptions, PruneImagesOptions, PushImageOptions,
+        RemoveImageOptions, TagImageOptions,
+    },
+    secret::{HistoryResponseItem, ImageInspect, ImagePruneResponse},
+};
+use futures::StreamExt as _;
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::{
+            DockerImagePruneRequest, DockerImagePushRequest, DockerImageQueryRequest,
+            DockerImageRemoveRequest, DockerImageTagRequest,
+        },
+        resp::{ApiJsonResponse, ImageRemoveResult},
+    },
+    http::ApiError,
+};
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        image_transfer::transfer_image_layers,
+        registry::image_credentials,
+        registry_cache::spawn_seed,
+        util::{split_image, until_error},
+    },
+    node::manager::{Node, NodeState},
+};
+
+pub(crate) const DEFAULT_REGISTRY: &str = "docker.io";
+/// copy only the missing layers of an image from a peer node
+const TRANSFER_LAYER: &str = "layer";
+
+pub async fn docker_image_inspect_by_node(
+    Json(req): Json<DockerImageQueryRequest>,
+) -> Result<Json<ApiJsonResponse<ImageInspect>>, ApiError> {
+    let state = get_state!(req.node_name);
+    Ok(ApiJsonResponse::success(state.docker.inspect_image(&req.image).await?).into())
+}
+
+pub async fn docker_image_history_by_node(
+    Json(req): Json<DockerImageQueryRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<HistoryResponseItem>>>, ApiError> {
+    let state = get_state!(req.node_name);
+    Ok(ApiJsonResponse::success(state.docker.image_history(&req.image).await?).into())
+}
+
+pub async fn docker_image_remove_by_node(
+    Json(req): Json<DockerImageRemoveRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ImageRemoveResult>>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let mut results = Vec::with_capacity(req.images.len());
+    for image in req.images.iter() {
+        let options = Some(RemoveImageOptions {
+            force: req.force,
+            noprune: req.noprune,
+        });
+        let mut result = ImageRemoveResult {
+            image: image.clone(),
+            ..Default::default()
+        };
+        match state.docker.remove_image(image, options, None).await {
+            Ok(items) => {
+                for item in items {
+                    result.untagged.extend(item.untagged);
+                    result.deleted.extend(item.deleted);
+                }
+                info!("removed image {} on node {}", image, req.node_name);
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        results.push(result);
+    }
+
+    Ok(ApiJsonResponse::success(results).into())
+}
+
+pub async fn docker_image_tag_by_node(
+    Json(req): Json<DockerImageTagRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+    if req.repo.is_empty() {
+        return Err(anyhow::anyhow!("repo is required").into());
+    }
+
+    let options = Some(TagImageOptions {
+        repo: req.repo.as_str(),
+        tag: req.tag.as_deref().unwrap_or("latest"),
+    });
+    Ok(ApiJsonResponse::success(state.docker.tag_image(&req.image, options).await?).into())
+}
+
+pub async fn docker_image_push_by_node(
+    Json(req): Json<DockerImagePushRequest>,
+) -> Result<Response, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let (repo, tag) = split_image(&req.image);
+    let credentials = image_credentials(&req.image).await?;
+    info!("push image {} on node {}", req.image, req.node_name);
+
+    let stream = state
+        .docker
+        .push_image(&repo, Some(PushImageOptions { tag }), credentials)
+        .map(|res| match res {
+            Ok(info) => {
+                let line = match info.error {
+                    Some(error) => format!("error: {}", error),
+                    None => [info.status, info.progress]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                };
+                Ok(format!("{}\n", line).into_bytes())
+            }
+            Err(e) => Err(std::io::Error::other(e)),
+        });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from_stream(stream))?)
+}
+
+pub async fn docker_image_prune_by_node(
+    Json(req): Json<DockerImagePruneRequest>,
+) -> Result<Json<ApiJsonResponse<ImagePruneResponse>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let mut filters = HashMap::new();
+    filters.insert("dangling".to_string(), vec![(!req.all).to_string()]);
+    if let Some(until) = req.until {
+        filters.insert("until".to_string(), vec![until]);
+    }
+
+    let res = state
+        .docker
+        .prune_images(Some(PruneImagesOptions { filters }))
+        .await?;
+    info!(
+        "pruned images on node {}, reclaimed {} bytes",
+        req.node_name,
+        res.space_reclaimed.unwrap_or(0)
+    );
+    Ok(ApiJsonResponse::success(res).into())
+}
+
+/// registry host of a repository, docker hub if there is none
+pub(crate) fn registry_host(repo: &str) -> &str {
+    match repo.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => DEFAULT_REGISTRY,
+    }
+}
+
+/// load the image of the source node into the target node unless it already has the
+/// same image, return whether it was copied
+pub(crate) async fn copy_image(
+    source: &NodeState,
+    target: &NodeState,
+    image: &str,
+    mut on_progress: impl FnMut(String),
+) -> anyhow::Result<bool> {
+    let source_id = source.docker.inspect_image(image).await?.id;
+    let target_id = target
+        .docker
+        .inspect_image(image)
+        .await
+        .ok()
+        .and_then(|i| i.id);
+    if source_id.is_some() && source_id == target_id {
+        on_progress(format!(
+            "image {} exists on node {}",
+            image, target.node.name
+        ));
+        return Ok(false);
+    }
+
+    on_progress(format!(
+        "transfer image {} from node {} to {}",
+        image, source.node.name, target.node.name
+    ));
+    let mut stream = target.docker.import_image_stream(
+        ImportImageOptions::default(),
+        until_error(source.docker.export_image(image)),
+        None,
+    );
+    while let Some(info) = stream.next().await {
+        let info = info?;
+        if let Some(e) = info.error {
+            return Err(anyhow::anyhow!("load image {} error: {}", image, e));
+        }
+        let line = [info.stream, info.status, info.progress]
+            .into_iter()
+            .flatten()
+            .map(|x| x.trim_end().to_string())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !line.is_empty() {
+            on_progress(line);
+        }
+    }
+    info!(
+        "copied image {} from node {} to {}",
+        image, source.node.name, target.node.name
+    );
+    Ok(true)
+}
+
+/// the os, architecture and variant of images a node can run, as docker names them
+#[derive(Debug, PartialEq)]
+pub(crate) struct Platform {
+    pub os: String,
+    pub arch: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// from the os and cpu architecture the agent registered with
+    pub(crate) fn of_node(node: &Node) -> Self {
+        let os = if node.os.to_lowercase().contains("windows") {
+            "windows"
+        } else {
+            "linux"
+        };
+        let arch = node.arch.to_lowercase();
+        let (arch, variant) = match arch.as_str() {
+            "x86_64" | "amd64" => ("amd64", None),
+            "aarch64" | "arm64" => ("arm64", None),
+            "i386" | "i686" | "x86" => ("386", None),
+            "armhf" | "arm" => ("arm", Some("v7")),
+            a if a.starts_with("armv7") => ("arm", Some("v7")),
+            a if a.starts_with("armv6") => ("arm", Some("v6")),
+            a if a.starts_with("armv5") => ("arm", Some("v5")),
+            a => (a, None),
+        };
+        Self {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            variant: variant.map(|v| v.to_string()),
+        }
+    }
+
+    /// images without a variant run on every variant of the architecture, arm64 is v8
+    pub(crate) fn matches(&self, image: &ImageInspect) -> bool {
+        self.matches_platform(
+            image.os.as_deref().unwrap_or("linux"),
+            image.architecture.as_deref().unwrap_or_default(),
+            image.variant.as_deref(),
+        )
+    }
+
+    pub(crate) fn matches_platform(&self, os: &str, arch: &str, variant: Option<&str>) -> bool {
+        if os != self.os || arch != self.arch {
+            return false;
+        }
+        match (variant, self.variant.as_deref()) {
+            (Some(v), Some(expect)) => v == expect,
+            (Some(v), None) => self.arch != "arm64" || v == "v8",
+            (None, _) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.arch)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+/// the online node with the image for the platform of the target, a node with the
+/// image digest of the reference or a registry digest is preferred
+pub(crate) async fn select_image_peer(
+    nodes: &[Arc<NodeState>],
+    target: &NodeState,
+    image: &str,
+) -> Option<Arc<NodeState>> {
+    let platform = Platform::of_node(&target.node);
+    let mut peer: Option<(u8, Arc<NodeState>)> = None;
+    for node in nodes
+        .iter()
+        .filter(|n| n.node.name != target.node.name && n.online())
+    {
+        let Ok(inspect) = node.docker.inspect_image(image).await else {
+            continue;
+        };
+        if !platform.matches(&inspect) {
+            info!(
+                "skip node {}, image {} is {}/{} but node {} is {}",
+                node.node.name,
+                image,
+                inspect.os.as_deref().unwrap_or_default(),
+                inspect.architecture.as_deref().unwrap_or_default(),
+                target.node.name,
+                platform
+            );
+            continue;
+        }
+        let Some(rank) = peer_rank(&inspect, image) else {
+            continue;
+        };
+        if peer.as_ref().is_none_or(|(r, _)| rank < *r) {
+            peer = Some((rank, node.clone()));
+        }
+        if rank == 0 {
+            break;
+        }
+    }
+    peer.map(|(_, node)| {
+        info!("select node {} has image {}", node.node.name, image);
+        node
+    })
+}
+
+/// lower is better, none if a digest reference does not match
+fn peer_rank(inspect: &ImageInspect, image: &str) -> Option<u8> {
+    let digests = inspect.repo_digests.as_deref().unwrap_or_default();
+    match image.split_once('@') {
+        Some((_, digest)) => digests
+            .iter()
+            .any(|d| d.split_once('@').is_some_and(|(_, x)| x == digest))
+            .then_some(0),
+        None if digests.is_empty() => Some(1),
+        None => Some(0),
+    }
+}
+
+/// pull the image from its registry with the saved login
+pub(crate) async fn pull_image(
+    state: &NodeState,
+    image: &str,
+    mut on_progress: impl FnMut(String),
+) -> anyhow::Result<()> {
+    let (from_image, tag) = split_image(image);
+    let credentials = image_credentials(image).await?;
+    let options = Some(CreateImageOptions {
+        from_image,
+        tag,
+        ..Default::default()
+    });
+    let mut stream = state.docker.create_image(options, None, credentials);
+    while let Some(info) = stream.next().await {
+        let info = info?;
+        if let Some(e) = info.error {
+            return Err(anyhow::anyhow!("pull image {} error: {}", image, e));
+        }
+        let line = [info.id, info.status, info.progress]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !line.is_empty() {
+            on_progress(line);
+        }
+    }
+    info!("pulled image {} on node {}", image, state.node.name);
+    Ok(())
+}
+
+/// docker load keeps the tags and layers of the copied image, a failed layer transfer
+/// falls back to the whole image and a failed copy to the registry, return the node it
+/// was copied from
+pub(crate) async fn pull_image_auto(
+    state: &NodeState,
+    nodes: &[Arc<NodeState>],
+    image: &str,
+    transfer: Option<&str>,
+    bandwidth_limit: Option<u64>,
+    send: impl Fn(String) + Copy,
+) -> anyhow::Result<Option<String>> {
+    if let Some(peer) = select_image_peer(nodes, state, image).await {
+        if transfer.unwrap_or(TRANSFER_LAYER) == TRANSFER_LAYER {
+            match transfer_image_layers(&peer, state, image, bandwidth_limit, send).await {
+                Ok(_) => return Ok(Some(peer.node.name.clone())),
+                Err(e) => {
+                    warn!(
+                        "transfer image {} layers from node {} error: {:?}",
+                        image, peer.node.name, e
+                    );
+                    send(format!(
+                        "layer transfer from node {} failed: {}, copy the whole image",
+                        peer.node.name, e
+                    ));
+                }
+            }
+        }
+        match copy_image(&peer, state, image, send).await {
+            Ok(_) => return Ok(Some(peer.node.name.clone())),
+            Err(e) => {
+                warn!(
+                    "copy image {} from node {} error: {:?}",
+                    image, peer.node.name, e
+                );
+                send(format!(
+                    "copy from node {} failed: {}, pull from registry",
+                    peer.node.name, e
+                ));
+            }
+        }
+    } else {
+        send(format!(
+            "no node has image {} for {}, pull from registry",
+            image,
+            Platform::of_node(&state.node)
+        ));
+    }
+    pull_image(state, image, send).await?;
+    // the next node pulls it from the cache
+    spawn_seed(image);
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_host() {
+        assert_eq!(registry_host("nginx"), DEFAULT_REGISTRY);
+        assert_eq!(registry_host("library/nginx"), DEFAULT_REGISTRY);
+        assert_eq!(registry_host("registry.local/app"), "registry.local");
+        assert_eq!(registry_host("localhost:5000/app"), "localhost:5000");
+        assert_eq!(registry_host("localhost/app"), "localhost");
+    }
+
+    #[test]
+    fn test_platform() {
+        let node = |arch: &str| Node {
+            arch: arch.to_string(),
+            os: "Ubuntu".to_string(),
+            ..Default::default()
+        };
+        let image = |arch: &str, variant: Option<&str>| ImageInspect {
+            os: Some("linux".to_string()),
+            architecture: Some(arch.to_string()),
+            variant: variant.map(|v| v.to_string()),
+            ..Default::default()
+        };
+
+        let amd64 = Platform::of_node(&node("x86_64"));
+        assert_eq!(amd64.to_string(), "linux/amd64");
+        assert!(amd64.matches(&image("amd64", None)));
+        assert!(!amd64.matches(&image("arm64", None)));
+
+        let arm64 = Platform::of_node(&node("aarch64"));
+        assert!(arm64.matches(&image("arm64", Some("v8"))));
+        assert!(!arm64.matches(&image("amd64", None)));
+
+        let armv7 = Platform::of_node(&node("armv7l"));
+        assert_eq!(armv7.to_string(), "linux/arm/v7");
+        assert!(armv7.matches(&image("arm", Some("v7"))));
+        assert!(!armv7.matches(&image("arm", Some("v6"))));
+    }
+
+    #[test]
+    fn test_peer_rank() {
+        let digest = "sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac";
+        let pulled = ImageInspect {
+            repo_digests: Some(vec![format!("nginx@{}", digest)]),
+            ..Default::default()
+        };
+        let built = ImageInspect::default();
+        assert_eq!(peer_rank(&pulled, "nginx:1.27"), Some(0));
+        assert_eq!(peer_rank(&built, "nginx:1.27"), Some(1));
+        assert_eq!(peer_rank(&pulled, &format!("nginx@{}", digest)), Some(0));
+        assert_eq!(peer_rank(&built, &format!("nginx@{}", digest)), None);
+    }
+}
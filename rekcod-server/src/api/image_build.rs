@@ -0,0 +1,449 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{body::Body, extract::Query, response::Response, Json};
+use base64::{engine::general_purpose::URL_SAFE, Engine as _};
+use bollard::{
+    image::{BuildImageOptions, TagImageOptions},
+    secret::BuildInfo,
+};
+use futures::{StreamExt as _, TryStreamExt as _};
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::{DockerImageBuildRequest, ImageBuildListRequest, ImageBuildLogRequest},
+        resp::{display_bytes, ApiJsonResponse, ImageBuildEvent, ImageBuildResponse},
+    },
+    client::get_client,
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _},
+    sync::mpsc::{self, UnboundedSender},
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{image::copy_image, registry::all_registry_credentials, util::split_image},
+    config::rekcod_server_config,
+    db,
+    node::manager::NodeState,
+};
+
+const BUILD_MODULE: &str = "image_build";
+const BUILD_DIR: &str = "build";
+/// the uploaded context is saved under the build dir until the build ends
+const MAX_CONTEXT_SIZE: u64 = 1024 * 1024 * 1024;
+const REGISTRY_CONFIG_HEADER: &str = "X-Registry-Config";
+const DEFAULT_DOCKERFILE: &str = "Dockerfile";
+
+const STATUS_RUNNING: &str = "running";
+const STATUS_SUCCESS: &str = "success";
+const STATUS_FAILED: &str = "failed";
+
+/// the events of a build, sent to the client while it is connected and always written
+/// to the build log
+struct BuildLog {
+    tx: UnboundedSender<String>,
+    file: Option<File>,
+}
+
+impl BuildLog {
+    async fn emit(&mut self, event: ImageBuildEvent) {
+        let Ok(line) = serde_json::to_string(&event) else {
+            return;
+        };
+        let line = format!("{}\n", line);
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                warn!("write build log error: {}", e);
+                self.file = None;
+            }
+        }
+        let _ = self.tx.send(line);
+    }
+}
+
+/// build an image from the tar context in the body, the output is streamed as ndjson
+pub async fn docker_image_build_by_node(
+    Query(req): Query<DockerImageBuildRequest>,
+    body: Body,
+) -> Result<Response, ApiError> {
+    let (tags, options) = match build_options(&req) {
+        Ok(x) => x,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("invalid build request: {}\n", e)))?);
+        }
+    };
+    let state = get_state!(req.node_name);
+    let mut targets = Vec::new();
+    for name in split_list(req.distribute.as_deref()) {
+        if name != req.node_name {
+            targets.push(get_state!(name));
+        }
+    }
+
+    let mut build = ImageBuildResponse {
+        id: Uuid::new_v4().to_string(),
+        node_name: req.node_name.clone(),
+        tags,
+        dockerfile: options.dockerfile.clone(),
+        target: req.target.clone().filter(|x| !x.is_empty()),
+        status: STATUS_RUNNING.to_string(),
+        created_at: unix_timestamp(),
+        ..Default::default()
+    };
+    let path = log_path(&build.id);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let context = context_path(&build.id);
+    let size = match save_context(body, &context).await {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&context).await;
+            return Err(e.into());
+        }
+    };
+    save_build(&build).await?;
+    info!(
+        "build image {} on node {}, context {} bytes",
+        build.tags.join(","),
+        build.node_name,
+        size
+    );
+
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    let mut log = BuildLog {
+        tx,
+        file: File::create(&path).await.ok(),
+    };
+    tokio::spawn(async move {
+        let res = build_image(&state, options, &context, &mut build, &mut log).await;
+        if let Err(e) = tokio::fs::remove_file(&context).await {
+            warn!("remove build context {:?} error: {}", context, e);
+        }
+        let res = match res {
+            Ok(_) => distribute(&state, &targets, &mut build, &mut log).await,
+            Err(e) => Err(e),
+        };
+
+        let mut done = ImageBuildEvent {
+            step: "done".to_string(),
+            node_name: build.node_name.clone(),
+            image_id: build.image_id.clone(),
+            ..Default::default()
+        };
+        match res {
+            Ok(_) => {
+                build.status = STATUS_SUCCESS.to_string();
+                done.message = format!("built {}", build.tags.join(", "));
+            }
+            Err(e) => {
+                error!("build image on node {} error: {:?}", build.node_name, e);
+                build.status = STATUS_FAILED.to_string();
+                build.error = Some(e.to_string());
+                done.error = Some(e.to_string());
+            }
+        }
+        build.finished_at = Some(unix_timestamp());
+        log.emit(done).await;
+        if let Err(e) = save_build(&build).await {
+            error!("save build {} error: {:?}", build.id, e);
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
+}
+
+pub async fn list_image_build(
+    Json(req): Json<ImageBuildListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ImageBuildResponse>>>, ApiError> {
+    let mut builds = db::repository()
+        .await
+        .kvs
+        .select(BUILD_MODULE, req.node_name.as_deref(), None, None)
+        .await?
+        .iter()
+        .filter_map(|x| serde_json::from_str::<ImageBuildResponse>(&x.value).ok())
+        .collect::<Vec<_>>();
+    builds.sort_by_key(|x| std::cmp::Reverse(x.created_at));
+    Ok(ApiJsonResponse::success(builds).into())
+}
+
+/// the ndjson events of a build, as streamed while it ran
+pub async fn image_build_log(Json(req): Json<ImageBuildLogRequest>) -> Result<Response, ApiError> {
+    let build = db::repository()
+        .await
+        .kvs
+        .select_one(BUILD_MODULE, None, None, Some(&req.id))
+        .await?;
+    if build.is_none() {
+        return Err(anyhow::anyhow!("build {} not found", req.id).into());
+    }
+
+    let file = File::open(log_path(&req.id)).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::from_stream(ReaderStream::new(file)))?)
+}
+
+/// stream the uploaded context to a file, it is never held in memory
+async fn save_context(body: Body, path: &Path) -> anyhow::Result<u64> {
+    let mut file = File::create(path).await?;
+    let mut stream = body.into_data_stream();
+    let mut size = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        if size > MAX_CONTEXT_SIZE {
+            return Err(anyhow::anyhow!(
+                "build context is larger than {}",
+                display_bytes(&MAX_CONTEXT_SIZE)
+            ));
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(size)
+}
+
+/// bollard takes the context in one piece, so the file is streamed to the docker api
+/// of the node directly
+async fn build_image(
+    state: &NodeState,
+    options: BuildImageOptions<String>,
+    context: &Path,
+    build: &mut ImageBuildResponse,
+    log: &mut BuildLog,
+) -> anyhow::Result<()> {
+    let credentials = serde_json::to_string(&all_registry_credentials().await?)?;
+    let file = File::open(context).await?;
+    let res = get_client()?
+        .post(format!("{}/build", state.get_docker_proxy()))
+        .query(&options)
+        .header(header::CONTENT_TYPE, "application/x-tar")
+        .header(REGISTRY_CONFIG_HEADER, URL_SAFE.encode(credentials))
+        .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+        .send()
+        .await?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!(
+            "build error: {} {}",
+            status,
+            res.text().await?.trim()
+        ));
+    }
+
+    let mut lines = StreamReader::new(res.bytes_stream().map_err(std::io::Error::other)).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let info = serde_json::from_str::<BuildInfo>(&line)?;
+        if let Some(e) = info.error {
+            return Err(anyhow::anyhow!("{}", e.trim_end()));
+        }
+        if let Some(id) = info.aux.and_then(|aux| aux.id) {
+            build.image_id = Some(id);
+        }
+        let message = [info.id, info.status, info.progress, info.stream]
+            .into_iter()
+            .flatten()
+            .map(|x| x.trim_end().to_string())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if message.is_empty() && build.image_id.is_none() {
+            continue;
+        }
+        log.emit(ImageBuildEvent {
+            step: "build".to_string(),
+            node_name: build.node_name.clone(),
+            message,
+            image_id: build.image_id.clone(),
+            ..Default::default()
+        })
+        .await;
+    }
+
+    for tag in build.tags.iter().skip(1) {
+        tag_image(state, &build.tags[0], tag).await?;
+    }
+    Ok(())
+}
+
+/// copy the image with its first tag to the nodes, the other tags are set there after it
+async fn distribute(
+    state: &NodeState,
+    targets: &[Arc<NodeState>],
+    build: &mut ImageBuildResponse,
+    log: &mut BuildLog,
+) -> anyhow::Result<()> {
+    let image = build.tags[0].clone();
+    for target in targets {
+        let mut lines = Vec::new();
+        let res = copy_image(state, target, &image, |line| lines.push(line)).await;
+        for message in lines {
+            log.emit(ImageBuildEvent {
+                step: "distribute".to_string(),
+                node_name: target.node.name.clone(),
+                message,
+                ..Default::default()
+            })
+            .await;
+        }
+        res?;
+        for tag in build.tags.iter().skip(1) {
+            tag_image(target, &image, tag).await?;
+        }
+        build.distributed.push(target.node.name.clone());
+    }
+    Ok(())
+}
+
+async fn tag_image(state: &NodeState, image: &str, tag: &str) -> anyhow::Result<()> {
+    let (repo, tag) = split_image(tag);
+    state
+        .docker
+        .tag_image(image, Some(TagImageOptions { repo, tag }))
+        .await?;
+    Ok(())
+}
+
+fn build_options(
+    req: &DockerImageBuildRequest,
+) -> anyhow::Result<(Vec<String>, BuildImageOptions<String>)> {
+    let tags = split_list(Some(&req.tags))
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    if tags.is_empty() {
+        return Err(anyhow::anyhow!("at least one tag is required"));
+    }
+    let buildargs = match req.build_args.as_deref().filter(|x| !x.is_empty()) {
+        Some(args) => serde_json::from_str::<HashMap<String, String>>(args)
+            .map_err(|e| anyhow::anyhow!("build args must be a json object of strings: {}", e))?,
+        None => HashMap::new(),
+    };
+    let dockerfile = req
+        .dockerfile
+        .clone()
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| DEFAULT_DOCKERFILE.to_string());
+    if Path::new(&dockerfile).is_absolute() {
+        return Err(anyhow::anyhow!("dockerfile must be a path in the context"));
+    }
+
+    let options = BuildImageOptions {
+        dockerfile,
+        t: tags[0].clone(),
+        buildargs,
+        target: req.target.clone().unwrap_or_default(),
+        nocache: req.nocache,
+        pull: req.pull,
+        rm: true,
+        forcerm: true,
+        ..Default::default()
+    };
+    Ok((tags, options))
+}
+
+fn split_list(value: Option<&str>) -> impl Iterator<Item = &str> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+}
+
+fn context_path(id: &str) -> PathBuf {
+    Path::new(&rekcod_server_config().data_path)
+        .join(BUILD_DIR)
+        .join(format!("{}.tar", id))
+}
+
+fn log_path(id: &str) -> PathBuf {
+    Path::new(&rekcod_server_config().data_path)
+        .join(BUILD_DIR)
+        .join(format!("{}.log", id))
+}
+
+async fn save_build(build: &ImageBuildResponse) -> anyhow::Result<()> {
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: BUILD_MODULE.to_string(),
+            key: build.node_name.clone(),
+            third_key: build.id.clone(),
+            value: serde_json::to_string(build)?,
+            ..Default::default()
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_options() {
+        let mut req = DockerImageBuildRequest {
+            tags: "app:1, app:latest,".to_string(),
+            build_args: Some(r#"{"VERSION":"1.2"}"#.to_string()),
+            target: Some("release".to_string()),
+            ..Default::default()
+        };
+        let (tags, options) = build_options(&req).unwrap();
+        assert_eq!(tags, vec!["app:1", "app:latest"]);
+        assert_eq!(options.t, "app:1");
+        assert_eq!(options.dockerfile, DEFAULT_DOCKERFILE);
+        assert_eq!(options.buildargs["VERSION"], "1.2");
+        assert_eq!(options.target, "release");
+
+        req.build_args = Some(r#"{"VERSION":1}"#.to_string());
+        assert!(build_options(&req).is_err());
+        req.build_args = None;
+        req.dockerfile = Some("/etc/Dockerfile".to_string());
+        assert!(build_options(&req).is_err());
+        req.dockerfile = None;
+        req.tags = " , ".to_string();
+        assert!(build_options(&req).is_err());
+    }
+
+    #[test]
+    fn test_build_query() {
+        let req = DockerImageBuildRequest {
+            tags: "app:1".to_string(),
+            build_args: Some(r#"{"VERSION":"1.2"}"#.to_string()),
+            ..Default::default()
+        };
+        let (_, options) = build_options(&req).unwrap();
+        let request = reqwest::Client::new()
+            .post("http://localhost/build")
+            .query(&options)
+            .build()
+            .unwrap();
+        let query = request.url().query().unwrap();
+        assert!(query.contains("t=app%3A1"));
+        assert!(query.contains("buildargs=%7B%22VERSION%22%3A%221.2%22%7D"));
+        assert!(query.contains("version=1"));
+    }
+}
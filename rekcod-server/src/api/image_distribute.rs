@@ -0,0 +1,219 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{body::Body, response::Response, Json};
+use futures::StreamExt as _;
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::ImageDistributeRequest,
+        resp::{ClusterNodeError, ImageDistributeEvent},
+    },
+    http::ApiError,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        cluster::{parse_selector, query_nodes, select_nodes, DEFAULT_NODE_TIMEOUT},
+        image::{pull_image_auto, Platform},
+    },
+    node::manager::{node_manager, NodeState},
+};
+
+const DEFAULT_CONCURRENCY: usize = 3;
+const MAX_CONCURRENCY: usize = 32;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_PROGRESS: &str = "progress";
+const STATUS_EXISTS: &str = "exists";
+const STATUS_DONE: &str = "done";
+const STATUS_FAILED: &str = "failed";
+const STATUS_FINISHED: &str = "finished";
+
+/// copy the image to every selected node that does not have it, a node that got it is
+/// a peer for the next ones, the progress is streamed as ndjson
+pub async fn cluster_image_distribute(
+    Json(req): Json<ImageDistributeRequest>,
+) -> Result<Response, ApiError> {
+    let selector = match parse_selector(req.selector.as_deref()) {
+        Ok(selector) if !req.image.trim().is_empty() => selector,
+        Ok(_) => return bad_request("image is required"),
+        Err(e) => return bad_request(&e.to_string()),
+    };
+    let all = node_manager().get_all_nodes(true).await?;
+    let (targets, errors) = select_nodes(&all, &req.node_names, &selector);
+    if targets.is_empty() && errors.is_empty() {
+        return bad_request("no online node is selected");
+    }
+    info!("distribute image {} to {} nodes", req.image, targets.len());
+
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        distribute(all, targets, errors, &req, &tx).await;
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::from_stream(
+            UnboundedReceiverStream::new(rx).map(anyhow::Ok),
+        ))?)
+}
+
+async fn distribute(
+    all: Vec<Arc<NodeState>>,
+    targets: Vec<Arc<NodeState>>,
+    errors: Vec<ClusterNodeError>,
+    req: &ImageDistributeRequest,
+    tx: &UnboundedSender<String>,
+) {
+    let emit = |node_name: &str, status: &str, message: String| {
+        let event = ImageDistributeEvent {
+            node_name: node_name.to_string(),
+            status: status.to_string(),
+            message,
+            ..Default::default()
+        };
+        send_event(tx, &event);
+    };
+    let mut failed = errors.len();
+    for e in errors {
+        send_event(
+            tx,
+            &ImageDistributeEvent {
+                node_name: e.node_name,
+                status: STATUS_FAILED.to_string(),
+                error: Some(e.error),
+                ..Default::default()
+            },
+        );
+    }
+
+    let online = all.iter().filter(|n| n.online()).cloned().collect();
+    let timeout = Duration::from_secs(DEFAULT_NODE_TIMEOUT);
+    let image = req.image.as_str();
+    let inspects = query_nodes(online, timeout, |node| async move {
+        let inspect = node.docker.inspect_image(image).await?;
+        Ok((node, inspect))
+    })
+    .await
+    .into_iter()
+    .filter_map(|(_, res)| res.ok())
+    .collect::<Vec<_>>();
+    let holders = inspects
+        .iter()
+        .map(|(node, _)| node.clone())
+        .collect::<Vec<_>>();
+
+    let mut pending = Vec::new();
+    for target in targets {
+        // a tag of another platform is replaced by the pull
+        let platform = Platform::of_node(&target.node);
+        if inspects
+            .iter()
+            .any(|(h, inspect)| h.node.name == target.node.name && platform.matches(inspect))
+        {
+            emit(&target.node.name, STATUS_EXISTS, "image exists".to_string());
+        } else {
+            emit(&target.node.name, STATUS_PENDING, String::new());
+            pending.push(target);
+        }
+    }
+    let exists = holders.len();
+
+    let holders = Mutex::new(holders);
+    let concurrency = req
+        .concurrency
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, MAX_CONCURRENCY);
+    let results = futures::stream::iter(pending)
+        .map(|target| {
+            let holders = &holders;
+            async move {
+                // the nodes that got the image last are not copying it yet
+                let mut peers = holders.lock().unwrap().clone();
+                peers.reverse();
+                let name = target.node.name.as_str();
+                let send = |message: String| emit(name, STATUS_PROGRESS, message);
+                let res = pull_image_auto(
+                    &target,
+                    &peers,
+                    &req.image,
+                    req.transfer.as_deref(),
+                    req.bandwidth_limit,
+                    send,
+                )
+                .await;
+                match res {
+                    Ok(source) => {
+                        holders.lock().unwrap().push(target.clone());
+                        send_event(
+                            tx,
+                            &ImageDistributeEvent {
+                                node_name: name.to_string(),
+                                status: STATUS_DONE.to_string(),
+                                message: match &source {
+                                    Some(source) => format!("copied from node {}", source),
+                                    None => "pulled from registry".to_string(),
+                                },
+                                source,
+                                ..Default::default()
+                            },
+                        );
+                        true
+                    }
+                    Err(e) => {
+                        warn!("distribute image {} to {} error: {:?}", req.image, name, e);
+                        send_event(
+                            tx,
+                            &ImageDistributeEvent {
+                                node_name: name.to_string(),
+                                status: STATUS_FAILED.to_string(),
+                                error: Some(e.to_string()),
+                                ..Default::default()
+                            },
+                        );
+                        false
+                    }
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let done = results.iter().filter(|x| **x).count();
+    failed += results.len() - done;
+    info!(
+        "distributed image {}, {} done, {} failed",
+        req.image, done, failed
+    );
+    emit(
+        "",
+        STATUS_FINISHED,
+        format!(
+            "{} done, {} failed, {} nodes had the image",
+            done, failed, exists
+        ),
+    );
+}
+
+fn send_event(tx: &UnboundedSender<String>, event: &ImageDistributeEvent) {
+    if let Ok(line) = serde_json::to_string(event) {
+        let _ = tx.send(format!("{}\n", line));
+    }
+}
+
+fn bad_request(message: &str) -> Result<Response, ApiError> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(format!(
+            "invalid distribute request: {}\n",
+            message
+        )))?)
+}
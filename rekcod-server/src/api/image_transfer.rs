@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use futures::StreamExt as _;
+use rekcod_core::{
+    api::{
+        req::{ImageFetchRequest, ImageLayersRequest},
+        resp::{display_bytes, ImageLayersResponse, ImageTransferFile},
+    },
+    client::get_client,
+};
+use tracing::info;
+
+use crate::node::manager::NodeState;
+
+/// copy only the layers the target node does not have, the agent of the target node
+/// fetches them from the agent of the source node and loads the image, return whether
+/// it was copied
+pub(crate) async fn transfer_image_layers(
+    source: &NodeState,
+    target: &NodeState,
+    image: &str,
+    bandwidth_limit: Option<u64>,
+    mut on_progress: impl FnMut(String),
+) -> anyhow::Result<bool> {
+    let source_id = source.docker.inspect_image(image).await?.id;
+    let target_id = target
+        .docker
+        .inspect_image(image)
+        .await
+        .ok()
+        .and_then(|i| i.id);
+    if source_id.is_some() && source_id == target_id {
+        on_progress(format!(
+            "image {} exists on node {}",
+            image, target.node.name
+        ));
+        return Ok(false);
+    }
+
+    on_progress(format!("save image {} on node {}", image, source.node.name));
+    let req = ImageLayersRequest {
+        image: image.to_string(),
+    };
+    let res = source
+        .agent_post::<_, ImageLayersResponse>("/image/layers", &req)
+        .await?;
+    if res.code() != 0 {
+        return Err(anyhow::anyhow!(
+            "save image on node {} error: {}",
+            source.node.name,
+            res.msg()
+        ));
+    }
+    let saved = res
+        .data()
+        .cloned()
+        .ok_or(anyhow::anyhow!("node {} saved no image", source.node.name))?;
+
+    let res = target
+        .agent_post::<_, Vec<String>>("/image/chains", &())
+        .await?;
+    if res.code() != 0 {
+        return Err(anyhow::anyhow!(
+            "list layers on node {} error: {}",
+            target.node.name,
+            res.msg()
+        ));
+    }
+    let chains = res.data().cloned().unwrap_or_default();
+
+    let (files, skipped) = missing_files(&saved, &chains.into_iter().collect());
+    on_progress(format!(
+        "transfer {} of {} layers ({}) from node {} to {}, {} layers exist",
+        saved.layers.len() - skipped,
+        saved.layers.len(),
+        display_bytes(&files.iter().map(|f| f.size).sum()),
+        source.node.name,
+        target.node.name,
+        skipped
+    ));
+
+    let res = get_client()?
+        .post(format!("{}/image/fetch", target.get_node_agent()))
+        .json(&ImageFetchRequest {
+            id: saved.id.clone(),
+            source: source.get_node_agent(),
+            root: saved.root.clone(),
+            files,
+            bandwidth_limit,
+        })
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // the agent streams its progress, a failure is the last line
+    let mut stream = res.bytes_stream();
+    let mut buf = String::new();
+    while let Some(chunk) = stream.next().await {
+        buf.push_str(&String::from_utf8_lossy(&chunk?));
+        while let Some(i) = buf.find('\n') {
+            let line = buf[..i].to_string();
+            buf.drain(..=i);
+            if let Some(e) = line.strip_prefix("error: ") {
+                return Err(anyhow::anyhow!("{}", e));
+            }
+            on_progress(line);
+        }
+    }
+    if target.docker.inspect_image(&saved.id).await.is_err() {
+        return Err(anyhow::anyhow!(
+            "image {} was not loaded on node {}",
+            image,
+            target.node.name
+        ));
+    }
+
+    info!(
+        "transferred image {} from node {} to {}, {} layers exist",
+        image, source.node.name, target.node.name, skipped
+    );
+    Ok(true)
+}
+
+/// the files of the saved image without the layers the target has, a layer is on the
+/// target if one of its images has the same chain id, return the number of layers skipped
+fn missing_files(
+    saved: &ImageLayersResponse,
+    chains: &HashSet<String>,
+) -> (Vec<ImageTransferFile>, usize) {
+    let layers = saved
+        .layers
+        .iter()
+        .map(|l| l.path.as_str())
+        .collect::<HashSet<_>>();
+    // a layer file may be shared by a layer the target does not have
+    let needed = saved
+        .layers
+        .iter()
+        .filter(|l| !chains.contains(&l.chain_id))
+        .map(|l| l.path.as_str())
+        .collect::<HashSet<_>>();
+
+    let files = saved
+        .files
+        .iter()
+        .filter(|f| !layers.contains(f.path.as_str()) || needed.contains(f.path.as_str()))
+        .cloned()
+        .collect();
+    let skipped = saved
+        .layers
+        .iter()
+        .filter(|l| chains.contains(&l.chain_id))
+        .count();
+    (files, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use rekcod_core::api::resp::ImageTransferLayer;
+
+    use super::*;
+
+    #[test]
+    fn test_missing_files() {
+        let file = |path: &str| ImageTransferFile {
+            path: path.to_string(),
+            size: 10,
+            digest: format!("sha256:{}", path),
+        };
+        let layer = |path: &str, chain_id: &str| ImageTransferLayer {
+            path: path.to_string(),
+            chain_id: chain_id.to_string(),
+        };
+        let saved = ImageLayersResponse {
+            files: vec![
+                file("manifest.json"),
+                file("blobs/sha256/config"),
+                file("blobs/sha256/l1"),
+                file("blobs/sha256/l2"),
+                file("blobs/sha256/l3"),
+            ],
+            layers: vec![
+                layer("blobs/sha256/l1", "c1"),
+                layer("blobs/sha256/l2", "c2"),
+                layer("blobs/sha256/l3", "c3"),
+            ],
+            ..Default::default()
+        };
+
+        let chains = HashSet::from(["c1".to_string(), "c2".to_string()]);
+        let (files, skipped) = missing_files(&saved, &chains);
+        assert_eq!(skipped, 2);
+        assert_eq!(
+            files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+            vec!["manifest.json", "blobs/sha256/config", "blobs/sha256/l3"]
+        );
+
+        let (files, skipped) = missing_files(&saved, &HashSet::new());
+        assert_eq!(skipped, 0);
+        assert_eq!(files.len(), 5);
+    }
+}
@@ -1,6 +1,38 @@
+/// get the node state by name, return a not found error if it does not exist
+macro_rules! get_state {
+    ($name:expr) => {
+        crate::node::manager::node_manager()
+            .get_node(&$name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("node {} not found", &$name))?
+    };
+}
+
 pub(crate) mod application;
+pub(crate) mod cluster;
+pub(crate) mod container;
+pub(crate) mod container_archive;
+pub(crate) mod container_exec;
+pub(crate) mod container_log;
+pub(crate) mod container_migrate;
 pub(crate) mod docker;
 pub(crate) mod env;
+pub(crate) mod gc;
+pub(crate) mod image;
+pub(crate) mod image_build;
+pub(crate) mod image_distribute;
+pub(crate) mod image_transfer;
+pub(crate) mod network;
 pub(crate) mod node;
 pub(crate) mod node_proxy;
+pub(crate) mod overview;
+pub(crate) mod process;
+pub(crate) mod registry;
+pub(crate) mod registry_cache;
+pub(crate) mod restart;
+pub(crate) mod search;
 pub mod socketio;
+pub(crate) mod system;
+pub(crate) mod util;
+pub(crate) mod volume;
+pub(crate) mod volume_backup;
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use axum::Json;
+use bollard::{
+    network::{
+        ConnectNetworkOptions, CreateNetworkOptions, DisconnectNetworkOptions,
+        InspectNetworkOptions, ListNetworksOptions, PruneNetworksOptions,
+    },
+    secret::{
+        ContainerSummary, EndpointIpamConfig, EndpointSettings, Ipam, IpamConfig, Network,
+        NetworkCreateResponse, NetworkPruneResponse,
+    },
+};
+use rekcod_core::{
+    api::{
+        req::{
+            DockerNetworkContainerRequest, DockerNetworkCreateRequest, DockerNetworkQueryRequest,
+            DockerPruneRequest, NodeDockerQueryRequest,
+        },
+        resp::{ApiJsonResponse, NetworkDetailResponse, ResourceContainer},
+    },
+    http::ApiError,
+};
+use tracing::info;
+
+use crate::api::util::{container_names, list_all_containers, resource_container};
+
+pub async fn docker_network_create_by_node(
+    Json(req): Json<DockerNetworkCreateRequest>,
+) -> Result<Json<ApiJsonResponse<NetworkCreateResponse>>, ApiError> {
+    validate_network(&req)?;
+    let state = get_state!(req.node_name);
+
+    let ipam = match &req.subnet {
+        Some(subnet) => Ipam {
+            config: Some(vec![IpamConfig {
+                subnet: Some(subnet.clone()),
+                gateway: req.gateway.clone(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        },
+        None => Ipam::default(),
+    };
+    let options = CreateNetworkOptions {
+        name: req.name.clone(),
+        check_duplicate: true,
+        driver: req.driver.clone().unwrap_or("bridge".to_string()),
+        internal: req.internal,
+        attachable: req.attachable,
+        ipam,
+        labels: req.labels.clone(),
+        ..Default::default()
+    };
+
+    let res = state.docker.create_network(options).await?;
+    info!("created network {} on node {}", req.name, req.node_name);
+    Ok(ApiJsonResponse::success(res).into())
+}
+
+pub async fn docker_network_inspect_by_node(
+    Json(req): Json<DockerNetworkQueryRequest>,
+) -> Result<Json<ApiJsonResponse<NetworkDetailResponse>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let network = state
+        .docker
+        .inspect_network(&req.network, None::<InspectNetworkOptions<String>>)
+        .await?;
+    let containers = list_all_containers(&state.docker).await?;
+    Ok(ApiJsonResponse::success(network_detail(network, &containers)).into())
+}
+
+/// every network of the node with the containers using it
+pub async fn docker_network_usage_by_node(
+    Json(req): Json<NodeDockerQueryRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<NetworkDetailResponse>>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let networks = state
+        .docker
+        .list_networks(None::<ListNetworksOptions<String>>)
+        .await?;
+    let containers = list_all_containers(&state.docker).await?;
+    Ok(ApiJsonResponse::success(
+        networks
+            .into_iter()
+            .map(|n| network_detail(n, &containers))
+            .collect::<Vec<_>>(),
+    )
+    .into())
+}
+
+pub async fn docker_network_connect_by_node(
+    Json(req): Json<DockerNetworkContainerRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let endpoint_config = EndpointSettings {
+        ipam_config: req.ipv4_address.clone().map(|ip| EndpointIpamConfig {
+            ipv4_address: Some(ip),
+            ..Default::default()
+        }),
+        aliases: Some(req.aliases.clone()).filter(|a| !a.is_empty()),
+        ..Default::default()
+    };
+    state
+        .docker
+        .connect_network(
+            &req.network,
+            ConnectNetworkOptions {
+                container: req.container.clone(),
+                endpoint_config,
+            },
+        )
+        .await?;
+    info!(
+        "connected container {} to network {} on node {}",
+        req.container, req.network, req.node_name
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn docker_network_disconnect_by_node(
+    Json(req): Json<DockerNetworkContainerRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    state
+        .docker
+        .disconnect_network(
+            &req.network,
+            DisconnectNetworkOptions {
+                container: req.container.clone(),
+                force: req.force,
+            },
+        )
+        .await?;
+    info!(
+        "disconnected container {} from network {} on node {}",
+        req.container, req.network, req.node_name
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+/// refuse to remove a network in use unless force, which disconnects the containers first
+pub async fn docker_network_remove_by_node(
+    Json(req): Json<DockerNetworkQueryRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let network = state
+        .docker
+        .inspect_network(&req.network, None::<InspectNetworkOptions<String>>)
+        .await?;
+    let containers = list_all_containers(&state.docker).await?;
+    let users = network_containers(&network, &containers);
+    if !users.is_empty() && !req.force {
+        return Err(anyhow::anyhow!(
+            "network {} is used by containers: {}",
+            req.network,
+            container_names(&users)
+        )
+        .into());
+    }
+
+    for container in users.iter() {
+        state
+            .docker
+            .disconnect_network(
+                &req.network,
+                DisconnectNetworkOptions {
+                    container: container.id.clone(),
+                    force: true,
+                },
+            )
+            .await?;
+    }
+    state.docker.remove_network(&req.network).await?;
+    info!("removed network {} on node {}", req.network, req.node_name);
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn docker_network_prune_by_node(
+    Json(req): Json<DockerPruneRequest>,
+) -> Result<Json<ApiJsonResponse<NetworkPruneResponse>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let mut filters = HashMap::new();
+    if let Some(until) = req.until {
+        filters.insert("until".to_string(), vec![until]);
+    }
+    let res = state
+        .docker
+        .prune_networks(Some(PruneNetworksOptions { filters }))
+        .await?;
+    info!(
+        "pruned networks on node {}: {:?}",
+        req.node_name, res.networks_deleted
+    );
+    Ok(ApiJsonResponse::success(res).into())
+}
+
+fn network_detail(network: Network, containers: &[ContainerSummary]) -> NetworkDetailResponse {
+    NetworkDetailResponse {
+        containers: network_containers(&network, containers),
+        network,
+    }
+}
+
+fn network_containers(
+    network: &Network,
+    containers: &[ContainerSummary],
+) -> Vec<ResourceContainer> {
+    containers
+        .iter()
+        .filter(|c| {
+            c.network_settings
+                .as_ref()
+                .and_then(|s| s.networks.as_ref())
+                .is_some_and(|networks| {
+                    networks.iter().any(|(name, endpoint)| {
+                        network.name.as_ref() == Some(name)
+                            || (network.id.is_some() && endpoint.network_id == network.id)
+                    })
+                })
+        })
+        .map(resource_container)
+        .collect()
+}
+
+fn validate_network(req: &DockerNetworkCreateRequest) -> anyhow::Result<()> {
+    if req.name.is_empty() {
+        return Err(anyhow::anyhow!("network name is required"));
+    }
+    if let Some(subnet) = &req.subnet {
+        validate_subnet(subnet)?;
+    }
+    if let Some(gateway) = &req.gateway {
+        if req.subnet.is_none() {
+            return Err(anyhow::anyhow!("gateway requires a subnet"));
+        }
+        gateway
+            .parse::<IpAddr>()
+            .map_err(|_| anyhow::anyhow!("invalid gateway {}", gateway))?;
+    }
+    Ok(())
+}
+
+fn validate_subnet(subnet: &str) -> anyhow::Result<()> {
+    let invalid = || anyhow::anyhow!("invalid subnet {}, e.g. 172.28.0.0/16", subnet);
+    let (ip, prefix) = subnet.split_once('/').ok_or_else(invalid)?;
+    let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+    let prefix = prefix.parse::<u8>().map_err(|_| invalid())?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    if prefix > max {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::secret::ContainerSummaryNetworkSettings;
+
+    #[test]
+    fn test_validate_subnet() {
+        assert!(validate_subnet("172.28.0.0/16").is_ok());
+        assert!(validate_subnet("fd00::/64").is_ok());
+        assert!(validate_subnet("172.28.0.0").is_err());
+        assert!(validate_subnet("172.28.0.0/33").is_err());
+        assert!(validate_subnet("net/16").is_err());
+    }
+
+    #[test]
+    fn test_network_containers() {
+        let container = |name: &str, network: &str| ContainerSummary {
+            id: Some(name.to_string()),
+            names: Some(vec![format!("/{}", name)]),
+            state: Some("running".to_string()),
+            network_settings: Some(ContainerSummaryNetworkSettings {
+                networks: Some(HashMap::from([(
+                    network.to_string(),
+                    EndpointSettings::default(),
+                )])),
+            }),
+            ..Default::default()
+        };
+        let containers = vec![container("web", "app"), container("db", "bridge")];
+        let network = Network {
+            name: Some("app".to_string()),
+            id: Some("n1".to_string()),
+            ..Default::default()
+        };
+
+        let users = network_containers(&network, &containers);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].name, "web");
+        assert_eq!(container_names(&users), "web(running)");
+    }
+}
@@ -2,12 +2,14 @@ use axum::Json;
 use rekcod_core::{
     api::{
         req::{NodeInfoRequest, NodeListRequest},
-        resp::{ApiJsonResponse, NodeItemResponse},
+        resp::{ApiJsonResponse, NodeItemResponse, SystemInfoResponse},
     },
+    client::get_client,
     http::ApiError,
 };
+use tracing::warn;
 
-use crate::node::manager::node_manager;
+use crate::node::manager::{node_manager, NodeState};
 
 pub async fn list_node(
     Json(req): Json<NodeListRequest>,
@@ -25,10 +27,31 @@ pub async fn list_node(
 pub async fn info_node(
     Json(req): Json<NodeInfoRequest>,
 ) -> Result<Json<ApiJsonResponse<NodeItemResponse>>, ApiError> {
-    let node = node_manager()
-        .get_node(&req.name)
-        .await?
-        .map(|ns| ns.node.clone().into());
+    let node = match node_manager().get_node(&req.name).await? {
+        Some(ns) => {
+            let mut node: NodeItemResponse = ns.node.clone().into();
+            if ns.online() {
+                match node_sys_info(&ns).await {
+                    Ok(sys) => node.sys = sys,
+                    Err(e) => warn!("get node {} sys info error: {}", req.name, e),
+                }
+            }
+            Some(node)
+        }
+        None => None,
+    };
 
     Ok(ApiJsonResponse::success_optional(node).into())
 }
+
+pub(crate) async fn node_sys_info(ns: &NodeState) -> anyhow::Result<Option<SystemInfoResponse>> {
+    let res = get_client()?
+        .get(format!("{}/sys", ns.get_node_agent()))
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await?
+        .json::<ApiJsonResponse<SystemInfoResponse>>()
+        .await?;
+
+    Ok(res.data().cloned())
+}
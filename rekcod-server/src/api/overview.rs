@@ -0,0 +1,259 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::Json;
+use bollard::{
+    container::ListContainersOptions,
+    image::ListImagesOptions,
+    secret::{ContainerSummary, ImageSummary},
+};
+use rekcod_core::{
+    api::{
+        req::ClusterOverviewRequest,
+        resp::{
+            ApiJsonResponse, ClusterAppCount, ClusterContainerCount, ClusterNodeError,
+            ClusterOverviewResponse, SystemInfoResponse,
+        },
+    },
+    http::ApiError,
+};
+
+use crate::{
+    api::{
+        cluster::{query_nodes, DEFAULT_NODE_TIMEOUT},
+        node::node_sys_info,
+        util::COMPOSE_PROJECT_LABEL,
+    },
+    app::manager::AppDeployInfo,
+    db,
+    node::manager::{node_manager, NodeState},
+};
+
+struct NodeOverview {
+    sys: Option<SystemInfoResponse>,
+    containers: Vec<ContainerSummary>,
+    images: Vec<ImageSummary>,
+}
+
+pub async fn cluster_overview(
+    Json(req): Json<ClusterOverviewRequest>,
+) -> Result<Json<ApiJsonResponse<ClusterOverviewResponse>>, ApiError> {
+    let timeout = Duration::from_secs(req.timeout.unwrap_or(DEFAULT_NODE_TIMEOUT));
+    let nodes = node_manager().get_all_nodes(true).await?;
+
+    let mut overview = ClusterOverviewResponse::default();
+    overview.nodes.total = nodes.len() as u32;
+
+    let online = nodes.into_iter().filter(|n| n.online()).collect::<Vec<_>>();
+    overview.nodes.online = online.len() as u32;
+    overview.nodes.offline = overview.nodes.total - overview.nodes.online;
+
+    let results = query_nodes(
+        online,
+        timeout,
+        |node| async move { node_overview(&node).await },
+    )
+    .await;
+
+    let mut node_containers = HashMap::new();
+    for (node_name, res) in results {
+        match res {
+            Ok(node) => {
+                add_node_overview(&mut overview, &node);
+                node_containers.insert(node_name, node.containers);
+            }
+            Err(e) => {
+                overview.nodes.unreachable += 1;
+                overview.errors.push(ClusterNodeError {
+                    node_name,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    let apps = db::repository()
+        .await
+        .kvs
+        .select("app", None, None, None)
+        .await?
+        .iter()
+        .filter_map(|x| serde_json::from_str::<AppDeployInfo>(&x.value).ok())
+        .collect::<Vec<_>>();
+    overview.apps = count_apps(&apps, &node_containers);
+
+    Ok(ApiJsonResponse::success(overview).into())
+}
+
+async fn node_overview(node: &Arc<NodeState>) -> anyhow::Result<NodeOverview> {
+    let container_options = Some(ListContainersOptions::<&str> {
+        all: true,
+        ..Default::default()
+    });
+    let image_options = Some(ListImagesOptions::<&str>::default());
+
+    let (sys, containers, images) = tokio::join!(
+        node_sys_info(node),
+        node.docker.list_containers(container_options),
+        node.docker.list_images(image_options)
+    );
+
+    Ok(NodeOverview {
+        sys: sys?,
+        containers: containers?,
+        images: images?,
+    })
+}
+
+fn add_node_overview(overview: &mut ClusterOverviewResponse, node: &NodeOverview) {
+    if let Some(sys) = &node.sys {
+        overview.cpu_count += sys.cpu_count;
+        overview.cpu_used += sys.cpu_usage / 100.0 * sys.cpu_count as f32;
+        overview.mem_total += sys.mem_total;
+        overview.mem_used += sys.mem_used;
+
+        // the same device may be mounted more than once
+        let mut disks = HashMap::new();
+        for disk in sys.disks.iter() {
+            disks.entry(&disk.name).or_insert((disk.total, disk.free));
+        }
+        for (total, free) in disks.values() {
+            overview.disk_total += total;
+            overview.disk_used += total.saturating_sub(*free);
+        }
+    }
+
+    for container in node.containers.iter() {
+        count_container(&mut overview.containers, container);
+    }
+
+    overview.image_count += node.images.len() as u64;
+    overview.image_size += node
+        .images
+        .iter()
+        .map(|i| i.size.max(0) as u64)
+        .sum::<u64>();
+}
+
+fn count_container(count: &mut ClusterContainerCount, container: &ContainerSummary) {
+    count.total += 1;
+    match container.state.as_deref() {
+        Some("running") => count.running += 1,
+        Some("paused") => count.paused += 1,
+        Some("restarting") => count.restarting += 1,
+        Some("exited") => count.exited += 1,
+        Some("created") => count.created += 1,
+        Some("dead") => count.dead += 1,
+        _ => {}
+    }
+    if is_unhealthy(container) {
+        count.unhealthy += 1;
+    }
+}
+
+fn is_unhealthy(container: &ContainerSummary) -> bool {
+    container
+        .status
+        .as_deref()
+        .is_some_and(|s| s.contains("(unhealthy)"))
+}
+
+/// apps are matched to containers by container name or compose project
+fn count_apps(
+    apps: &[AppDeployInfo],
+    node_containers: &HashMap<String, Vec<ContainerSummary>>,
+) -> ClusterAppCount {
+    let mut count = ClusterAppCount {
+        total: apps.len() as u32,
+        ..Default::default()
+    };
+
+    for app in apps {
+        let Some(containers) = node_containers.get(&app.node_name) else {
+            count.unknown += 1;
+            continue;
+        };
+
+        let app_containers = containers
+            .iter()
+            .filter(|c| is_app_container(&app.name, c))
+            .collect::<Vec<_>>();
+        if app_containers.is_empty() {
+            count.missing += 1;
+        } else if app_containers.iter().any(|c| is_unhealthy(c)) {
+            count.unhealthy += 1;
+        } else if app_containers
+            .iter()
+            .all(|c| c.state.as_deref() == Some("running"))
+        {
+            count.healthy += 1;
+        } else {
+            count.stopped += 1;
+        }
+    }
+
+    count
+}
+
+fn is_app_container(app_name: &str, container: &ContainerSummary) -> bool {
+    let name = format!("/{}", app_name);
+    container
+        .names
+        .as_ref()
+        .is_some_and(|n| n.iter().any(|x| x == &name))
+        || container
+            .labels
+            .as_ref()
+            .and_then(|l| l.get(COMPOSE_PROJECT_LABEL))
+            .is_some_and(|p| p == app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, state: &str, status: &str) -> ContainerSummary {
+        ContainerSummary {
+            names: Some(vec![format!("/{}", name)]),
+            state: Some(state.to_string()),
+            status: Some(status.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn app(name: &str, node_name: &str) -> AppDeployInfo {
+        AppDeployInfo {
+            name: name.to_string(),
+            node_name: node_name.to_string(),
+            values: None,
+            project: None,
+            build: None,
+        }
+    }
+
+    #[test]
+    fn test_count_apps() {
+        let node_containers = HashMap::from([(
+            "n1".to_string(),
+            vec![
+                container("web", "running", "Up 1 hour"),
+                container("db", "running", "Up 1 hour (unhealthy)"),
+                container("job", "exited", "Exited (1) 2 hours ago"),
+            ],
+        )]);
+        let apps = vec![
+            app("web", "n1"),
+            app("db", "n1"),
+            app("job", "n1"),
+            app("cache", "n1"),
+            app("web", "n2"),
+        ];
+
+        let count = count_apps(&apps, &node_containers);
+        assert_eq!(count.total, 5);
+        assert_eq!(count.healthy, 1);
+        assert_eq!(count.unhealthy, 1);
+        assert_eq!(count.stopped, 1);
+        assert_eq!(count.missing, 1);
+        assert_eq!(count.unknown, 1);
+    }
+}
@@ -0,0 +1,29 @@
+use axum::Json;
+use rekcod_core::{
+    api::{
+        req::{ProcessListRequest, ProcessSignalRequest},
+        resp::{ApiJsonResponse, ProcessItemResponse},
+    },
+    http::ApiError,
+};
+use tracing::info;
+
+pub async fn list_process(
+    Json(req): Json<ProcessListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ProcessItemResponse>>>, ApiError> {
+    let node = get_state!(req.node_name);
+
+    Ok(node.agent_post("/process/list", &req).await?.into())
+}
+
+pub async fn signal_process(
+    Json(req): Json<ProcessSignalRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let node = get_state!(req.node_name);
+
+    info!(
+        "send signal {} to process {} on node {}",
+        req.signal, req.pid, req.node_name
+    );
+    Ok(node.agent_post("/process/signal", &req).await?.into())
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use axum::Json;
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use bollard::auth::DockerCredentials;
+use once_cell::sync::OnceCell;
+use rekcod_core::{
+    api::{
+        req::{
+            RegistryCredentialDeleteRequest, RegistryCredentialRequest,
+            RegistryCredentialTestRequest,
+        },
+        resp::{ApiJsonResponse, RegistryCredentialResponse, RegistryLoginResponse},
+    },
+    client::get_client,
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        image::{registry_host, DEFAULT_REGISTRY},
+        util::split_image,
+    },
+    config::rekcod_server_config,
+    db,
+    node::manager::{node_manager, NodeState},
+};
+
+const REGISTRY_MODULE: &str = "registry";
+const KEY_FILE_NAME: &str = "registry.key";
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+/// the docker cli keeps the docker hub login under this address
+const DOCKER_HUB_ADDRESS: &str = "https://index.docker.io/v1/";
+
+const KIND_PASSWORD: &str = "password";
+const KIND_TOKEN: &str = "token";
+const KIND_IDENTITY_TOKEN: &str = "identity_token";
+
+static CIPHER: OnceCell<Aes256Gcm> = OnceCell::new();
+
+/// registry login stored in kvs module `registry`, keyed by registry host
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct RegistryAuth {
+    username: String,
+    /// plaintext password of logins saved before they were encrypted, only read
+    #[serde(skip_serializing)]
+    password: String,
+    /// `password`, `token` or `identity_token`
+    kind: String,
+    /// base64 of the nonce and the encrypted password or token
+    secret: String,
+    created_at: u64,
+}
+
+/// a decrypted registry login
+struct RegistryLogin {
+    registry: String,
+    username: String,
+    kind: String,
+    secret: String,
+}
+
+impl RegistryLogin {
+    fn credentials(&self) -> DockerCredentials {
+        let mut credentials = DockerCredentials {
+            username: Some(self.username.clone()),
+            serveraddress: Some(server_address(&self.registry).to_string()),
+            ..Default::default()
+        };
+        if self.kind == KIND_IDENTITY_TOKEN {
+            credentials.identitytoken = Some(self.secret.clone());
+        } else {
+            credentials.password = Some(self.secret.clone());
+        }
+        credentials
+    }
+}
+
+pub async fn add_registry_credential(
+    Json(req): Json<RegistryCredentialRequest>,
+) -> Result<Json<ApiJsonResponse<RegistryCredentialResponse>>, ApiError> {
+    let login = request_login(
+        &req.registry,
+        Some(&req.username),
+        req.password.as_deref(),
+        req.token.as_deref(),
+        req.identity_token.as_deref(),
+    )?
+    .ok_or_else(|| anyhow::anyhow!("username is required"))?;
+    if req.verify {
+        let state = login_node(req.node_name.as_deref()).await?;
+        test_login(&state, &login).await?;
+    }
+
+    let auth = RegistryAuth {
+        username: login.username.clone(),
+        kind: login.kind.clone(),
+        secret: encrypt(cipher()?, &login.secret)?,
+        created_at: unix_timestamp(),
+        ..Default::default()
+    };
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: REGISTRY_MODULE.to_string(),
+            key: login.registry.clone(),
+            value: serde_json::to_string(&auth)?,
+            ..Default::default()
+        })
+        .await?;
+    info!("saved {} login of registry {}", auth.kind, login.registry);
+
+    Ok(ApiJsonResponse::success(RegistryCredentialResponse {
+        registry: login.registry,
+        username: auth.username,
+        kind: auth.kind,
+        created_at: auth.created_at,
+    })
+    .into())
+}
+
+pub async fn list_registry_credential(
+) -> Result<Json<ApiJsonResponse<Vec<RegistryCredentialResponse>>>, ApiError> {
+    let mut credentials = db::repository()
+        .await
+        .kvs
+        .select(REGISTRY_MODULE, None, None, None)
+        .await?
+        .into_iter()
+        .filter_map(|x| {
+            let auth = serde_json::from_str::<RegistryAuth>(&x.value).ok()?;
+            Some(RegistryCredentialResponse {
+                registry: x.key,
+                kind: kind(&auth).to_string(),
+                username: auth.username,
+                created_at: auth.created_at,
+            })
+        })
+        .collect::<Vec<_>>();
+    credentials.sort_by(|a, b| a.registry.cmp(&b.registry));
+    Ok(ApiJsonResponse::success(credentials).into())
+}
+
+pub async fn delete_registry_credential(
+    Json(req): Json<RegistryCredentialDeleteRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let registry = normalize_registry(&req.registry);
+    db::repository()
+        .await
+        .kvs
+        .delete(REGISTRY_MODULE, Some(&registry), None, None)
+        .await?;
+    info!("deleted login of registry {}", registry);
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+/// log in to the registry from the docker of a node
+pub async fn test_registry_credential(
+    Json(req): Json<RegistryCredentialTestRequest>,
+) -> Result<Json<ApiJsonResponse<RegistryLoginResponse>>, ApiError> {
+    let login = match request_login(
+        &req.registry,
+        req.username.as_deref(),
+        req.password.as_deref(),
+        req.token.as_deref(),
+        req.identity_token.as_deref(),
+    )? {
+        Some(login) => login,
+        None => {
+            let registry = normalize_registry(&req.registry);
+            load_login(&registry)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("no login saved for registry {}", registry))?
+        }
+    };
+
+    let state = login_node(req.node_name.as_deref()).await?;
+    let status = test_login(&state, &login).await?;
+    Ok(ApiJsonResponse::success(RegistryLoginResponse {
+        registry: login.registry,
+        node_name: state.node.name.clone(),
+        status,
+    })
+    .into())
+}
+
+/// the saved login of a registry host
+pub(crate) async fn registry_credentials(
+    registry: &str,
+) -> anyhow::Result<Option<DockerCredentials>> {
+    Ok(load_login(&normalize_registry(registry))
+        .await?
+        .map(|login| login.credentials()))
+}
+
+/// the username and password or token of the saved login of a registry host, none for
+/// an identity token as it is no basic auth password
+pub(crate) async fn registry_basic_auth(
+    registry: &str,
+) -> anyhow::Result<Option<(String, String)>> {
+    Ok(load_login(&normalize_registry(registry))
+        .await?
+        .filter(|login| login.kind != KIND_IDENTITY_TOKEN)
+        .map(|login| (login.username, login.secret)))
+}
+
+/// the saved login of the registry the image is pulled from or pushed to
+pub(crate) async fn image_credentials(image: &str) -> anyhow::Result<Option<DockerCredentials>> {
+    let (repo, _) = split_image(image);
+    registry_credentials(registry_host(&repo)).await
+}
+
+/// every saved login keyed by registry address, as docker wants them for a build
+pub(crate) async fn all_registry_credentials() -> anyhow::Result<HashMap<String, DockerCredentials>>
+{
+    Ok(load_logins()
+        .await?
+        .iter()
+        .map(|login| {
+            (
+                server_address(&login.registry).to_string(),
+                login.credentials(),
+            )
+        })
+        .collect())
+}
+
+/// write a docker config with every saved login for the docker cli, the caller removes
+/// the dir once the cli exits, none if there is no login
+pub(crate) async fn write_docker_config() -> anyhow::Result<Option<PathBuf>> {
+    let logins = load_logins().await?;
+    if logins.is_empty() {
+        return Ok(None);
+    }
+
+    let dir = Path::new(&rekcod_server_config().data_path)
+        .join("tmp")
+        .join(format!("docker-config-{}", Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await?;
+    let config = serde_json::to_vec_pretty(&docker_config(&logins))?;
+    write_private(&dir.join("config.json"), &config)?;
+    Ok(Some(dir))
+}
+
+fn docker_config(logins: &[RegistryLogin]) -> serde_json::Value {
+    let auths = logins
+        .iter()
+        .map(|login| {
+            let auth = if login.kind == KIND_IDENTITY_TOKEN {
+                json!({
+                    "auth": BASE64_STANDARD.encode(format!("{}:", login.username)),
+                    "identitytoken": login.secret,
+                })
+            } else {
+                json!({
+                    "auth": BASE64_STANDARD.encode(format!("{}:{}", login.username, login.secret)),
+                })
+            };
+            (server_address(&login.registry).to_string(), auth)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({ "auths": auths })
+}
+
+/// the login given in a request, none if there is no username
+fn request_login(
+    registry: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+    token: Option<&str>,
+    identity_token: Option<&str>,
+) -> anyhow::Result<Option<RegistryLogin>> {
+    let registry = normalize_registry(registry);
+    if registry.is_empty() {
+        return Err(anyhow::anyhow!("registry is required"));
+    }
+    let Some(username) = username.map(|x| x.trim()).filter(|x| !x.is_empty()) else {
+        return Ok(None);
+    };
+    let (kind, secret) = match (
+        password.filter(|x| !x.is_empty()),
+        token.filter(|x| !x.is_empty()),
+        identity_token.filter(|x| !x.is_empty()),
+    ) {
+        (Some(password), None, None) => (KIND_PASSWORD, password),
+        (None, Some(token), None) => (KIND_TOKEN, token),
+        (None, None, Some(identity_token)) => (KIND_IDENTITY_TOKEN, identity_token),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "exactly one of a password, a token or an identity token is required"
+            ))
+        }
+    };
+    Ok(Some(RegistryLogin {
+        registry,
+        username: username.to_string(),
+        kind: kind.to_string(),
+        secret: secret.to_string(),
+    }))
+}
+
+async fn load_login(registry: &str) -> anyhow::Result<Option<RegistryLogin>> {
+    let auth = db::repository()
+        .await
+        .kvs
+        .select_one(REGISTRY_MODULE, Some(registry), None, None)
+        .await?;
+    match auth {
+        Some(auth) => Ok(Some(decrypt_login(auth.key, &auth.value)?)),
+        None => Ok(None),
+    }
+}
+
+/// every saved login, one that fails to decrypt is skipped so it does not break the
+/// builds and deploys of the other registries
+async fn load_logins() -> anyhow::Result<Vec<RegistryLogin>> {
+    Ok(db::repository()
+        .await
+        .kvs
+        .select(REGISTRY_MODULE, None, None, None)
+        .await?
+        .into_iter()
+        .filter_map(|x| match decrypt_login(x.key, &x.value) {
+            Ok(login) => Some(login),
+            Err(e) => {
+                warn!("skip saved registry login: {:?}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn decrypt_login(registry: String, value: &str) -> anyhow::Result<RegistryLogin> {
+    let auth: RegistryAuth = serde_json::from_str(value)?;
+    let secret = if auth.secret.is_empty() {
+        auth.password.clone()
+    } else {
+        decrypt(cipher()?, &auth.secret)
+            .map_err(|e| anyhow::anyhow!("decrypt login of registry {}: {}", registry, e))?
+    };
+    Ok(RegistryLogin {
+        registry,
+        username: auth.username.clone(),
+        kind: kind(&auth).to_string(),
+        secret,
+    })
+}
+
+fn kind(auth: &RegistryAuth) -> &str {
+    if auth.kind.is_empty() {
+        KIND_PASSWORD
+    } else {
+        &auth.kind
+    }
+}
+
+async fn login_node(node_name: Option<&str>) -> anyhow::Result<std::sync::Arc<NodeState>> {
+    let nodes = node_manager().get_all_nodes(true).await?;
+    let node = match node_name.filter(|x| !x.is_empty()) {
+        Some(name) => nodes
+            .into_iter()
+            .find(|n| n.node.name == name)
+            .ok_or_else(|| anyhow::anyhow!("node {} not found", name))?,
+        None => nodes
+            .into_iter()
+            .find(|n| n.online())
+            .ok_or_else(|| anyhow::anyhow!("no online node to log in from"))?,
+    };
+    if !node.online() {
+        return Err(anyhow::anyhow!("node {} is offline", node.node.name));
+    }
+    Ok(node)
+}
+
+/// bollard has no api for the docker login check, return the status of the registry
+async fn test_login(state: &NodeState, login: &RegistryLogin) -> anyhow::Result<String> {
+    let res = get_client()?
+        .post(format!("{}/auth", state.get_docker_proxy()))
+        .json(&login.credentials())
+        .send()
+        .await?;
+    let ok = res.status().is_success();
+    let body = res.json::<serde_json::Value>().await.unwrap_or_default();
+    if !ok {
+        let message = body["message"].as_str().unwrap_or("login failed");
+        return Err(anyhow::anyhow!(
+            "login to registry {} failed: {}",
+            login.registry,
+            message
+        ));
+    }
+    Ok(body["Status"]
+        .as_str()
+        .unwrap_or("Login Succeeded")
+        .to_string())
+}
+
+/// the registry host the logins are saved with, docker hub has a few aliases
+pub(crate) fn normalize_registry(registry: &str) -> String {
+    let registry = registry.trim().to_lowercase();
+    let host = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => {
+            DEFAULT_REGISTRY.to_string()
+        }
+        _ => host.to_string(),
+    }
+}
+
+fn server_address(registry: &str) -> &str {
+    if registry == DEFAULT_REGISTRY {
+        DOCKER_HUB_ADDRESS
+    } else {
+        registry
+    }
+}
+
+/// the key is generated in the config dir on first use
+fn cipher() -> anyhow::Result<&'static Aes256Gcm> {
+    CIPHER.get_or_try_init(|| {
+        let path = Path::new(&rekcod_server_config().config_path).join(KEY_FILE_NAME);
+        let key = if path.exists() {
+            hex::decode(std::fs::read_to_string(&path)?.trim())?
+        } else {
+            let key = Aes256Gcm::generate_key(OsRng).to_vec();
+            write_private(&path, hex::encode(&key).as_bytes())?;
+            info!("generated registry key {}", path.display());
+            key
+        };
+        if key.len() != KEY_SIZE {
+            return Err(anyhow::anyhow!("invalid registry key {}", path.display()));
+        }
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    })
+}
+
+fn encrypt(cipher: &Aes256Gcm, plaintext: &str) -> anyhow::Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|e| anyhow::anyhow!("encrypt error: {}", e))?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(BASE64_STANDARD.encode(data))
+}
+
+fn decrypt(cipher: &Aes256Gcm, secret: &str) -> anyhow::Result<String> {
+    let data = BASE64_STANDARD.decode(secret)?;
+    if data.len() < NONCE_SIZE {
+        return Err(anyhow::anyhow!("secret is too short"));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| anyhow::anyhow!("decrypt error: {}", e))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// only the server user can read the key and the docker config
+fn write_private(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    use std::io::Write as _;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let cipher = Aes256Gcm::new(&key);
+        let secret = encrypt(&cipher, "s3cret").unwrap();
+        assert!(!secret.contains("s3cret"));
+        assert_ne!(secret, encrypt(&cipher, "s3cret").unwrap());
+        assert_eq!(decrypt(&cipher, &secret).unwrap(), "s3cret");
+
+        let other = Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng));
+        assert!(decrypt(&other, &secret).is_err());
+    }
+
+    #[test]
+    fn test_normalize_registry() {
+        assert_eq!(
+            normalize_registry("https://index.docker.io/v1/"),
+            "docker.io"
+        );
+        assert_eq!(normalize_registry("Docker.io"), "docker.io");
+        assert_eq!(
+            normalize_registry("http://registry.local:5000/"),
+            "registry.local:5000"
+        );
+    }
+
+    #[test]
+    fn test_docker_config() {
+        let logins = vec![
+            RegistryLogin {
+                registry: "docker.io".to_string(),
+                username: "user".to_string(),
+                kind: KIND_PASSWORD.to_string(),
+                secret: "pass".to_string(),
+            },
+            RegistryLogin {
+                registry: "ghcr.io".to_string(),
+                username: "bot".to_string(),
+                kind: KIND_TOKEN.to_string(),
+                secret: "tok".to_string(),
+            },
+            RegistryLogin {
+                registry: "quay.io".to_string(),
+                username: "robot".to_string(),
+                kind: KIND_IDENTITY_TOKEN.to_string(),
+                secret: "refresh".to_string(),
+            },
+        ];
+        let config = docker_config(&logins);
+        assert_eq!(
+            config["auths"][DOCKER_HUB_ADDRESS]["auth"],
+            BASE64_STANDARD.encode("user:pass")
+        );
+        assert_eq!(
+            config["auths"]["ghcr.io"]["auth"],
+            BASE64_STANDARD.encode("bot:tok")
+        );
+        assert!(config["auths"]["ghcr.io"]["identitytoken"].is_null());
+        assert_eq!(config["auths"]["quay.io"]["identitytoken"], "refresh");
+        assert_eq!(logins[1].credentials().password.as_deref(), Some("tok"));
+        assert_eq!(
+            logins[2].credentials().identitytoken.as_deref(),
+            Some("refresh")
+        );
+    }
+}
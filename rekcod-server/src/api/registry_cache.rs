@@ -0,0 +1,1063 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{self, ConnectInfo, Request},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use futures::StreamExt as _;
+use hyper::{header, HeaderMap, StatusCode};
+use once_cell::sync::Lazy;
+use rekcod_core::{
+    api::{
+        req::{
+            RegistryCacheConfigRequest, RegistryCacheDeleteRequest, RegistryCacheListRequest,
+            RegistryCacheSeedRequest,
+        },
+        resp::{
+            ApiJsonResponse, RegistryCacheItem, RegistryCacheSeedResponse,
+            RegistryCacheStatusResponse,
+        },
+    },
+    auth::get_token,
+    constants::TOEKN_HEADER_KEY,
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest as _, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt as _};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        image::{Platform, DEFAULT_REGISTRY},
+        registry::{normalize_registry, registry_basic_auth},
+        util::split_image,
+    },
+    config::rekcod_server_config,
+    db,
+    node::manager::node_manager,
+};
+
+const CONFIG_MODULE: &str = "registry_cache_config";
+/// manifests keyed by repository and reference
+const MANIFEST_MODULE: &str = "registry_cache";
+/// blobs keyed by digest, manifests are stored as blobs too
+const BLOB_MODULE: &str = "registry_cache_blob";
+const CACHE_DIR: &str = "registry";
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// the upstream client does not send the rekcod token
+static UPSTREAM_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(UPSTREAM_TIMEOUT)
+        .build()
+        .expect("build registry client")
+});
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+struct CachedManifest {
+    digest: String,
+    media_type: String,
+    size: u64,
+    cached_at: u64,
+    last_access: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+struct CachedBlob {
+    size: u64,
+    last_access: u64,
+}
+
+/// the fields of image manifests and indexes the cache needs
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Manifest {
+    media_type: Option<String>,
+    config: Option<Descriptor>,
+    layers: Vec<Descriptor>,
+    manifests: Vec<Descriptor>,
+}
+
+impl Manifest {
+    /// whether the config or a layer of the manifest is the blob
+    fn references(&self, digest: &str) -> bool {
+        self.config
+            .iter()
+            .chain(self.layers.iter())
+            .any(|d| d.digest == digest)
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Descriptor {
+    digest: String,
+    size: u64,
+    platform: Option<DescriptorPlatform>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct DescriptorPlatform {
+    os: String,
+    architecture: String,
+    variant: Option<String>,
+}
+
+/// the registry and repository of a cached repository
+#[derive(Debug, PartialEq)]
+struct Repository {
+    /// host of the registry api
+    host: String,
+    /// registry the login is saved for
+    registry: String,
+    name: String,
+}
+
+impl Repository {
+    /// `nginx`, `library/nginx` and `docker.io/library/nginx` are the same repository,
+    /// other registries are the first part of the name like `ghcr.io/org/app`
+    fn parse(name: &str) -> Option<Self> {
+        let (registry, name) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_string(), rest.to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        if name.is_empty()
+            || name
+                .split('/')
+                .any(|x| x.is_empty() || x == "." || x == "..")
+        {
+            return None;
+        }
+        if registry == DEFAULT_REGISTRY {
+            let name = if name.contains('/') {
+                name
+            } else {
+                format!("library/{}", name)
+            };
+            return Some(Self {
+                host: DOCKER_HUB_API.to_string(),
+                registry,
+                name,
+            });
+        }
+        Some(Self {
+            host: registry.clone(),
+            registry,
+            name,
+        })
+    }
+
+    /// the key the manifests are cached with
+    fn key(&self) -> String {
+        format!("{}/{}", self.registry, self.name)
+    }
+
+    fn url(&self, kind: &str, reference: &str) -> String {
+        format!(
+            "https://{}/v2/{}/{}/{}",
+            self.host, self.name, kind, reference
+        )
+    }
+}
+
+/// only the nodes pull through the cache, docker daemons can not send the rekcod
+/// token so they are known by their address
+pub async fn registry_auth(req: Request, next: Next) -> Response {
+    let token = header_str(req.headers(), TOEKN_HEADER_KEY).is_some_and(|x| x == get_token());
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|x| x.0.ip());
+    if token || is_node_address(ip).await {
+        return next.run(req).await;
+    }
+    v2_error(
+        StatusCode::UNAUTHORIZED,
+        "UNAUTHORIZED",
+        "registry cache is only served to the nodes",
+    )
+}
+
+/// `GET /v2/`, docker checks the registry api version with it
+pub async fn registry_v2_base() -> Response {
+    if !load_config().await.enabled {
+        return v2_error(
+            StatusCode::NOT_FOUND,
+            "UNSUPPORTED",
+            "registry cache is disabled",
+        );
+    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Docker-Distribution-API-Version", "registry/2.0")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap_or_default()
+}
+
+/// `GET` and `HEAD` of `/v2/<name>/manifests/<reference>` and `/v2/<name>/blobs/<digest>`
+pub async fn registry_v2(extract::Path(path): extract::Path<String>) -> Response {
+    let config = load_config().await;
+    if !config.enabled {
+        return v2_error(
+            StatusCode::NOT_FOUND,
+            "UNSUPPORTED",
+            "registry cache is disabled",
+        );
+    }
+    let Some((name, kind, reference)) = parse_v2_path(&path) else {
+        return v2_error(StatusCode::NOT_FOUND, "NAME_UNKNOWN", "unknown path");
+    };
+    let Some(repo) = Repository::parse(name) else {
+        return v2_error(
+            StatusCode::NOT_FOUND,
+            "NAME_INVALID",
+            "invalid repository name",
+        );
+    };
+    if !allows_registry(&config, &repo.registry) {
+        return v2_error(
+            StatusCode::FORBIDDEN,
+            "DENIED",
+            &format!("registry {} is not cached", repo.registry),
+        );
+    }
+
+    let res = match kind {
+        "manifests" => serve_manifest(&repo, reference).await,
+        _ => serve_blob(&repo, reference).await,
+    };
+    match res {
+        Ok(res) => res,
+        Err(e) => {
+            warn!("registry cache {} error: {:?}", path, e);
+            let code = if kind == "manifests" {
+                "MANIFEST_UNKNOWN"
+            } else {
+                "BLOB_UNKNOWN"
+            };
+            v2_error(StatusCode::NOT_FOUND, code, &e.to_string())
+        }
+    }
+}
+
+pub async fn get_registry_cache_config(
+) -> Result<Json<ApiJsonResponse<RegistryCacheStatusResponse>>, ApiError> {
+    let config = load_config().await;
+    let blobs = load_blobs().await?;
+    let manifests = db::repository()
+        .await
+        .kvs
+        .select(MANIFEST_MODULE, None, None, None)
+        .await?
+        .len();
+    Ok(ApiJsonResponse::success(RegistryCacheStatusResponse {
+        enabled: config.enabled,
+        max_size: config.max_size,
+        registries: upstream_registries(&config),
+        size: blobs.values().map(|b| b.size).sum(),
+        blobs: blobs.len(),
+        manifests,
+    })
+    .into())
+}
+
+pub async fn set_registry_cache_config(
+    Json(req): Json<RegistryCacheConfigRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: CONFIG_MODULE.to_string(),
+            key: "config".to_string(),
+            value: serde_json::to_string(&req)?,
+            ..Default::default()
+        })
+        .await?;
+    info!(
+        "registry cache enabled: {}, max size: {}, registries: {:?}",
+        req.enabled,
+        req.max_size,
+        upstream_registries(&req)
+    );
+    evict(req.max_size, None).await?;
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn list_registry_cache(
+    Json(req): Json<RegistryCacheListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<RegistryCacheItem>>>, ApiError> {
+    let key = req
+        .repository
+        .as_deref()
+        .filter(|x| !x.is_empty())
+        .and_then(Repository::parse)
+        .map(|r| r.key());
+    let mut items = db::repository()
+        .await
+        .kvs
+        .select(MANIFEST_MODULE, key.as_deref(), None, None)
+        .await?
+        .into_iter()
+        .filter_map(|x| {
+            let manifest = serde_json::from_str::<CachedManifest>(&x.value).ok()?;
+            Some(RegistryCacheItem {
+                repository: x.key,
+                reference: x.sub_key,
+                digest: manifest.digest,
+                media_type: manifest.media_type,
+                size: manifest.size,
+                cached_at: manifest.cached_at,
+                last_access: manifest.last_access,
+            })
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| (&a.repository, &a.reference).cmp(&(&b.repository, &b.reference)));
+    Ok(ApiJsonResponse::success(items).into())
+}
+
+/// remove the manifests, the blobs stay until they are evicted as other images may
+/// share them
+pub async fn delete_registry_cache(
+    Json(req): Json<RegistryCacheDeleteRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let repo = Repository::parse(&req.repository)
+        .ok_or_else(|| anyhow::anyhow!("invalid repository {}", req.repository))?;
+    let reference = req.reference.as_deref().filter(|x| !x.is_empty());
+    db::repository()
+        .await
+        .kvs
+        .delete(MANIFEST_MODULE, Some(&repo.key()), reference, None)
+        .await?;
+    info!(
+        "deleted registry cache {} {}",
+        repo.key(),
+        reference.unwrap_or("*")
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn seed_registry_cache(
+    Json(req): Json<RegistryCacheSeedRequest>,
+) -> Result<Json<ApiJsonResponse<RegistryCacheSeedResponse>>, ApiError> {
+    Ok(ApiJsonResponse::success(seed_image(&req.image).await?).into())
+}
+
+/// seed the cache with the image in the background if the cache is enabled
+pub(crate) fn spawn_seed(image: &str) {
+    let image = image.to_string();
+    tokio::spawn(async move {
+        if !load_config().await.enabled {
+            return;
+        }
+        if let Err(e) = seed_image(&image).await {
+            warn!("seed registry cache with {} error: {:?}", image, e);
+        }
+    });
+}
+
+/// fetch the image for the platforms of the online nodes into the cache
+pub(crate) async fn seed_image(image: &str) -> anyhow::Result<RegistryCacheSeedResponse> {
+    let config = load_config().await;
+    if !config.enabled {
+        return Err(anyhow::anyhow!("registry cache is disabled"));
+    }
+    let (name, reference) = split_image(image);
+    let repo =
+        Repository::parse(&name).ok_or_else(|| anyhow::anyhow!("invalid image {}", image))?;
+    if !allows_registry(&config, &repo.registry) {
+        return Err(anyhow::anyhow!("registry {} is not cached", repo.registry));
+    }
+    let platforms = node_manager()
+        .get_all_nodes(false)
+        .await?
+        .iter()
+        .map(|n| Platform::of_node(&n.node))
+        .collect::<Vec<_>>();
+
+    let mut res = RegistryCacheSeedResponse {
+        image: image.to_string(),
+        ..Default::default()
+    };
+    let mut pending = vec![reference];
+    while let Some(reference) = pending.pop() {
+        let (_, data, fetched) = fetch_manifest(&repo, &reference).await?;
+        res.manifests += 1;
+        res.fetched += fetched;
+        let manifest = serde_json::from_slice::<Manifest>(&data)?;
+        for m in manifest.manifests.iter().filter(|m| wanted(m, &platforms)) {
+            pending.push(m.digest.clone());
+        }
+        for blob in manifest.config.iter().chain(manifest.layers.iter()) {
+            res.fetched += fetch_blob(&repo, &blob.digest).await?;
+            res.blobs += 1;
+        }
+    }
+    info!(
+        "seeded registry cache with {}, {} manifests, {} blobs",
+        image, res.manifests, res.blobs
+    );
+    Ok(res)
+}
+
+/// the manifests of an index for the platforms of the nodes, every manifest if there
+/// is no node
+fn wanted(descriptor: &Descriptor, platforms: &[Platform]) -> bool {
+    let Some(p) = &descriptor.platform else {
+        return false;
+    };
+    platforms.is_empty()
+        || platforms
+            .iter()
+            .any(|x| x.matches_platform(&p.os, &p.architecture, p.variant.as_deref()))
+}
+
+async fn serve_manifest(repo: &Repository, reference: &str) -> anyhow::Result<Response> {
+    let (manifest, data, _) = fetch_manifest(repo, reference).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, manifest.media_type)
+        .header(header::CONTENT_LENGTH, data.len())
+        .header("Docker-Content-Digest", manifest.digest)
+        .body(Body::from(data))?)
+}
+
+async fn serve_blob(repo: &Repository, digest: &str) -> anyhow::Result<Response> {
+    if !owns_blob(repo, digest).await? {
+        return Err(anyhow::anyhow!(
+            "blob {} is not in a cached manifest of {}",
+            digest,
+            repo.key()
+        ));
+    }
+    fetch_blob(repo, digest).await?;
+    let path = blob_path(digest)?;
+    let file = File::open(&path).await?;
+    let size = file.metadata().await?.len();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, size)
+        .header("Docker-Content-Digest", digest)
+        .body(Body::from_stream(ReaderStream::new(file)))?)
+}
+
+/// whether a cached manifest of the repository references the blob, so a blob cached
+/// for one repository is not served under the name of another
+async fn owns_blob(repo: &Repository, digest: &str) -> anyhow::Result<bool> {
+    let manifests = db::repository()
+        .await
+        .kvs
+        .select(MANIFEST_MODULE, Some(&repo.key()), None, None)
+        .await?
+        .into_iter()
+        .filter_map(|x| serde_json::from_str::<CachedManifest>(&x.value).ok())
+        .map(|m| m.digest)
+        .collect::<HashSet<_>>();
+    for manifest in manifests {
+        let Ok(data) = tokio::fs::read(blob_path(&manifest)?).await else {
+            continue;
+        };
+        if serde_json::from_slice::<Manifest>(&data).is_ok_and(|m| m.references(digest)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// the cached manifest of a digest, a tag is checked with the upstream registry and
+/// the cached one is used if it is unreachable, return the manifest, its content and
+/// the bytes fetched from the upstream
+async fn fetch_manifest(
+    repo: &Repository,
+    reference: &str,
+) -> anyhow::Result<(CachedManifest, Vec<u8>, u64)> {
+    let cached = load_manifest(repo, reference).await?;
+    if let Some(cached) = &cached {
+        let fresh = if is_digest(reference) {
+            true
+        } else {
+            match upstream(
+                repo,
+                reqwest::Method::HEAD,
+                &repo.url("manifests", reference),
+            )
+            .await
+            {
+                Ok(res) => header_str(res.headers(), "Docker-Content-Digest")
+                    .is_some_and(|digest| digest == cached.digest),
+                Err(e) => {
+                    warn!(
+                        "check {}:{} with the upstream error, use the cache: {:?}",
+                        repo.key(),
+                        reference,
+                        e
+                    );
+                    true
+                }
+            }
+        };
+        if fresh {
+            if let Ok(data) = tokio::fs::read(blob_path(&cached.digest)?).await {
+                touch_manifest(repo, reference, cached).await?;
+                return Ok((cached.clone(), data, 0));
+            }
+        }
+    }
+
+    let res = upstream(
+        repo,
+        reqwest::Method::GET,
+        &repo.url("manifests", reference),
+    )
+    .await?;
+    let media_type = header_str(res.headers(), header::CONTENT_TYPE.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let data = res.bytes().await?.to_vec();
+    let digest = format!("sha256:{}", hex::encode(Sha256::digest(&data)));
+    if is_digest(reference) && reference != digest {
+        return Err(anyhow::anyhow!(
+            "digest of manifest {} does not match",
+            reference
+        ));
+    }
+
+    let manifest_type = serde_json::from_slice::<Manifest>(&data)?;
+    let now = unix_timestamp();
+    let manifest = CachedManifest {
+        media_type: manifest_type
+            .media_type
+            .clone()
+            .filter(|x| !x.is_empty())
+            .unwrap_or(media_type),
+        size: data.len() as u64
+            + manifest_type
+                .config
+                .iter()
+                .chain(manifest_type.layers.iter())
+                .map(|d| d.size)
+                .sum::<u64>(),
+        digest: digest.clone(),
+        cached_at: now,
+        last_access: now,
+    };
+    store_blob_data(&digest, &data).await?;
+    save_manifest(repo, reference, &manifest).await?;
+    if reference != digest {
+        save_manifest(repo, &digest, &manifest).await?;
+    }
+    info!("cached manifest {}:{} {}", repo.key(), reference, digest);
+    Ok((manifest, data.clone(), data.len() as u64))
+}
+
+/// download the blob into the cache if it is not there, return the bytes fetched
+async fn fetch_blob(repo: &Repository, digest: &str) -> anyhow::Result<u64> {
+    let path = blob_path(digest)?;
+    if tokio::fs::try_exists(&path).await? {
+        touch_blob(digest, tokio::fs::metadata(&path).await?.len()).await?;
+        return Ok(0);
+    }
+
+    let res = upstream(repo, reqwest::Method::GET, &repo.url("blobs", digest)).await?;
+    let tmp = cache_dir().join("tmp").join(Uuid::new_v4().to_string());
+    if let Some(dir) = tmp.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let res = async {
+        let mut file = File::create(&tmp).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        if format!("sha256:{}", hex::encode(hasher.finalize())) != digest {
+            return Err(anyhow::anyhow!("digest of blob {} does not match", digest));
+        }
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(size)
+    }
+    .await;
+    let size = match res {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e);
+        }
+    };
+
+    touch_blob(digest, size).await?;
+    info!("cached blob {} of {}, {} bytes", digest, repo.key(), size);
+    evict(load_config().await.max_size, Some(digest)).await?;
+    Ok(size)
+}
+
+/// send the request to the upstream registry, log in with the saved login if it asks
+async fn upstream(
+    repo: &Repository,
+    method: reqwest::Method,
+    url: &str,
+) -> anyhow::Result<reqwest::Response> {
+    let request = || {
+        UPSTREAM_CLIENT
+            .request(method.clone(), url)
+            .header(header::ACCEPT, MANIFEST_ACCEPT)
+            .timeout(UPSTREAM_TIMEOUT * 20)
+    };
+    let res = request().send().await?;
+    if res.status() != StatusCode::UNAUTHORIZED {
+        return Ok(res.error_for_status()?);
+    }
+
+    let login = registry_basic_auth(&repo.registry).await?;
+    let challenge = header_str(res.headers(), header::WWW_AUTHENTICATE.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let res = match challenge.split_once(' ') {
+        Some((scheme, params)) if scheme.eq_ignore_ascii_case("bearer") => {
+            let params = parse_challenge(params);
+            let realm = params
+                .get("realm")
+                .ok_or_else(|| anyhow::anyhow!("no realm in the registry challenge"))?;
+            let mut query = Vec::new();
+            if let Some(service) = params.get("service") {
+                query.push(("service", service.clone()));
+            }
+            query.push((
+                "scope",
+                params
+                    .get("scope")
+                    .cloned()
+                    .unwrap_or_else(|| format!("repository:{}:pull", repo.name)),
+            ));
+            let mut token_request = UPSTREAM_CLIENT.get(realm).query(&query);
+            if let Some((username, password)) = &login {
+                token_request = token_request.basic_auth(username, Some(password));
+            }
+            let token = token_request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<serde_json::Value>()
+                .await?;
+            let token = token["token"]
+                .as_str()
+                .or(token["access_token"].as_str())
+                .ok_or_else(|| anyhow::anyhow!("no token from {}", realm))?
+                .to_string();
+            request().bearer_auth(token).send().await?
+        }
+        _ => match &login {
+            Some((username, password)) => {
+                request()
+                    .basic_auth(username, Some(password))
+                    .send()
+                    .await?
+            }
+            None => return Err(anyhow::anyhow!("registry {} needs a login", repo.registry)),
+        },
+    };
+    Ok(res.error_for_status()?)
+}
+
+/// the `key="value"` params of a `WWW-Authenticate` challenge
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    let mut res = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let value = value.trim_start();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(value) => match value.split_once('"') {
+                Some((value, next)) => (value, next),
+                None => (value, ""),
+            },
+            None => match value.split_once(',') {
+                Some((value, next)) => (value, next),
+                None => (value, ""),
+            },
+        };
+        res.insert(key, value.to_string());
+        rest = next.trim_start_matches(',').trim();
+    }
+    res
+}
+
+/// split `<name>/manifests/<reference>` and `<name>/blobs/<digest>`
+fn parse_v2_path(path: &str) -> Option<(&str, &'static str, &str)> {
+    for kind in ["manifests", "blobs"] {
+        if let Some((name, reference)) = path.rsplit_once(&format!("/{}/", kind)) {
+            if reference.is_empty() || reference.contains('/') {
+                return None;
+            }
+            if kind == "blobs" && !is_digest(reference) {
+                return None;
+            }
+            return Some((name.trim_start_matches('/'), kind, reference));
+        }
+    }
+    None
+}
+
+fn is_digest(reference: &str) -> bool {
+    reference.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64
+            && hex
+                .bytes()
+                .all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+    })
+}
+
+/// the registries the cache pulls through, docker hub if none is configured
+fn upstream_registries(config: &RegistryCacheConfigRequest) -> Vec<String> {
+    let registries = config
+        .registries
+        .iter()
+        .map(|x| normalize_registry(x))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    if registries.is_empty() {
+        vec![DEFAULT_REGISTRY.to_string()]
+    } else {
+        registries
+    }
+}
+
+fn allows_registry(config: &RegistryCacheConfigRequest, registry: &str) -> bool {
+    upstream_registries(config).iter().any(|x| x == registry)
+}
+
+/// loopback or the address of a registered node
+async fn is_node_address(ip: Option<IpAddr>) -> bool {
+    let Some(ip) = ip.map(|x| x.to_canonical()) else {
+        return false;
+    };
+    if ip.is_loopback() {
+        return true;
+    }
+    match node_manager().get_all_nodes(true).await {
+        Ok(nodes) => nodes
+            .iter()
+            .any(|n| n.node.ip.parse::<IpAddr>().is_ok_and(|x| x == ip)),
+        Err(e) => {
+            warn!("list nodes for the registry cache error: {:?}", e);
+            false
+        }
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|x| x.to_str().ok())
+}
+
+fn v2_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = json!({ "errors": [{ "code": code, "message": message }] });
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("Docker-Distribution-API-Version", "registry/2.0")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+fn cache_dir() -> PathBuf {
+    Path::new(&rekcod_server_config().data_path).join(CACHE_DIR)
+}
+
+fn blob_path(digest: &str) -> anyhow::Result<PathBuf> {
+    if !is_digest(digest) {
+        return Err(anyhow::anyhow!("invalid digest {}", digest));
+    }
+    Ok(cache_dir()
+        .join("blobs")
+        .join("sha256")
+        .join(&digest["sha256:".len()..]))
+}
+
+async fn load_config() -> RegistryCacheConfigRequest {
+    let config = match db::repository()
+        .await
+        .kvs
+        .select_one(CONFIG_MODULE, Some("config"), None, None)
+        .await
+    {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("load registry cache config error: {:?}", e);
+            None
+        }
+    };
+    config
+        .and_then(|x| serde_json::from_str(&x.value).ok())
+        .unwrap_or_default()
+}
+
+async fn load_manifest(
+    repo: &Repository,
+    reference: &str,
+) -> anyhow::Result<Option<CachedManifest>> {
+    let manifest = db::repository()
+        .await
+        .kvs
+        .select_one(MANIFEST_MODULE, Some(&repo.key()), Some(reference), None)
+        .await?;
+    Ok(manifest.and_then(|x| serde_json::from_str(&x.value).ok()))
+}
+
+async fn save_manifest(
+    repo: &Repository,
+    reference: &str,
+    manifest: &CachedManifest,
+) -> anyhow::Result<()> {
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: MANIFEST_MODULE.to_string(),
+            key: repo.key(),
+            sub_key: reference.to_string(),
+            value: serde_json::to_string(manifest)?,
+            ..Default::default()
+        })
+        .await
+}
+
+async fn touch_manifest(
+    repo: &Repository,
+    reference: &str,
+    manifest: &CachedManifest,
+) -> anyhow::Result<()> {
+    let manifest = CachedManifest {
+        last_access: unix_timestamp(),
+        ..manifest.clone()
+    };
+    save_manifest(repo, reference, &manifest).await?;
+    touch_blob(&manifest.digest, 0).await
+}
+
+async fn store_blob_data(digest: &str, data: &[u8]) -> anyhow::Result<()> {
+    let path = blob_path(digest)?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(&path, data).await?;
+    touch_blob(digest, data.len() as u64).await
+}
+
+/// record the access of a blob, the size is kept if it is 0
+async fn touch_blob(digest: &str, size: u64) -> anyhow::Result<()> {
+    let repository = db::repository().await;
+    let size = match size {
+        0 => repository
+            .kvs
+            .select_one(BLOB_MODULE, Some(digest), None, None)
+            .await?
+            .and_then(|x| serde_json::from_str::<CachedBlob>(&x.value).ok())
+            .map(|b| b.size)
+            .unwrap_or_default(),
+        size => size,
+    };
+    repository
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: BLOB_MODULE.to_string(),
+            key: digest.to_string(),
+            value: serde_json::to_string(&CachedBlob {
+                size,
+                last_access: unix_timestamp(),
+            })?,
+            ..Default::default()
+        })
+        .await
+}
+
+async fn load_blobs() -> anyhow::Result<HashMap<String, CachedBlob>> {
+    Ok(db::repository()
+        .await
+        .kvs
+        .select(BLOB_MODULE, None, None, None)
+        .await?
+        .into_iter()
+        .filter_map(|x| Some((x.key, serde_json::from_str::<CachedBlob>(&x.value).ok()?)))
+        .collect())
+}
+
+/// remove the least recently pulled blobs until the cache fits the max size, and the
+/// manifests of the removed blobs
+async fn evict(max_size: u64, keep: Option<&str>) -> anyhow::Result<()> {
+    if max_size == 0 {
+        return Ok(());
+    }
+    let blobs = load_blobs().await?;
+    let evicted = evict_candidates(&blobs, max_size, keep);
+    if evicted.is_empty() {
+        return Ok(());
+    }
+
+    let repository = db::repository().await;
+    for digest in &evicted {
+        let _ = tokio::fs::remove_file(blob_path(digest)?).await;
+        repository
+            .kvs
+            .delete(BLOB_MODULE, Some(digest), None, None)
+            .await?;
+    }
+    for manifest in repository
+        .kvs
+        .select(MANIFEST_MODULE, None, None, None)
+        .await?
+    {
+        let digest = serde_json::from_str::<CachedManifest>(&manifest.value)
+            .map(|m| m.digest)
+            .unwrap_or_default();
+        if evicted.contains(&digest) {
+            repository
+                .kvs
+                .delete(
+                    MANIFEST_MODULE,
+                    Some(&manifest.key),
+                    Some(&manifest.sub_key),
+                    None,
+                )
+                .await?;
+        }
+    }
+    info!("evicted {} blobs from the registry cache", evicted.len());
+    Ok(())
+}
+
+fn evict_candidates(
+    blobs: &HashMap<String, CachedBlob>,
+    max_size: u64,
+    keep: Option<&str>,
+) -> Vec<String> {
+    let mut size = blobs.values().map(|b| b.size).sum::<u64>();
+    let mut blobs = blobs
+        .iter()
+        .filter(|(digest, _)| Some(digest.as_str()) != keep)
+        .collect::<Vec<_>>();
+    blobs.sort_by_key(|(digest, blob)| (blob.last_access, digest.to_string()));
+
+    let mut evicted = Vec::new();
+    for (digest, blob) in blobs {
+        if size <= max_size {
+            break;
+        }
+        size -= blob.size;
+        evicted.push(digest.clone());
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac";
+
+    #[test]
+    fn test_parse_v2_path() {
+        assert_eq!(
+            parse_v2_path("library/nginx/manifests/latest"),
+            Some(("library/nginx", "manifests", "latest"))
+        );
+        assert_eq!(
+            parse_v2_path(&format!("ghcr.io/org/app/blobs/{}", DIGEST)),
+            Some(("ghcr.io/org/app", "blobs", DIGEST))
+        );
+        assert_eq!(parse_v2_path("library/nginx/blobs/../../etc"), None);
+        assert_eq!(parse_v2_path("library/nginx/tags/list"), None);
+    }
+
+    #[test]
+    fn test_repository() {
+        let repo = Repository::parse("nginx").unwrap();
+        assert_eq!(repo.key(), "docker.io/library/nginx");
+        assert_eq!(repo.host, DOCKER_HUB_API);
+        assert_eq!(Repository::parse("docker.io/library/nginx").unwrap(), repo);
+        let repo = Repository::parse("registry.local:5000/team/app").unwrap();
+        assert_eq!(repo.host, "registry.local:5000");
+        assert_eq!(repo.name, "team/app");
+        assert!(Repository::parse("ghcr.io/../app").is_none());
+    }
+
+    #[test]
+    fn test_allows_registry() {
+        let mut config = RegistryCacheConfigRequest::default();
+        assert!(allows_registry(&config, DEFAULT_REGISTRY));
+        assert!(!allows_registry(&config, "ghcr.io"));
+        assert!(!allows_registry(&config, "169.254.169.254:80"));
+        config.registries = vec!["https://GHCR.io/".to_string()];
+        assert!(allows_registry(&config, "ghcr.io"));
+        assert!(!allows_registry(&config, DEFAULT_REGISTRY));
+    }
+
+    #[test]
+    fn test_manifest_references() {
+        let manifest = serde_json::from_value::<Manifest>(json!({
+            "config": { "digest": "sha256:a", "size": 1 },
+            "layers": [{ "digest": "sha256:b", "size": 2 }],
+        }))
+        .unwrap();
+        assert!(manifest.references("sha256:a"));
+        assert!(manifest.references("sha256:b"));
+        assert!(!manifest.references(DIGEST));
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let params = parse_challenge(
+            r#"realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull""#,
+        );
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/nginx:pull");
+    }
+
+    #[test]
+    fn test_evict_candidates() {
+        let blob = |size, last_access| CachedBlob { size, last_access };
+        let blobs = HashMap::from([
+            ("a".to_string(), blob(100, 3)),
+            ("b".to_string(), blob(50, 1)),
+            ("c".to_string(), blob(70, 2)),
+        ]);
+        assert!(evict_candidates(&blobs, 300, None).is_empty());
+        assert_eq!(evict_candidates(&blobs, 200, None), vec!["b"]);
+        assert_eq!(evict_candidates(&blobs, 100, None), vec!["b", "c"]);
+        assert_eq!(evict_candidates(&blobs, 100, Some("b")), vec!["c", "a"]);
+    }
+}
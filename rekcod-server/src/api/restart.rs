@@ -0,0 +1,59 @@
+use axum::Json;
+use rekcod_core::{
+    api::{
+        req::{ContainerRestartAction, ContainerRestartEventRequest, ContainerRestartListRequest},
+        resp::ApiJsonResponse,
+    },
+    http::ApiError,
+};
+use tracing::{info, warn};
+
+use crate::db;
+
+pub async fn report_restart_event(
+    Json(req): Json<ContainerRestartEventRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    match req.action {
+        ContainerRestartAction::Restarted => info!(
+            "node {} restarted container({}): {}, count: {}",
+            req.node_name, req.container_name, req.container_id, req.restart_count
+        ),
+        _ => warn!(
+            "node {} container({}): {} restart {:?}, count: {}",
+            req.node_name, req.container_name, req.container_id, req.action, req.restart_count
+        ),
+    }
+
+    // keep the latest event of every container
+    let repositry = db::repository().await;
+    repositry
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: "restart".to_string(),
+            key: req.node_name.clone(),
+            sub_key: req.container_id.clone(),
+            value: serde_json::to_string(&req)?,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn list_restart_event(
+    Json(req): Json<ContainerRestartListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<ContainerRestartEventRequest>>>, ApiError> {
+    let db = db::repository().await;
+    let events = db
+        .kvs
+        .select("restart", req.node_name.as_deref(), None, None)
+        .await?;
+
+    Ok(ApiJsonResponse::success(
+        events
+            .iter()
+            .filter_map(|x| serde_json::from_str(&x.value).ok())
+            .collect::<Vec<_>>(),
+    )
+    .into())
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use axum::{body::Body, response::IntoResponse as _, response::Response, Json};
+use bollard::{
+    image::ListImagesOptions,
+    secret::{ContainerSummary, ImageSummary, Volume},
+    volume::ListVolumesOptions,
+};
+use hyper::StatusCode;
+use rekcod_core::{
+    api::{
+        req::ClusterSearchRequest,
+        resp::{ApiJsonResponse, ClusterNodeError, ClusterSearchItem, ClusterSearchResponse},
+    },
+    http::ApiError,
+};
+use serde::Serialize;
+
+use crate::{
+    api::{
+        cluster::{query_nodes, DEFAULT_NODE_TIMEOUT},
+        util::{list_all_containers, COMPOSE_PROJECT_LABEL},
+    },
+    node::manager::{node_manager, NodeState},
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 1000;
+
+const CONTAINER_SORTS: [&str; 5] = ["node", "name", "image", "status", "created"];
+const IMAGE_SORTS: [&str; 4] = ["node", "name", "size", "created"];
+const VOLUME_SORTS: [&str; 4] = ["node", "name", "driver", "created"];
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(i64),
+    Text(String),
+}
+
+/// how a kind of resource is listed, filtered and sorted
+trait Searchable: Serialize + Send + Sync + Sized + 'static {
+    const SORTS: &'static [&'static str];
+
+    fn list(node: Arc<NodeState>) -> impl Future<Output = anyhow::Result<Vec<Self>>> + Send;
+    fn matches(&self, req: &ClusterSearchRequest) -> bool;
+    /// `sort` is one of `SORTS` except node
+    fn sort_value(&self, sort: &str) -> SortValue;
+}
+
+pub async fn cluster_container_search(
+    Json(req): Json<ClusterSearchRequest>,
+) -> Result<Response, ApiError> {
+    search::<ContainerSummary>(req).await
+}
+
+pub async fn cluster_image_search(
+    Json(req): Json<ClusterSearchRequest>,
+) -> Result<Response, ApiError> {
+    search::<ImageSummary>(req).await
+}
+
+pub async fn cluster_volume_search(
+    Json(req): Json<ClusterSearchRequest>,
+) -> Result<Response, ApiError> {
+    search::<Volume>(req).await
+}
+
+async fn search<T: Searchable>(req: ClusterSearchRequest) -> Result<Response, ApiError> {
+    let sort = req.sort.as_deref().unwrap_or("node");
+    if !T::SORTS.contains(&sort) {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!(
+                "invalid sort {}, one of {}\n",
+                sort,
+                T::SORTS.join(", ")
+            )))?);
+    }
+
+    let timeout = Duration::from_secs(req.timeout.unwrap_or(DEFAULT_NODE_TIMEOUT));
+    let (online, offline) = node_manager()
+        .get_all_nodes(true)
+        .await?
+        .into_iter()
+        .partition::<Vec<_>, _>(|n| n.online());
+
+    let mut errors = offline
+        .iter()
+        .map(|node| ClusterNodeError {
+            node_name: node.node.name.clone(),
+            error: "node is offline".to_string(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = Vec::new();
+    for (node_name, res) in query_nodes(online, timeout, T::list).await {
+        match res {
+            Ok(list) => items.extend(list.into_iter().filter(|item| item.matches(&req)).map(
+                |item| ClusterSearchItem {
+                    node_name: node_name.clone(),
+                    item,
+                },
+            )),
+            Err(e) => errors.push(ClusterNodeError {
+                node_name,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    sort_items(&mut items, sort, req.desc);
+    let page = req.page.unwrap_or(1).max(1);
+    let page_size = req
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let total = items.len();
+    let items = items
+        .into_iter()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+        .collect();
+
+    let res = ClusterSearchResponse {
+        items,
+        total,
+        page,
+        page_size,
+        errors,
+    };
+    Ok(Json(ApiJsonResponse::success(res)).into_response())
+}
+
+/// by the sort key, then node and name so pages are stable
+fn sort_items<T: Searchable>(items: &mut [ClusterSearchItem<T>], sort: &str, desc: bool) {
+    items.sort_by(|a, b| {
+        let ordering = match sort {
+            "node" => a.node_name.cmp(&b.node_name),
+            sort => a.item.sort_value(sort).cmp(&b.item.sort_value(sort)),
+        };
+        let ordering = if desc { ordering.reverse() } else { ordering };
+        ordering
+            .then_with(|| a.node_name.cmp(&b.node_name))
+            .then_with(|| a.item.sort_value("name").cmp(&b.item.sort_value("name")))
+    });
+}
+
+impl Searchable for ContainerSummary {
+    const SORTS: &'static [&'static str] = &CONTAINER_SORTS;
+
+    async fn list(node: Arc<NodeState>) -> anyhow::Result<Vec<Self>> {
+        list_all_containers(&node.docker).await
+    }
+
+    fn matches(&self, req: &ClusterSearchRequest) -> bool {
+        let names = self
+            .names
+            .iter()
+            .flatten()
+            .map(|n| n.trim_start_matches('/'));
+        text_matches(names, req.name.as_deref())
+            && text_matches(self.image.as_deref(), req.image.as_deref())
+            && req
+                .status
+                .as_deref()
+                .is_none_or(|s| self.state.as_deref() == Some(s))
+            && label_matches(self.labels.as_ref(), req.label.as_deref())
+            && project_matches(self.labels.as_ref(), req.project.as_deref())
+    }
+
+    fn sort_value(&self, sort: &str) -> SortValue {
+        match sort {
+            "image" => SortValue::Text(self.image.clone().unwrap_or_default()),
+            "status" => SortValue::Text(self.state.clone().unwrap_or_default()),
+            "created" => SortValue::Number(self.created.unwrap_or_default()),
+            _ => SortValue::Text(
+                self.names
+                    .iter()
+                    .flatten()
+                    .next()
+                    .map(|n| n.trim_start_matches('/').to_string())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+impl Searchable for ImageSummary {
+    const SORTS: &'static [&'static str] = &IMAGE_SORTS;
+
+    async fn list(node: Arc<NodeState>) -> anyhow::Result<Vec<Self>> {
+        let options = Some(ListImagesOptions::<&str>::default());
+        Ok(node.docker.list_images(options).await?)
+    }
+
+    fn matches(&self, req: &ClusterSearchRequest) -> bool {
+        text_matches(self.repo_tags.iter(), req.name.as_deref())
+            && text_matches(self.repo_tags.iter(), req.image.as_deref())
+            && label_matches(Some(&self.labels), req.label.as_deref())
+            && project_matches(Some(&self.labels), req.project.as_deref())
+    }
+
+    fn sort_value(&self, sort: &str) -> SortValue {
+        match sort {
+            "size" => SortValue::Number(self.size),
+            "created" => SortValue::Number(self.created),
+            _ => SortValue::Text(self.repo_tags.first().cloned().unwrap_or_default()),
+        }
+    }
+}
+
+impl Searchable for Volume {
+    const SORTS: &'static [&'static str] = &VOLUME_SORTS;
+
+    async fn list(node: Arc<NodeState>) -> anyhow::Result<Vec<Self>> {
+        Ok(node
+            .docker
+            .list_volumes(None::<ListVolumesOptions<String>>)
+            .await?
+            .volumes
+            .unwrap_or_default())
+    }
+
+    fn matches(&self, req: &ClusterSearchRequest) -> bool {
+        text_matches(Some(&self.name), req.name.as_deref())
+            && label_matches(Some(&self.labels), req.label.as_deref())
+            && project_matches(Some(&self.labels), req.project.as_deref())
+    }
+
+    fn sort_value(&self, sort: &str) -> SortValue {
+        match sort {
+            "driver" => SortValue::Text(self.driver.clone()),
+            // a string or a time depending on the bollard features
+            "created" => SortValue::Text(
+                self.created_at
+                    .as_ref()
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+            ),
+            _ => SortValue::Text(self.name.clone()),
+        }
+    }
+}
+
+/// any of the values contains the filter, case insensitive
+fn text_matches<I, S>(values: I, filter: Option<&str>) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let Some(filter) = filter.filter(|f| !f.is_empty()) else {
+        return true;
+    };
+    let filter = filter.to_lowercase();
+    values
+        .into_iter()
+        .any(|v| v.as_ref().to_lowercase().contains(&filter))
+}
+
+/// `key` matches any value of the label, `key=value` only the exact value
+fn label_matches(labels: Option<&HashMap<String, String>>, filter: Option<&str>) -> bool {
+    let Some(filter) = filter.filter(|f| !f.is_empty()) else {
+        return true;
+    };
+    let Some(labels) = labels else {
+        return false;
+    };
+    match filter.split_once('=') {
+        Some((key, value)) => labels.get(key).is_some_and(|v| v == value),
+        None => labels.contains_key(filter),
+    }
+}
+
+fn project_matches(labels: Option<&HashMap<String, String>>, project: Option<&str>) -> bool {
+    project.is_none_or(|p| {
+        labels
+            .and_then(|l| l.get(COMPOSE_PROJECT_LABEL))
+            .is_some_and(|v| v == p)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, image: &str, state: &str, project: &str) -> ContainerSummary {
+        ContainerSummary {
+            names: Some(vec![format!("/{}", name)]),
+            image: Some(image.to_string()),
+            state: Some(state.to_string()),
+            labels: Some(HashMap::from([(
+                COMPOSE_PROJECT_LABEL.to_string(),
+                project.to_string(),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_container_matches() {
+        let redis = container("cache-redis-1", "redis:7", "running", "cache");
+        let req = |f: fn(&mut ClusterSearchRequest)| {
+            let mut req = ClusterSearchRequest::default();
+            f(&mut req);
+            req
+        };
+
+        assert!(redis.matches(&req(|_| {})));
+        assert!(redis.matches(&req(|r| r.name = Some("REDIS".to_string()))));
+        assert!(redis.matches(&req(|r| r.image = Some("redis".to_string()))));
+        assert!(!redis.matches(&req(|r| r.status = Some("exited".to_string()))));
+        assert!(redis.matches(&req(|r| r.project = Some("cache".to_string()))));
+        assert!(redis.matches(&req(|r| r.label = Some(COMPOSE_PROJECT_LABEL.to_string()))));
+        assert!(!redis.matches(&req(
+            |r| r.label = Some(format!("{}=web", COMPOSE_PROJECT_LABEL))
+        )));
+    }
+
+    #[test]
+    fn test_sort_items() {
+        let item = |node: &str, name: &str, created: i64| ClusterSearchItem {
+            node_name: node.to_string(),
+            item: ContainerSummary {
+                created: Some(created),
+                ..container(name, "redis", "running", "")
+            },
+        };
+        let mut items = vec![item("n2", "a", 3), item("n1", "b", 1), item("n1", "a", 2)];
+
+        sort_items(&mut items, "node", false);
+        let order = |items: &[ClusterSearchItem<ContainerSummary>]| {
+            items
+                .iter()
+                .map(|i| format!("{}/{}", i.node_name, i.item.sort_value("name").text()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(&items), vec!["n1/a", "n1/b", "n2/a"]);
+
+        sort_items(&mut items, "created", true);
+        assert_eq!(order(&items), vec!["n2/a", "n1/a", "n1/b"]);
+    }
+
+    impl SortValue {
+        fn text(&self) -> String {
+            match self {
+                SortValue::Number(n) => n.to_string(),
+                SortValue::Text(t) => t.clone(),
+            }
+        }
+    }
+}
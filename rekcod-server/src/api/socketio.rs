@@ -1,4 +1,4 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use bollard::{
     container::LogOutput,
@@ -16,7 +16,13 @@ use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 use url::Url;
 
-use crate::node::manager::{node_manager, NodeState};
+use crate::{
+    api::container_archive::container_path_exists,
+    node::manager::{node_manager, NodeState},
+};
+
+/// tried in order when no cmd is set
+const DEFAULT_SHELLS: [&str; 3] = ["/bin/bash", "/bin/sh", "/bin/ash"];
 
 pub fn socketio_routers() -> SocketIoLayer {
     let (layer, io) = SocketIo::new_layer();
@@ -26,15 +32,15 @@ pub fn socketio_routers() -> SocketIoLayer {
 
 async fn on_connect(socket: SocketRef) {
     info!(ns = socket.ns(), ?socket.id, "Socket.IO connected");
-    let (node_name, id) = match get_query_params(&socket).await {
-        Ok((node_name, id)) => (node_name, id),
+    let params = match get_query_params(&socket).await {
+        Ok(params) => params,
         Err(err) => {
             socket.emit("err", &err.to_string()).ok();
             return;
         }
     };
 
-    let node = match node_manager().get_node(&node_name).await {
+    let node = match node_manager().get_node(&params.node_name).await {
         Ok(Some(n)) => n,
         Ok(None) => {
             socket.emit("err", "node not found").ok();
@@ -47,7 +53,7 @@ async fn on_connect(socket: SocketRef) {
     };
 
     let node_clone = Arc::clone(&node);
-    let (exec_id, res) = match connect_to_docker(node_clone, &id).await {
+    let (exec_id, res) = match connect_to_docker(node_clone, &params).await {
         Ok(data) => {
             socket.emit("connected", "ok").ok();
             data
@@ -90,12 +96,13 @@ async fn on_connect(socket: SocketRef) {
     );
 
     let node_clone = Arc::clone(&node);
+    let resize_exec_id = exec_id.clone();
     socket.on(
         "resize",
         |_socket: SocketRef, Data::<ResizeInfo>(data)| async move {
             debug!(?data, "Received resize event:");
             {
-                let _ = resize_docker_cmd(node_clone, &data, &exec_id).await;
+                let _ = resize_docker_cmd(node_clone, &data, &resize_exec_id).await;
             }
         },
     );
@@ -116,7 +123,19 @@ async fn on_connect(socket: SocketRef) {
                     break;
                 }
 
-                Some(res) = output.next() => {
+                res = output.next() => {
+                    let Some(res) = res else {
+                        // the exec died, tell the client how
+                        let exit_code = match node.docker.inspect_exec(&exec_id).await {
+                            Ok(inspect) => inspect.exit_code,
+                            Err(err) => {
+                                info!(?err, "Failed to inspect exec");
+                                None
+                            }
+                        };
+                        let _ = socket.emit("exit", &exit_code);
+                        break;
+                    };
                     if let Ok(res) = res {
                         match res {
                             LogOutput::StdOut { message } => {
@@ -138,41 +157,120 @@ async fn on_connect(socket: SocketRef) {
 
 async fn connect_to_docker(
     node: Arc<NodeState>,
-    container_id: &str,
+    params: &ExecParams,
 ) -> anyhow::Result<(String, StartExecResults)> {
-    // get node
+    let cmd = match &params.cmd {
+        Some(cmd) => cmd.clone(),
+        None => vec![find_shell(&node, &params.id).await?],
+    };
     let config = CreateExecOptions {
-        cmd: Some(vec!["/bin/bash"]),
+        cmd: Some(cmd),
+        user: params.user.clone(),
+        working_dir: params.working_dir.clone(),
+        env: Some(params.env.clone()),
         attach_stdout: Some(true),
         attach_stderr: Some(true),
         attach_stdin: Some(true),
         tty: Some(true),
         ..Default::default()
     };
-    let s = &node.docker.create_exec(&container_id, config).await?;
+    let s = &node.docker.create_exec(&params.id, config).await?;
     let res = node
         .docker
         .start_exec(&s.id, None::<StartExecOptions>)
         .await?;
+    if let Some(size) = &params.size {
+        if let Err(err) = resize_docker_cmd(Arc::clone(&node), size, &s.id).await {
+            info!(?err, "Failed to set the terminal size");
+        }
+    }
     return Ok((s.id.clone(), res));
 }
 
-async fn get_query_params(socket: &SocketRef) -> anyhow::Result<(String, String)> {
+/// the first shell of the container, distroless images have none
+async fn find_shell(node: &NodeState, container_id: &str) -> anyhow::Result<String> {
+    for shell in DEFAULT_SHELLS {
+        if container_path_exists(node, container_id, shell).await? {
+            return Ok(shell.to_string());
+        }
+    }
+    Err(anyhow::anyhow!(
+        "no shell of {} in the container, set the cmd",
+        DEFAULT_SHELLS.join(", ")
+    ))
+}
+
+async fn get_query_params(socket: &SocketRef) -> anyhow::Result<ExecParams> {
     let base_url = "http://example.com";
     let req_parts = socket.req_parts();
     let full_url = format!("{}{}", base_url, &req_parts.uri.to_string());
     let url = Url::parse(&full_url)?;
-    let query_params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    ExecParams::parse(url.query_pairs().into_owned())
+}
+
+/// the query of the exec namespace
+#[derive(Debug, Default, PartialEq)]
+struct ExecParams {
+    node_name: String,
+    id: String,
+    /// one argument per `cmd` parameter or a json array, a shell of the container if not set
+    cmd: Option<Vec<String>>,
+    user: Option<String>,
+    working_dir: Option<String>,
+    /// `KEY=VALUE`, the `env` parameter may repeat
+    env: Vec<String>,
+    /// the initial terminal size
+    size: Option<ResizeInfo>,
+}
 
-    let node_name = query_params.get("node_name");
-    let id = query_params.get("id");
+impl ExecParams {
+    fn parse(pairs: impl Iterator<Item = (String, String)>) -> anyhow::Result<Self> {
+        let mut params = ExecParams::default();
+        let (mut height, mut width) = (None, None);
+        for (key, value) in pairs {
+            match key.as_str() {
+                "node_name" => params.node_name = value,
+                "id" => params.id = value,
+                "cmd" if value.trim().is_empty() => {}
+                "cmd" if value.trim_start().starts_with('[') => {
+                    let cmd = serde_json::from_str::<Vec<String>>(&value).map_err(|e| {
+                        anyhow::anyhow!("params error: cmd {} is not a json array: {}", value, e)
+                    })?;
+                    params.cmd = (!cmd.is_empty()).then_some(cmd);
+                }
+                "cmd" => params.cmd.get_or_insert_with(Vec::new).push(value),
+                "user" => params.user = (!value.is_empty()).then_some(value),
+                "workdir" => params.working_dir = (!value.is_empty()).then_some(value),
+                "env" => {
+                    if !value.contains('=') {
+                        return Err(anyhow::anyhow!("env {} is not KEY=VALUE", value));
+                    }
+                    params.env.push(value);
+                }
+                "height" => height = Some(parse_size(&key, &value)?),
+                "width" => width = Some(parse_size(&key, &value)?),
+                _ => {}
+            }
+        }
 
-    if node_name.is_none() || id.is_none() {
-        // params error
-        return Err(anyhow::anyhow!("params error").into());
+        if params.node_name.is_empty() || params.id.is_empty() {
+            return Err(anyhow::anyhow!(
+                "params error: node_name and id are required"
+            ));
+        }
+        params.size = match (height, width) {
+            (Some(height), Some(width)) => Some(ResizeInfo { height, width }),
+            (None, None) => None,
+            _ => return Err(anyhow::anyhow!("params error: set both height and width")),
+        };
+        Ok(params)
     }
+}
 
-    Ok((node_name.unwrap().to_string(), id.unwrap().to_string()))
+fn parse_size(key: &str, value: &str) -> anyhow::Result<u32> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("params error: {} {} is not a number", key, value))
 }
 
 async fn resize_docker_cmd(
@@ -191,7 +289,7 @@ async fn resize_docker_cmd(
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResizeInfo {
     pub height: u32,
     pub width: u32,
@@ -208,6 +306,70 @@ mod test {
     use rekcod_core::docker::rekcod_connect;
     use tokio::io::AsyncWriteExt;
 
+    use super::{ExecParams, ResizeInfo};
+
+    #[test]
+    fn test_exec_params() {
+        let parse = |query: &[(&str, &str)]| {
+            ExecParams::parse(query.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+        };
+        let params = parse(&[
+            ("node_name", "n1"),
+            ("id", "c1"),
+            ("cmd", "/bin/sh"),
+            ("cmd", "-c"),
+            ("cmd", "echo a  b"),
+            ("user", "root"),
+            ("workdir", "/app"),
+            ("env", "A=1"),
+            ("env", "B=x=y"),
+            ("height", "40"),
+            ("width", "120"),
+        ])
+        .unwrap();
+        assert_eq!(
+            params,
+            ExecParams {
+                node_name: "n1".to_string(),
+                id: "c1".to_string(),
+                cmd: Some(vec![
+                    "/bin/sh".to_string(),
+                    "-c".to_string(),
+                    "echo a  b".to_string()
+                ]),
+                user: Some("root".to_string()),
+                working_dir: Some("/app".to_string()),
+                env: vec!["A=1".to_string(), "B=x=y".to_string()],
+                size: Some(ResizeInfo {
+                    height: 40,
+                    width: 120
+                }),
+            }
+        );
+
+        let params = parse(&[("node_name", "n1"), ("id", "c1"), ("cmd", " ")]).unwrap();
+        assert_eq!(params.cmd, None);
+        let params = parse(&[
+            ("node_name", "n1"),
+            ("id", "c1"),
+            ("cmd", r#"["ls", "-l", "/my dir"]"#),
+        ])
+        .unwrap();
+        assert_eq!(
+            params.cmd,
+            Some(vec![
+                "ls".to_string(),
+                "-l".to_string(),
+                "/my dir".to_string()
+            ])
+        );
+        assert!(parse(&[("node_name", "n1"), ("id", "c1"), ("cmd", "[ls")]).is_err());
+        assert!(parse(&[("node_name", "n1")]).is_err());
+        assert!(parse(&[("node_name", "n1"), ("id", "c1"), ("env", "A")]).is_err());
+        assert!(parse(&[("node_name", "n1"), ("id", "c1"), ("height", "40")]).is_err());
+        assert!(parse(&[("node_name", "n1"), ("id", "c1"), ("width", "x")]).is_err());
+    }
+
     #[tokio::test]
     async fn test_attch_container() -> anyhow::Result<()> {
         let docker = Docker::connect_with_defaults()?;
@@ -0,0 +1,470 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::Json;
+use bollard::{
+    container::PruneContainersOptions,
+    image::PruneImagesOptions,
+    secret::{BuildPruneResponse, ContainerSummary, MountPointTypeEnum, SystemDataUsageResponse},
+    volume::PruneVolumesOptions,
+};
+use rekcod_core::{
+    api::{
+        req::{SystemDfRequest, SystemPruneRequest},
+        resp::{
+            ApiJsonResponse, ClusterDiskUsageResponse, ClusterNodeError, ClusterPruneResponse,
+            DiskUsageItem, GcItem, NodePruneReport,
+        },
+    },
+    client::get_client,
+    http::ApiError,
+};
+use tracing::info;
+
+use crate::{
+    api::cluster::{parse_selector, query_nodes, select_nodes},
+    node::manager::{node_manager, NodeState},
+};
+
+/// docker computes the size of every image, container and volume for df
+const DEFAULT_DF_TIMEOUT: u64 = 60;
+const DEFAULT_PRUNE_TIMEOUT: u64 = 600;
+
+pub async fn cluster_system_df(
+    Json(req): Json<SystemDfRequest>,
+) -> Result<Json<ApiJsonResponse<ClusterDiskUsageResponse>>, ApiError> {
+    let selector = parse_selector(req.selector.as_deref())?;
+    let all = node_manager().get_all_nodes(true).await?;
+    let (nodes, mut errors) = select_nodes(&all, &req.node_names, &selector);
+    let timeout = Duration::from_secs(req.timeout.unwrap_or(DEFAULT_DF_TIMEOUT));
+
+    let mut items = Vec::new();
+    for (node_name, res) in
+        query_nodes(
+            nodes,
+            timeout,
+            |node| async move { Ok(node.docker.df().await?) },
+        )
+        .await
+    {
+        match res {
+            Ok(df) => items.extend(disk_usage(&node_name, &df)),
+            Err(e) => errors.push(ClusterNodeError {
+                node_name,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(ApiJsonResponse::success(ClusterDiskUsageResponse { items, errors }).into())
+}
+
+/// prune the selected kinds of resources, a dry run lists what would be removed
+pub async fn cluster_system_prune(
+    Json(req): Json<SystemPruneRequest>,
+) -> Result<Json<ApiJsonResponse<ClusterPruneResponse>>, ApiError> {
+    let selector = parse_selector(req.selector.as_deref())?;
+    let all = node_manager().get_all_nodes(true).await?;
+    let (nodes, mut errors) = select_nodes(&all, &req.node_names, &selector);
+    let timeout = Duration::from_secs(req.timeout.unwrap_or(DEFAULT_PRUNE_TIMEOUT));
+
+    let mut reports = Vec::new();
+    for (node_name, res) in query_nodes(nodes, timeout, |node| prune_node(node, &req)).await {
+        match res {
+            Ok(report) => reports.push(report),
+            Err(e) => errors.push(ClusterNodeError {
+                node_name,
+                error: e.to_string(),
+            }),
+        }
+    }
+    reports.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+
+    Ok(ApiJsonResponse::success(ClusterPruneResponse {
+        nodes: reports,
+        errors,
+    })
+    .into())
+}
+
+async fn prune_node(
+    node: Arc<NodeState>,
+    req: &SystemPruneRequest,
+) -> anyhow::Result<NodePruneReport> {
+    let df = node.docker.df().await?;
+    let candidates = prune_candidates(&df, req);
+    let mut report = NodePruneReport {
+        node_name: node.node.name.clone(),
+        dry_run: req.dry_run,
+        ..Default::default()
+    };
+    if req.dry_run {
+        report.reclaimed = candidates.iter().map(|x| x.size).sum();
+        report.items = candidates;
+        return Ok(report);
+    }
+
+    // containers first, so their images and volumes are unused by the later prunes
+    if req.containers {
+        let res = node
+            .docker
+            .prune_containers(None::<PruneContainersOptions<String>>)
+            .await?;
+        add_deleted(
+            &mut report,
+            "container",
+            res.containers_deleted,
+            res.space_reclaimed,
+            &candidates,
+        );
+    }
+    if req.images {
+        let filters = HashMap::from([(
+            "dangling",
+            vec![if req.all_images { "false" } else { "true" }],
+        )]);
+        let res = node
+            .docker
+            .prune_images(Some(PruneImagesOptions { filters }))
+            .await?;
+        let deleted = res
+            .images_deleted
+            .map(|x| x.into_iter().filter_map(|i| i.deleted).collect());
+        add_deleted(
+            &mut report,
+            "image",
+            deleted,
+            res.space_reclaimed,
+            &candidates,
+        );
+    }
+    if req.volumes {
+        let filters = HashMap::from([("all", vec!["true"])]);
+        let res = node
+            .docker
+            .prune_volumes(Some(PruneVolumesOptions { filters }))
+            .await?;
+        add_deleted(
+            &mut report,
+            "volume",
+            res.volumes_deleted,
+            res.space_reclaimed,
+            &candidates,
+        );
+    }
+    if req.build_cache {
+        // bollard has no api for the build cache prune
+        let res = get_client()?
+            .post(format!("{}/build/prune", node.get_docker_proxy()))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BuildPruneResponse>()
+            .await?;
+        add_deleted(
+            &mut report,
+            "build_cache",
+            res.caches_deleted,
+            res.space_reclaimed,
+            &candidates,
+        );
+    }
+
+    info!(
+        "pruned node {}, reclaimed {} bytes, {} items",
+        report.node_name,
+        report.reclaimed,
+        report.items.len()
+    );
+    Ok(report)
+}
+
+/// only deleted ids known from df are listed, e.g. image layers are not
+fn add_deleted(
+    report: &mut NodePruneReport,
+    kind: &str,
+    deleted: Option<Vec<String>>,
+    reclaimed: Option<i64>,
+    candidates: &[GcItem],
+) {
+    let deleted = deleted.unwrap_or_default();
+    report.items.extend(
+        candidates
+            .iter()
+            .filter(|c| c.kind == kind && deleted.contains(&c.id))
+            .cloned(),
+    );
+    report.reclaimed += reclaimed.unwrap_or(0).max(0) as u64;
+}
+
+fn disk_usage(node_name: &str, df: &SystemDataUsageResponse) -> Vec<DiskUsageItem> {
+    let item = |kind: &str| DiskUsageItem {
+        node_name: node_name.to_string(),
+        kind: kind.to_string(),
+        ..Default::default()
+    };
+    let size = |x: i64| x.max(0) as u64;
+
+    let mut images = item("images");
+    for image in df.images.iter().flatten() {
+        images.total += 1;
+        // layers shared with other images stay after the image is removed
+        let own = size(image.size - image.shared_size.max(0));
+        if image.containers > 0 {
+            images.active += 1;
+        } else {
+            images.reclaimable += own;
+        }
+    }
+    images.size = df
+        .layers_size
+        .map(size)
+        .unwrap_or_else(|| df.images.iter().flatten().map(|i| size(i.size)).sum());
+
+    let mut containers = item("containers");
+    for container in df.containers.iter().flatten() {
+        let rw = size(container.size_rw.unwrap_or(0));
+        containers.total += 1;
+        containers.size += rw;
+        if container.state.as_deref() == Some("running") {
+            containers.active += 1;
+        } else {
+            containers.reclaimable += rw;
+        }
+    }
+
+    let mut volumes = item("volumes");
+    for volume in df.volumes.iter().flatten() {
+        let (used, bytes) = volume
+            .usage_data
+            .as_ref()
+            .map(|u| (u.ref_count > 0, size(u.size)))
+            .unwrap_or_default();
+        volumes.total += 1;
+        volumes.size += bytes;
+        if used {
+            volumes.active += 1;
+        } else {
+            volumes.reclaimable += bytes;
+        }
+    }
+
+    let mut build_cache = item("build_cache");
+    for cache in df.build_cache.iter().flatten() {
+        let bytes = size(cache.size.unwrap_or(0));
+        build_cache.total += 1;
+        build_cache.size += bytes;
+        if cache.in_use.unwrap_or(false) {
+            build_cache.active += 1;
+        } else if !cache.shared.unwrap_or(false) {
+            build_cache.reclaimable += bytes;
+        }
+    }
+
+    vec![images, containers, volumes, build_cache]
+}
+
+/// what the prunes of the request remove, images and volumes of removed containers included
+fn prune_candidates(df: &SystemDataUsageResponse, req: &SystemPruneRequest) -> Vec<GcItem> {
+    let mut items = Vec::new();
+
+    let (removed, kept): (Vec<&ContainerSummary>, Vec<&ContainerSummary>) =
+        df.containers.iter().flatten().partition(|c| {
+            req.containers && matches!(c.state.as_deref(), Some("exited" | "created" | "dead"))
+        });
+    for container in removed {
+        items.push(GcItem {
+            kind: "container".to_string(),
+            id: container.id.clone().unwrap_or_default(),
+            name: container
+                .names
+                .iter()
+                .flatten()
+                .next()
+                .map(|n| n.trim_start_matches('/').to_string())
+                .unwrap_or_default(),
+            size: container.size_rw.unwrap_or(0).max(0) as u64,
+        });
+    }
+
+    if req.images {
+        let used = kept
+            .iter()
+            .filter_map(|c| c.image_id.as_deref())
+            .collect::<HashSet<_>>();
+        for image in df.images.iter().flatten() {
+            let dangling = image.repo_tags.iter().all(|t| t == "<none>:<none>");
+            if used.contains(image.id.as_str()) || !(dangling || req.all_images) {
+                continue;
+            }
+            items.push(GcItem {
+                kind: "image".to_string(),
+                id: image.id.clone(),
+                name: image.repo_tags.join(","),
+                size: (image.size - image.shared_size.max(0)).max(0) as u64,
+            });
+        }
+    }
+
+    if req.volumes {
+        let used = kept
+            .iter()
+            .flat_map(|c| c.mounts.iter().flatten())
+            .filter(|m| m.typ == Some(MountPointTypeEnum::VOLUME))
+            .filter_map(|m| m.name.as_deref())
+            .collect::<HashSet<_>>();
+        for volume in df.volumes.iter().flatten() {
+            if used.contains(volume.name.as_str()) {
+                continue;
+            }
+            items.push(GcItem {
+                kind: "volume".to_string(),
+                id: volume.name.clone(),
+                name: volume.name.clone(),
+                size: volume
+                    .usage_data
+                    .as_ref()
+                    .map(|u| u.size.max(0) as u64)
+                    .unwrap_or(0),
+            });
+        }
+    }
+
+    if req.build_cache {
+        for cache in df.build_cache.iter().flatten() {
+            if cache.in_use.unwrap_or(false) || cache.shared.unwrap_or(false) {
+                continue;
+            }
+            items.push(GcItem {
+                kind: "build_cache".to_string(),
+                id: cache.id.clone().unwrap_or_default(),
+                name: cache.description.clone().unwrap_or_default(),
+                size: cache.size.unwrap_or(0).max(0) as u64,
+            });
+        }
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use bollard::secret::{ImageSummary, MountPoint, Volume, VolumeUsageData};
+
+    use super::*;
+
+    fn container(id: &str, state: &str, image_id: &str, volume: &str) -> ContainerSummary {
+        ContainerSummary {
+            id: Some(id.to_string()),
+            names: Some(vec![format!("/{}", id)]),
+            image_id: Some(image_id.to_string()),
+            state: Some(state.to_string()),
+            size_rw: Some(10),
+            mounts: Some(vec![MountPoint {
+                typ: Some(MountPointTypeEnum::VOLUME),
+                name: Some(volume.to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn image(id: &str, tag: &str, containers: i64) -> ImageSummary {
+        ImageSummary {
+            id: id.to_string(),
+            repo_tags: vec![tag.to_string()],
+            size: 100,
+            shared_size: 40,
+            containers,
+            ..Default::default()
+        }
+    }
+
+    fn volume(name: &str, ref_count: i64) -> Volume {
+        Volume {
+            name: name.to_string(),
+            usage_data: Some(VolumeUsageData {
+                size: 1000,
+                ref_count,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn df() -> SystemDataUsageResponse {
+        SystemDataUsageResponse {
+            layers_size: Some(500),
+            containers: Some(vec![
+                container("web", "running", "sha256:nginx", "html"),
+                container("job", "exited", "sha256:job", "data"),
+            ]),
+            images: Some(vec![
+                image("sha256:nginx", "nginx:1", 1),
+                image("sha256:job", "job:1", 1),
+                image("sha256:old", "<none>:<none>", 0),
+            ]),
+            volumes: Some(vec![volume("html", 1), volume("data", 1), volume("tmp", 0)]),
+            build_cache: None,
+        }
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let items = disk_usage("n1", &df());
+        let kinds = items.iter().map(|x| x.kind.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec!["images", "containers", "volumes", "build_cache"]
+        );
+
+        let images = &items[0];
+        assert_eq!((images.total, images.active), (3, 2));
+        assert_eq!((images.size, images.reclaimable), (500, 60));
+        let containers = &items[1];
+        assert_eq!((containers.total, containers.active), (2, 1));
+        assert_eq!((containers.size, containers.reclaimable), (20, 10));
+        let volumes = &items[2];
+        assert_eq!((volumes.active, volumes.reclaimable), (2, 1000));
+        assert_eq!(items[3].total, 0);
+    }
+
+    #[test]
+    fn test_prune_candidates() {
+        let ids = |req: &SystemPruneRequest| {
+            prune_candidates(&df(), req)
+                .into_iter()
+                .map(|x| format!("{}:{}", x.kind, x.id))
+                .collect::<Vec<_>>()
+        };
+
+        let req = SystemPruneRequest::default();
+        assert_eq!(ids(&req), vec!["container:job", "image:sha256:old"]);
+
+        // the image and volume of the removed container are unused after it
+        let req = SystemPruneRequest {
+            all_images: true,
+            volumes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&req),
+            vec![
+                "container:job",
+                "image:sha256:job",
+                "image:sha256:old",
+                "volume:data",
+                "volume:tmp"
+            ]
+        );
+
+        let req = SystemPruneRequest {
+            containers: false,
+            all_images: true,
+            ..Default::default()
+        };
+        assert_eq!(ids(&req), vec!["image:sha256:old"]);
+    }
+}
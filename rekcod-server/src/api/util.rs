@@ -0,0 +1,144 @@
+use axum::body::Bytes;
+use bollard::{
+    container::ListContainersOptions,
+    secret::{ContainerSummary, RestartPolicy, RestartPolicyNameEnum},
+    Docker,
+};
+use futures::{Stream, StreamExt as _};
+use rekcod_core::api::resp::ResourceContainer;
+use tracing::warn;
+
+/// the label docker compose sets to the project of a container
+pub(crate) const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+/// docker refuses memory limits lower than 6MB
+pub(crate) const MIN_MEMORY: i64 = 6 * 1024 * 1024;
+
+/// split an image reference into the `fromImage` and `tag` the pull api expects,
+/// docker pulls every tag if the tag is empty so `latest` is used by default
+pub(crate) fn split_image(image: &str) -> (String, String) {
+    if let Some((repo, digest)) = image.split_once('@') {
+        return (repo.to_string(), digest.to_string());
+    }
+
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    match image[name_start..].rfind(':') {
+        Some(i) => (
+            image[..name_start + i].to_string(),
+            image[name_start + i + 1..].to_string(),
+        ),
+        None => (image.to_string(), "latest".to_string()),
+    }
+}
+
+pub(crate) fn parse_restart_policy(policy: &str) -> anyhow::Result<RestartPolicy> {
+    let (name, max) = match policy.split_once(':') {
+        Some((name, max)) => (name, Some(max)),
+        None => (policy, None),
+    };
+    let name = match name {
+        "no" => RestartPolicyNameEnum::NO,
+        "always" => RestartPolicyNameEnum::ALWAYS,
+        "unless-stopped" => RestartPolicyNameEnum::UNLESS_STOPPED,
+        "on-failure" => RestartPolicyNameEnum::ON_FAILURE,
+        _ => return Err(anyhow::anyhow!("unknown restart policy {}", policy)),
+    };
+
+    let maximum_retry_count = match max {
+        Some(_) if name != RestartPolicyNameEnum::ON_FAILURE => {
+            return Err(anyhow::anyhow!(
+                "max retry count is only allowed for on-failure"
+            ))
+        }
+        Some(max) => Some(
+            max.parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("invalid max retry count {}", max))?,
+        ),
+        None => None,
+    };
+
+    Ok(RestartPolicy {
+        name: Some(name),
+        maximum_retry_count,
+    })
+}
+
+/// the chunks of an archive upload, a read error truncates the archive
+/// which docker then refuses to extract
+pub(crate) fn until_error<S, E>(archive: S) -> impl Stream<Item = Bytes> + Send + 'static
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    archive
+        .take_while(|chunk| {
+            if let Err(e) = chunk {
+                warn!("read archive error: {}", e);
+            }
+            futures::future::ready(chunk.is_ok())
+        })
+        .filter_map(|chunk| futures::future::ready(chunk.ok()))
+}
+
+pub(crate) async fn list_all_containers(docker: &Docker) -> anyhow::Result<Vec<ContainerSummary>> {
+    let options = Some(ListContainersOptions::<&str> {
+        all: true,
+        ..Default::default()
+    });
+    Ok(docker.list_containers(options).await?)
+}
+
+pub(crate) fn resource_container(container: &ContainerSummary) -> ResourceContainer {
+    ResourceContainer {
+        id: container.id.clone().unwrap_or_default(),
+        name: container
+            .names
+            .as_ref()
+            .and_then(|n| n.first())
+            .map(|n| n.trim_start_matches('/').to_string())
+            .unwrap_or_default(),
+        state: container.state.clone().unwrap_or_default(),
+    }
+}
+
+pub(crate) fn container_names(containers: &[ResourceContainer]) -> String {
+    containers
+        .iter()
+        .map(|c| format!("{}({})", c.name, c.state))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_image() {
+        assert_eq!(
+            split_image("nginx"),
+            ("nginx".to_string(), "latest".to_string())
+        );
+        assert_eq!(
+            split_image("localhost:5000/app:v1"),
+            ("localhost:5000/app".to_string(), "v1".to_string())
+        );
+        assert_eq!(
+            split_image("localhost:5000/app"),
+            ("localhost:5000/app".to_string(), "latest".to_string())
+        );
+        assert_eq!(
+            split_image("redis@sha256:abc"),
+            ("redis".to_string(), "sha256:abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_restart_policy() {
+        let policy = parse_restart_policy("on-failure:3").unwrap();
+        assert_eq!(policy.name, Some(RestartPolicyNameEnum::ON_FAILURE));
+        assert_eq!(policy.maximum_retry_count, Some(3));
+        assert!(parse_restart_policy("unless-stopped").is_ok());
+        assert!(parse_restart_policy("always:3").is_err());
+        assert!(parse_restart_policy("sometimes").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use bollard::{
+    secret::{ContainerSummary, MountPointTypeEnum, Volume, VolumePruneResponse},
+    volume::{CreateVolumeOptions, PruneVolumesOptions, RemoveVolumeOptions},
+};
+use rekcod_core::{
+    api::{
+        req::{
+            DockerPruneRequest, DockerVolumeCreateRequest, DockerVolumeQueryRequest,
+            NodeDockerQueryRequest,
+        },
+        resp::{ApiJsonResponse, ResourceContainer, VolumeDetailResponse},
+    },
+    http::ApiError,
+};
+use tracing::info;
+
+use crate::api::util::{container_names, list_all_containers, resource_container};
+
+pub async fn docker_volume_create_by_node(
+    Json(req): Json<DockerVolumeCreateRequest>,
+) -> Result<Json<ApiJsonResponse<Volume>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let options = CreateVolumeOptions {
+        name: req.name.clone(),
+        driver: req.driver.clone().unwrap_or("local".to_string()),
+        driver_opts: req.driver_opts.clone(),
+        labels: req.labels.clone(),
+    };
+    let volume = state.docker.create_volume(options).await?;
+    info!("created volume {} on node {}", volume.name, req.node_name);
+    Ok(ApiJsonResponse::success(volume).into())
+}
+
+pub async fn docker_volume_inspect_by_node(
+    Json(req): Json<DockerVolumeQueryRequest>,
+) -> Result<Json<ApiJsonResponse<VolumeDetailResponse>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let volume = state.docker.inspect_volume(&req.volume).await?;
+    let containers = list_all_containers(&state.docker).await?;
+    Ok(ApiJsonResponse::success(VolumeDetailResponse {
+        containers: volume_containers(&volume.name, &containers),
+        volume,
+    })
+    .into())
+}
+
+/// every volume of the node with the containers using it
+pub async fn docker_volume_usage_by_node(
+    Json(req): Json<NodeDockerQueryRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<VolumeDetailResponse>>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let volumes = state
+        .docker
+        .list_volumes::<String>(None)
+        .await?
+        .volumes
+        .unwrap_or_default();
+    let containers = list_all_containers(&state.docker).await?;
+    Ok(ApiJsonResponse::success(
+        volumes
+            .into_iter()
+            .map(|volume| VolumeDetailResponse {
+                containers: volume_containers(&volume.name, &containers),
+                volume,
+            })
+            .collect::<Vec<_>>(),
+    )
+    .into())
+}
+
+/// refuse to remove a volume in use unless force
+pub async fn docker_volume_remove_by_node(
+    Json(req): Json<DockerVolumeQueryRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let containers = list_all_containers(&state.docker).await?;
+    let users = volume_containers(&req.volume, &containers);
+    if !users.is_empty() && !req.force {
+        return Err(anyhow::anyhow!(
+            "volume {} is used by containers: {}",
+            req.volume,
+            container_names(&users)
+        )
+        .into());
+    }
+
+    state
+        .docker
+        .remove_volume(&req.volume, Some(RemoveVolumeOptions { force: req.force }))
+        .await?;
+    info!("removed volume {} on node {}", req.volume, req.node_name);
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn docker_volume_prune_by_node(
+    Json(req): Json<DockerPruneRequest>,
+) -> Result<Json<ApiJsonResponse<VolumePruneResponse>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    let mut filters = HashMap::new();
+    if req.all {
+        filters.insert("all".to_string(), vec!["true".to_string()]);
+    }
+    let res = state
+        .docker
+        .prune_volumes(Some(PruneVolumesOptions { filters }))
+        .await?;
+    info!(
+        "pruned volumes on node {}, reclaimed {} bytes",
+        req.node_name,
+        res.space_reclaimed.unwrap_or(0)
+    );
+    Ok(ApiJsonResponse::success(res).into())
+}
+
+pub(crate) fn volume_containers(
+    volume: &str,
+    containers: &[ContainerSummary],
+) -> Vec<ResourceContainer> {
+    containers
+        .iter()
+        .filter(|c| {
+            c.mounts.as_ref().is_some_and(|mounts| {
+                mounts.iter().any(|m| {
+                    m.typ == Some(MountPointTypeEnum::VOLUME) && m.name.as_deref() == Some(volume)
+                })
+            })
+        })
+        .map(resource_container)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::secret::MountPoint;
+
+    #[test]
+    fn test_volume_containers() {
+        let container = |name: &str, typ: MountPointTypeEnum, source: &str| ContainerSummary {
+            id: Some(name.to_string()),
+            names: Some(vec![format!("/{}", name)]),
+            state: Some("exited".to_string()),
+            mounts: Some(vec![MountPoint {
+                typ: Some(typ),
+                name: Some(source.to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let containers = vec![
+            container("db", MountPointTypeEnum::VOLUME, "data"),
+            container("web", MountPointTypeEnum::BIND, "data"),
+        ];
+
+        let users = volume_containers("data", &containers);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].name, "db");
+        assert_eq!(users[0].state, "exited");
+        assert!(volume_containers("logs", &containers).is_empty());
+    }
+}
@@ -0,0 +1,559 @@
+use std::path::{Path, PathBuf};
+
+use async_compression::tokio::bufread::GzipEncoder;
+use axum::{
+    body::{Body, Bytes},
+    extract::Query,
+    response::{IntoResponse as _, Response},
+    Json,
+};
+use bollard::{
+    container::{
+        Config, CreateContainerOptions, DownloadFromContainerOptions, RemoveContainerOptions,
+        UploadToContainerOptions,
+    },
+    secret::{HostConfig, Mount, MountTypeEnum},
+    volume::CreateVolumeOptions,
+    Docker,
+};
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
+use hyper::{header, StatusCode};
+use rekcod_core::{
+    api::{
+        req::{
+            VolumeBackupDeleteRequest, VolumeBackupListRequest, VolumeBackupRequest,
+            VolumeBackupScheduleRequest, VolumeRestoreRequest,
+        },
+        resp::{ApiJsonResponse, VolumeBackupResponse, VolumeBackupScheduleResponse},
+    },
+    http::ApiError,
+    utils::unix_timestamp,
+};
+use sha2::{Digest as _, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt as _, sync::mpsc};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::{
+    io::{ReaderStream, StreamReader},
+    sync::CancellationToken,
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    api::{image::pull_image_auto, util::until_error},
+    config::rekcod_server_config,
+    db,
+    node::manager::{node_manager, NodeState},
+};
+
+const BACKUP_MODULE: &str = "volume_backup";
+const SCHEDULE_MODULE: &str = "volume_backup_schedule";
+const BACKUP_DIR: &str = "backup";
+/// only used to mount the volume, the container is never started
+const HELPER_IMAGE: &str = "busybox";
+const HELPER_IMAGE_TAG: &str = "latest";
+const HELPER_MOUNT: &str = "/volume";
+const SCHEDULE_CHECK_INTERVAL: u64 = 60;
+
+/// archive a volume into the server data directory, or stream it to the caller
+pub async fn docker_volume_backup_by_node(
+    Json(req): Json<VolumeBackupRequest>,
+) -> Result<Response, ApiError> {
+    let state = get_state!(req.node_name);
+    state.docker.inspect_volume(&req.volume).await?;
+
+    if !req.download {
+        let backup = backup_volume(&state, &req.volume, false).await?;
+        return Ok(Json(ApiJsonResponse::success(backup)).into_response());
+    }
+
+    let helper = create_helper(&state, &req.volume, true).await?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let archive = archive_stream(&state.docker, &helper);
+        futures::pin_mut!(archive);
+        while let Some(chunk) = archive.next().await {
+            let failed = chunk.is_err();
+            if tx.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+        remove_helper(&state.docker, &helper).await;
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/gzip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.tar.gz\"", req.volume),
+        )
+        .body(Body::from_stream(UnboundedReceiverStream::new(rx)))?)
+}
+
+/// restore a backup kept on the server into a new or existing volume
+pub async fn docker_volume_restore_by_node(
+    Json(req): Json<VolumeRestoreRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let backup_id = req
+        .backup_id
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("backup_id is required"))?;
+    let state = get_state!(req.node_name);
+    let backup = get_backup(backup_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("backup {} not found", backup_id))?;
+
+    let path = backup_path(&backup);
+    let sha256 = file_sha256(&path).await?;
+    if sha256 != backup.sha256 {
+        return Err(anyhow::anyhow!("checksum mismatch of backup {}", backup.id).into());
+    }
+
+    let archive = ReaderStream::new(File::open(&path).await?);
+    restore_volume(&state, &req.volume, archive).await?;
+    info!(
+        "restored backup {} into volume {} on node {}",
+        backup.id, req.volume, req.node_name
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+/// restore the uploaded archive (the request body) into a new or existing volume
+pub async fn docker_volume_restore_upload_by_node(
+    Query(req): Query<VolumeRestoreRequest>,
+    body: Body,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let state = get_state!(req.node_name);
+
+    restore_volume(&state, &req.volume, body.into_data_stream()).await?;
+    info!(
+        "restored uploaded archive into volume {} on node {}",
+        req.volume, req.node_name
+    );
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn list_volume_backup(
+    Json(req): Json<VolumeBackupListRequest>,
+) -> Result<Json<ApiJsonResponse<Vec<VolumeBackupResponse>>>, ApiError> {
+    let mut backups = select_backups(req.node_name.as_deref(), req.volume.as_deref()).await?;
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(ApiJsonResponse::success(backups).into())
+}
+
+pub async fn delete_volume_backup(
+    Json(req): Json<VolumeBackupDeleteRequest>,
+) -> Result<Json<ApiJsonResponse<()>>, ApiError> {
+    let backup = get_backup(&req.backup_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("backup {} not found", req.backup_id))?;
+    delete_backup(&backup).await?;
+    Ok(ApiJsonResponse::success(()).into())
+}
+
+pub async fn schedule_volume_backup(
+    Json(req): Json<VolumeBackupScheduleRequest>,
+) -> Result<Json<ApiJsonResponse<Option<VolumeBackupScheduleResponse>>>, ApiError> {
+    let repository = db::repository().await;
+    if req.interval == 0 {
+        repository
+            .kvs
+            .delete(
+                SCHEDULE_MODULE,
+                Some(&req.node_name),
+                Some(&req.volume),
+                None,
+            )
+            .await?;
+        info!(
+            "removed backup schedule of volume {} on node {}",
+            req.volume, req.node_name
+        );
+        return Ok(ApiJsonResponse::success(None).into());
+    }
+
+    let state = get_state!(req.node_name);
+    state.docker.inspect_volume(&req.volume).await?;
+
+    let schedule = VolumeBackupScheduleResponse {
+        node_name: req.node_name,
+        volume: req.volume,
+        interval: req.interval,
+        keep: req.keep,
+        ..Default::default()
+    };
+    save_schedule(&schedule).await?;
+    info!(
+        "scheduled backup of volume {} on node {} every {}s",
+        schedule.volume, schedule.node_name, schedule.interval
+    );
+    Ok(ApiJsonResponse::success(Some(schedule)).into())
+}
+
+pub async fn list_volume_backup_schedule(
+) -> Result<Json<ApiJsonResponse<Vec<VolumeBackupScheduleResponse>>>, ApiError> {
+    Ok(ApiJsonResponse::success(select_schedules().await?).into())
+}
+
+/// run the due backup schedules, then drop the scheduled backups beyond `keep`
+pub async fn backup_scheduler(cancel: CancellationToken) {
+    info!("start volume backup scheduler");
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("volume backup scheduler cancelled");
+                break;
+            }
+            _ = tokio::time::sleep(std::time::Duration::from_secs(SCHEDULE_CHECK_INTERVAL)) => {
+                if let Err(e) = run_schedules().await {
+                    error!("run volume backup schedules error: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+async fn run_schedules() -> anyhow::Result<()> {
+    let now = unix_timestamp();
+    for mut schedule in select_schedules().await? {
+        if now < schedule.last_run + schedule.interval {
+            continue;
+        }
+
+        schedule.last_run = now;
+        schedule.last_error = match run_schedule(&schedule).await {
+            Ok(()) => None,
+            Err(e) => {
+                warn!(
+                    "scheduled backup of volume {} on node {} error: {:?}",
+                    schedule.volume, schedule.node_name, e
+                );
+                Some(e.to_string())
+            }
+        };
+        save_schedule(&schedule).await?;
+    }
+    Ok(())
+}
+
+async fn run_schedule(schedule: &VolumeBackupScheduleResponse) -> anyhow::Result<()> {
+    let state = node_manager()
+        .get_node(&schedule.node_name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("node {} not found", schedule.node_name))?;
+    backup_volume(&state, &schedule.volume, true).await?;
+
+    let backups = select_backups(Some(&schedule.node_name), Some(&schedule.volume)).await?;
+    for backup in expired_backups(backups, schedule.keep) {
+        delete_backup(&backup).await?;
+    }
+    Ok(())
+}
+
+/// scheduled backups beyond the newest `keep`, backups made by hand are never expired
+fn expired_backups(backups: Vec<VolumeBackupResponse>, keep: usize) -> Vec<VolumeBackupResponse> {
+    if keep == 0 {
+        return Vec::new();
+    }
+
+    let mut scheduled = backups
+        .into_iter()
+        .filter(|b| b.scheduled)
+        .collect::<Vec<_>>();
+    scheduled.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    scheduled.into_iter().skip(keep).collect()
+}
+
+async fn backup_volume(
+    state: &NodeState,
+    volume: &str,
+    scheduled: bool,
+) -> anyhow::Result<VolumeBackupResponse> {
+    let mut backup = VolumeBackupResponse {
+        id: Uuid::new_v4().to_string(),
+        node_name: state.node.name.clone(),
+        volume: volume.to_string(),
+        created_at: unix_timestamp(),
+        scheduled,
+        ..Default::default()
+    };
+    let path = backup_path(&backup);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let helper = create_helper(state, volume, true).await?;
+    let res = write_archive(archive_stream(&state.docker, &helper), &path).await;
+    remove_helper(&state.docker, &helper).await;
+    match res {
+        Ok((size, sha256)) => {
+            backup.size = size;
+            backup.sha256 = sha256;
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+    }
+
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: BACKUP_MODULE.to_string(),
+            key: backup.node_name.clone(),
+            sub_key: backup.volume.clone(),
+            third_key: backup.id.clone(),
+            value: serde_json::to_string(&backup)?,
+            ..Default::default()
+        })
+        .await?;
+    info!(
+        "backup volume {} on node {} into {}, {} bytes",
+        volume,
+        backup.node_name,
+        path.display(),
+        backup.size
+    );
+    Ok(backup)
+}
+
+async fn restore_volume<S, E>(state: &NodeState, volume: &str, archive: S) -> anyhow::Result<()>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    if state.docker.inspect_volume(volume).await.is_err() {
+        state
+            .docker
+            .create_volume(CreateVolumeOptions {
+                name: volume.to_string(),
+                ..Default::default()
+            })
+            .await?;
+        info!("created volume {} on node {}", volume, state.node.name);
+    }
+
+    // backups are archived from the mount point, so they extract into it from the root
+    let helper = create_helper(state, volume, false).await?;
+    let res = state
+        .docker
+        .upload_to_container_streaming(
+            &helper,
+            Some(UploadToContainerOptions {
+                path: "/",
+                ..Default::default()
+            }),
+            until_error(archive),
+        )
+        .await;
+    remove_helper(&state.docker, &helper).await;
+    Ok(res?)
+}
+
+/// copy the content of a volume into the volume of the same name on another node
+pub(crate) async fn copy_volume(
+    source: &NodeState,
+    target: &NodeState,
+    volume: &str,
+) -> anyhow::Result<()> {
+    let helper = create_helper(source, volume, true).await?;
+    let tar = source.docker.download_from_container(
+        &helper,
+        Some(DownloadFromContainerOptions { path: HELPER_MOUNT }),
+    );
+    let res = restore_volume(target, volume, tar).await;
+    remove_helper(&source.docker, &helper).await;
+    res
+}
+
+/// a stopped container with the volume mounted, docker can copy from or into it
+async fn create_helper(state: &NodeState, volume: &str, read_only: bool) -> anyhow::Result<String> {
+    let docker = &state.docker;
+    let image = format!("{}:{}", HELPER_IMAGE, HELPER_IMAGE_TAG);
+    if docker.inspect_image(&image).await.is_err() {
+        let nodes = node_manager().get_all_nodes(false).await?;
+        pull_image_auto(state, &nodes, &image, None, None, |_| {}).await?;
+    }
+
+    let config = Config {
+        image: Some(image),
+        host_config: Some(HostConfig {
+            mounts: Some(vec![Mount {
+                target: Some(HELPER_MOUNT.to_string()),
+                source: Some(volume.to_string()),
+                typ: Some(MountTypeEnum::VOLUME),
+                read_only: Some(read_only),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let res = docker
+        .create_container(None::<CreateContainerOptions<String>>, config)
+        .await?;
+    Ok(res.id)
+}
+
+async fn remove_helper(docker: &Docker, id: &str) {
+    let options = Some(RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    });
+    if let Err(e) = docker.remove_container(id, options).await {
+        warn!("remove volume helper container {} error: {:?}", id, e);
+    }
+}
+
+/// the volume content as a tar.gz stream
+fn archive_stream(docker: &Docker, helper: &str) -> impl Stream<Item = std::io::Result<Bytes>> {
+    let tar = docker
+        .download_from_container(
+            helper,
+            Some(DownloadFromContainerOptions { path: HELPER_MOUNT }),
+        )
+        .map_err(std::io::Error::other);
+    ReaderStream::new(GzipEncoder::new(StreamReader::new(tar)))
+}
+
+/// write the archive to the file, return its size and sha256
+async fn write_archive(
+    archive: impl Stream<Item = std::io::Result<Bytes>>,
+    path: &Path,
+) -> anyhow::Result<(u64, String)> {
+    futures::pin_mut!(archive);
+    let mut file = File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = archive.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+async fn file_sha256(path: &Path) -> anyhow::Result<String> {
+    let mut stream = ReaderStream::new(File::open(path).await?);
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn backup_path(backup: &VolumeBackupResponse) -> PathBuf {
+    Path::new(&rekcod_server_config().data_path)
+        .join(BACKUP_DIR)
+        .join(&backup.node_name)
+        .join(&backup.volume)
+        .join(format!("{}.tar.gz", backup.id))
+}
+
+async fn delete_backup(backup: &VolumeBackupResponse) -> anyhow::Result<()> {
+    let path = backup_path(backup);
+    if tokio::fs::try_exists(&path).await? {
+        tokio::fs::remove_file(&path).await?;
+    }
+    db::repository()
+        .await
+        .kvs
+        .delete(
+            BACKUP_MODULE,
+            Some(&backup.node_name),
+            Some(&backup.volume),
+            Some(&backup.id),
+        )
+        .await?;
+    info!(
+        "deleted backup {} of volume {} on node {}",
+        backup.id, backup.volume, backup.node_name
+    );
+    Ok(())
+}
+
+async fn get_backup(id: &str) -> anyhow::Result<Option<VolumeBackupResponse>> {
+    let backup = db::repository()
+        .await
+        .kvs
+        .select_one(BACKUP_MODULE, None, None, Some(id))
+        .await?;
+    Ok(match backup {
+        Some(backup) => Some(serde_json::from_str(&backup.value)?),
+        None => None,
+    })
+}
+
+async fn select_backups(
+    node_name: Option<&str>,
+    volume: Option<&str>,
+) -> anyhow::Result<Vec<VolumeBackupResponse>> {
+    Ok(db::repository()
+        .await
+        .kvs
+        .select(BACKUP_MODULE, node_name, volume, None)
+        .await?
+        .iter()
+        .filter_map(|x| serde_json::from_str(&x.value).ok())
+        .collect())
+}
+
+async fn select_schedules() -> anyhow::Result<Vec<VolumeBackupScheduleResponse>> {
+    Ok(db::repository()
+        .await
+        .kvs
+        .select(SCHEDULE_MODULE, None, None, None)
+        .await?
+        .iter()
+        .filter_map(|x| serde_json::from_str(&x.value).ok())
+        .collect())
+}
+
+async fn save_schedule(schedule: &VolumeBackupScheduleResponse) -> anyhow::Result<()> {
+    db::repository()
+        .await
+        .kvs
+        .insert_or_update_value(&db::kvs::KvsForDb {
+            module: SCHEDULE_MODULE.to_string(),
+            key: schedule.node_name.clone(),
+            sub_key: schedule.volume.clone(),
+            value: serde_json::to_string(schedule)?,
+            ..Default::default()
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(id: &str, created_at: u64, scheduled: bool) -> VolumeBackupResponse {
+        VolumeBackupResponse {
+            id: id.to_string(),
+            created_at,
+            scheduled,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_expired_backups() {
+        let backups = vec![
+            backup("a", 1, true),
+            backup("b", 3, true),
+            backup("c", 2, true),
+            backup("manual", 0, false),
+        ];
+
+        let expired = expired_backups(backups.clone(), 2);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, "a");
+        assert!(expired_backups(backups.clone(), 0).is_empty());
+        assert!(expired_backups(backups, 5).is_empty());
+    }
+}
@@ -1,6 +1,11 @@
 use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use crate::{config::rekcod_server_config, db, node::manager::node_manager};
+use crate::{
+    api::{registry::write_docker_config, registry_cache::spawn_seed},
+    config::rekcod_server_config,
+    db,
+    node::manager::node_manager,
+};
 use bollard::container::RemoveContainerOptions;
 use once_cell::sync::Lazy;
 use rekcod_core::{
@@ -272,9 +277,23 @@ pub async fn deploy(
             &cli_args,
             project_dir,
         )?;
-        if let Some(c) = get_docker_compose_file(&maps) {
-            docker_compose_cli.run_cache(c).await?;
+        let docker_config = write_docker_config().await?;
+        if let Some(dir) = &docker_config {
+            docker_compose_cli.docker_config(dir);
+        }
+        let res = match get_docker_compose_file(&maps) {
+            Some(c) => {
+                for image in get_docker_compose_images(c) {
+                    spawn_seed(&image);
+                }
+                docker_compose_cli.run_cache(c).await
+            }
+            None => Ok(()),
+        };
+        if let Some(dir) = docker_config {
+            let _ = tokio::fs::remove_dir_all(dir).await;
         }
+        res?;
     }
 
     let _ = log_writer.send(format!(
@@ -305,6 +324,24 @@ pub async fn deploy(
     Ok(())
 }
 
+/// the images of the services in a compose file
+fn get_docker_compose_images(content: &str) -> Vec<String> {
+    let Ok(compose) = serde_yaml::from_str::<serde_yaml::Value>(content) else {
+        return Vec::new();
+    };
+    compose
+        .get("services")
+        .and_then(|s| s.as_mapping())
+        .map(|services| {
+            services
+                .values()
+                .filter_map(|s| s.get("image").and_then(|i| i.as_str()))
+                .map(|i| i.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn get_docker_compose_file(map: &HashMap<String, String>) -> Option<&str> {
     let tmp = map
         .iter()
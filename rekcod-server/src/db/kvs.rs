@@ -113,8 +113,6 @@ impl DbSet<'static, Sqlite, SqliteRow, Kvs> {
             vs.push(third_key);
         }
 
-        q = format!("{} limit 1 ", q);
-
         let mut query = sqlx::query(&q);
         for v in vs {
             query = query.bind(v);
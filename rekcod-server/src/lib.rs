@@ -31,7 +31,8 @@ pub fn routers() -> Router {
         .nest(
             REKCOD_API_PREFIX_PATH,
             server::api_routers(Arc::clone(&ctx)),
-        );
+        )
+        .merge(server::registry_routers());
 
     let config = config::rekcod_server_config();
     if config.dashboard {
@@ -60,8 +61,9 @@ pub async fn init(cancel: CancellationToken) -> anyhow::Result<()> {
     // init app tmpl manager
     get_app_tmpl_manager().init().await?;
     // monitor nodes
-    let cancel = cancel.clone();
-    tokio::spawn(node::monitor::monitor(cancel));
+    tokio::spawn(node::monitor::monitor(cancel.clone()));
+    // scheduled volume backups
+    tokio::spawn(api::volume_backup::backup_scheduler(cancel));
     Ok(())
 }
 
@@ -2,13 +2,17 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use once_cell::sync::Lazy;
 use rekcod_core::{
-    api::{req::RegisterNodeRequest, resp::NodeItemResponse},
+    api::{
+        req::RegisterNodeRequest,
+        resp::{ApiJsonResponse, NodeItemResponse},
+    },
     auth::get_token,
-    constants::REKCOD_AGENT_PREFIX_PATH,
+    client::get_client,
+    constants::{DOCKER_PROXY_PATH, REKCOD_AGENT_PREFIX_PATH},
     docker::rekcod_connect,
     obj::NodeStatus,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::{sync::RwLock, time::Instant};
 use tracing::{info, warn};
 
@@ -36,7 +40,7 @@ impl NodeState {
         let node = Node::try_from(node)?;
         let docker_client = rekcod_connect(
             Some(format!("http://{}:{}", node.ip, node.port)),
-            rekcod_core::constants::DOCKER_PROXY_PATH,
+            DOCKER_PROXY_PATH,
             40,
             get_token(),
         )?;
@@ -70,15 +74,38 @@ impl NodeState {
         self.last_heartbeat = Instant::now();
     }
 
-    #[allow(dead_code)]
     fn get_node_host(&self) -> String {
         format!("http://{}:{}", self.node.ip, self.node.port)
     }
 
-    #[allow(dead_code)]
     pub fn get_node_agent(&self) -> String {
         format!("{}{}", self.get_node_host(), REKCOD_AGENT_PREFIX_PATH)
     }
+
+    /// docker api of this node, served by the agent proxy
+    pub fn get_docker_proxy(&self) -> String {
+        format!("{}{}", self.get_node_host(), DOCKER_PROXY_PATH)
+    }
+
+    /// post a json request to the agent api of this node
+    pub async fn agent_post<Req, Resp>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> anyhow::Result<ApiJsonResponse<Resp>>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned + Serialize + Send + Sync,
+    {
+        Ok(get_client()?
+            .post(format!("{}{}", self.get_node_agent(), path))
+            .json(req)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ApiJsonResponse<Resp>>()
+            .await?)
+    }
 }
 
 impl NodeManager {
@@ -302,6 +329,7 @@ impl Into<NodeItemResponse> for Node {
             os_version: self.os_version,
             os_kernel: self.os_kernel,
             status: self.status,
+            sys: None,
         }
     }
 }
@@ -20,16 +20,64 @@ use crate::{
             app_deploy, delete_deploy_app, dynamic_render_tmpl, get_app_template_by_name,
             get_app_tmpl_by_id, get_app_tmpl_list, list_deploy_app,
         },
+        container::docker_container_create_by_node,
+        container_archive::{
+            docker_container_archive_download_by_node, docker_container_archive_stat_by_node,
+            docker_container_archive_upload_by_node,
+        },
+        container_exec::docker_container_exec_by_node,
+        container_log::docker_container_logs_by_node,
+        container_migrate::{
+            confirm_container_migration, docker_container_migrate_by_node, list_container_migration,
+        },
         docker::{
-            docker_container_delete_by_node, docker_container_info_by_node,
-            docker_container_list_by_node, docker_container_logs_by_node,
-            docker_container_restart_by_node, docker_container_start_by_node,
-            docker_container_stop_by_node, docker_image_list_by_node, docker_image_pull_auto,
+            docker_container_delete_by_node, docker_container_diff_by_node,
+            docker_container_info_by_node, docker_container_kill_by_node,
+            docker_container_list_by_node, docker_container_pause_by_node,
+            docker_container_rename_by_node, docker_container_restart_by_node,
+            docker_container_start_by_node, docker_container_stop_by_node,
+            docker_container_top_by_node, docker_container_unpause_by_node,
+            docker_container_update_by_node, docker_image_list_by_node, docker_image_pull_auto,
             docker_info_by_node, docker_network_list_by_node, docker_volume_list_by_node,
         },
         env::{get_global_env, set_global_env},
+        gc::{list_gc_report, node_gc, report_gc},
+        image::{
+            docker_image_history_by_node, docker_image_inspect_by_node, docker_image_prune_by_node,
+            docker_image_push_by_node, docker_image_remove_by_node, docker_image_tag_by_node,
+        },
+        image_build::{docker_image_build_by_node, image_build_log, list_image_build},
+        image_distribute::cluster_image_distribute,
+        network::{
+            docker_network_connect_by_node, docker_network_create_by_node,
+            docker_network_disconnect_by_node, docker_network_inspect_by_node,
+            docker_network_prune_by_node, docker_network_remove_by_node,
+            docker_network_usage_by_node,
+        },
         node::{info_node, list_node},
         node_proxy::{node_proxy_handler, NodeProxyClient},
+        overview::cluster_overview,
+        process::{list_process, signal_process},
+        registry::{
+            add_registry_credential, delete_registry_credential, list_registry_credential,
+            test_registry_credential,
+        },
+        registry_cache::{
+            delete_registry_cache, get_registry_cache_config, list_registry_cache, registry_auth,
+            registry_v2, registry_v2_base, seed_registry_cache, set_registry_cache_config,
+        },
+        restart::{list_restart_event, report_restart_event},
+        search::{cluster_container_search, cluster_image_search, cluster_volume_search},
+        system::{cluster_system_df, cluster_system_prune},
+        volume::{
+            docker_volume_create_by_node, docker_volume_inspect_by_node,
+            docker_volume_prune_by_node, docker_volume_remove_by_node, docker_volume_usage_by_node,
+        },
+        volume_backup::{
+            delete_volume_backup, docker_volume_backup_by_node, docker_volume_restore_by_node,
+            docker_volume_restore_upload_by_node, list_volume_backup, list_volume_backup_schedule,
+            schedule_volume_backup,
+        },
     },
     db,
     node::manager::{node_manager, Node},
@@ -37,14 +85,73 @@ use crate::{
 
 pub fn api_routers(ctx: Arc<NodeProxyClient>) -> Router {
     Router::new()
+        .route("/cluster/overview", post(cluster_overview))
+        .route("/cluster/container/search", post(cluster_container_search))
+        .route("/cluster/system/df", post(cluster_system_df))
+        .route("/cluster/system/prune", post(cluster_system_prune))
+        .route("/cluster/image/search", post(cluster_image_search))
+        .route("/cluster/image/distribute", post(cluster_image_distribute))
+        .route("/cluster/volume/search", post(cluster_volume_search))
+        .route("/registry/credential/add", post(add_registry_credential))
+        .route("/registry/credential/list", post(list_registry_credential))
+        .route(
+            "/registry/credential/delete",
+            post(delete_registry_credential),
+        )
+        .route("/registry/credential/test", post(test_registry_credential))
+        .route("/registry/cache/config", post(get_registry_cache_config))
+        .route(
+            "/registry/cache/config/set",
+            post(set_registry_cache_config),
+        )
+        .route("/registry/cache/list", post(list_registry_cache))
+        .route("/registry/cache/delete", post(delete_registry_cache))
+        .route("/registry/cache/seed", post(seed_registry_cache))
         .route("/node/list", post(list_node))
         .route("/node/info", post(info_node))
         .route("/node/proxy/*sub", any(node_proxy_handler))
+        .route("/node/container/restart/list", post(list_restart_event))
+        .route("/node/gc", post(node_gc))
+        .route("/node/gc/report/list", post(list_gc_report))
+        .route("/node/process/list", post(list_process))
+        .route("/node/process/signal", post(signal_process))
+        .route(
+            "/node/docker/container/exec",
+            post(docker_container_exec_by_node),
+        )
+        .route(
+            "/node/docker/container/migrate",
+            post(docker_container_migrate_by_node),
+        )
+        .route(
+            "/node/docker/container/migrate/list",
+            post(list_container_migration),
+        )
+        .route(
+            "/node/docker/container/migrate/confirm",
+            post(confirm_container_migration),
+        )
+        .route(
+            "/node/docker/container/archive/stat",
+            post(docker_container_archive_stat_by_node),
+        )
+        .route(
+            "/node/docker/container/archive/download",
+            post(docker_container_archive_download_by_node),
+        )
+        .route(
+            "/node/docker/container/archive/upload",
+            post(docker_container_archive_upload_by_node),
+        )
         .route("/node/docker/info", post(docker_info_by_node))
         .route(
             "/node/docker/container/list",
             post(docker_container_list_by_node),
         )
+        .route(
+            "/node/docker/container/create",
+            post(docker_container_create_by_node),
+        )
         .route(
             "/node/docker/container/start/:id",
             post(docker_container_start_by_node),
@@ -69,13 +176,132 @@ pub fn api_routers(ctx: Arc<NodeProxyClient>) -> Router {
             "/node/docker/container/inspect/:id",
             post(docker_container_info_by_node),
         )
+        .route(
+            "/node/docker/container/rename/:id",
+            post(docker_container_rename_by_node),
+        )
+        .route(
+            "/node/docker/container/update/:id",
+            post(docker_container_update_by_node),
+        )
+        .route(
+            "/node/docker/container/pause/:id",
+            post(docker_container_pause_by_node),
+        )
+        .route(
+            "/node/docker/container/unpause/:id",
+            post(docker_container_unpause_by_node),
+        )
+        .route(
+            "/node/docker/container/kill/:id",
+            post(docker_container_kill_by_node),
+        )
+        .route(
+            "/node/docker/container/top/:id",
+            post(docker_container_top_by_node),
+        )
+        .route(
+            "/node/docker/container/diff/:id",
+            post(docker_container_diff_by_node),
+        )
         .route("/node/docker/image/list", post(docker_image_list_by_node))
         .route("/node/docker/image/pull_auto", post(docker_image_pull_auto))
+        .route(
+            "/node/docker/image/inspect",
+            post(docker_image_inspect_by_node),
+        )
+        .route(
+            "/node/docker/image/history",
+            post(docker_image_history_by_node),
+        )
+        .route(
+            "/node/docker/image/remove",
+            post(docker_image_remove_by_node),
+        )
+        .route("/node/docker/image/tag", post(docker_image_tag_by_node))
+        .route("/node/docker/image/push", post(docker_image_push_by_node))
+        .route("/node/docker/image/prune", post(docker_image_prune_by_node))
+        .route("/node/docker/image/build", post(docker_image_build_by_node))
+        .route("/node/docker/image/build/list", post(list_image_build))
+        .route("/node/docker/image/build/log", post(image_build_log))
         .route(
             "/node/docker/network/list",
             post(docker_network_list_by_node),
         )
+        .route(
+            "/node/docker/network/create",
+            post(docker_network_create_by_node),
+        )
+        .route(
+            "/node/docker/network/inspect",
+            post(docker_network_inspect_by_node),
+        )
+        .route(
+            "/node/docker/network/usage",
+            post(docker_network_usage_by_node),
+        )
+        .route(
+            "/node/docker/network/connect",
+            post(docker_network_connect_by_node),
+        )
+        .route(
+            "/node/docker/network/disconnect",
+            post(docker_network_disconnect_by_node),
+        )
+        .route(
+            "/node/docker/network/remove",
+            post(docker_network_remove_by_node),
+        )
+        .route(
+            "/node/docker/network/prune",
+            post(docker_network_prune_by_node),
+        )
         .route("/node/docker/volume/list", post(docker_volume_list_by_node))
+        .route(
+            "/node/docker/volume/create",
+            post(docker_volume_create_by_node),
+        )
+        .route(
+            "/node/docker/volume/inspect",
+            post(docker_volume_inspect_by_node),
+        )
+        .route(
+            "/node/docker/volume/usage",
+            post(docker_volume_usage_by_node),
+        )
+        .route(
+            "/node/docker/volume/remove",
+            post(docker_volume_remove_by_node),
+        )
+        .route(
+            "/node/docker/volume/prune",
+            post(docker_volume_prune_by_node),
+        )
+        .route(
+            "/node/docker/volume/backup",
+            post(docker_volume_backup_by_node),
+        )
+        .route("/node/docker/volume/backup/list", post(list_volume_backup))
+        .route(
+            "/node/docker/volume/backup/delete",
+            post(delete_volume_backup),
+        )
+        .route(
+            "/node/docker/volume/backup/schedule",
+            post(schedule_volume_backup),
+        )
+        .route(
+            "/node/docker/volume/backup/schedule/list",
+            post(list_volume_backup_schedule),
+        )
+        .route(
+            "/node/docker/volume/restore",
+            post(docker_volume_restore_by_node),
+        )
+        .route(
+            "/node/docker/volume/restore/upload",
+            post(docker_volume_restore_upload_by_node),
+        )
         .route("/app/tmpl/list", post(get_app_tmpl_list))
         .route("/app/tmpl/info/:id", post(get_app_tmpl_by_id))
         .route(
@@ -94,13 +320,75 @@ pub fn api_routers(ctx: Arc<NodeProxyClient>) -> Router {
 pub fn routers(ctx: Arc<NodeProxyClient>) -> Router {
     Router::new()
         .route("/node/register", post(register_node))
+        .route("/node/container/restart/report", post(report_restart_event))
+        .route("/node/gc/report", post(report_gc))
         .route("/node/proxy/*sub", any(node_proxy_handler))
         .route("/node/list", post(list_node))
         .route("/node/info", post(info_node))
+        .route("/node/process/list", post(list_process))
+        .route("/cluster/system/df", post(cluster_system_df))
+        .route("/cluster/system/prune", post(cluster_system_prune))
+        .route("/cluster/image/distribute", post(cluster_image_distribute))
+        .route("/registry/credential/add", post(add_registry_credential))
+        .route("/registry/credential/list", post(list_registry_credential))
+        .route(
+            "/registry/credential/delete",
+            post(delete_registry_credential),
+        )
+        .route("/registry/credential/test", post(test_registry_credential))
+        .route("/registry/cache/config", post(get_registry_cache_config))
+        .route(
+            "/registry/cache/config/set",
+            post(set_registry_cache_config),
+        )
+        .route("/registry/cache/list", post(list_registry_cache))
+        .route("/registry/cache/delete", post(delete_registry_cache))
+        .route("/registry/cache/seed", post(seed_registry_cache))
+        .route(
+            "/node/docker/container/exec",
+            post(docker_container_exec_by_node),
+        )
+        .route(
+            "/node/docker/container/migrate",
+            post(docker_container_migrate_by_node),
+        )
+        .route(
+            "/node/docker/container/migrate/list",
+            post(list_container_migration),
+        )
+        .route(
+            "/node/docker/container/migrate/confirm",
+            post(confirm_container_migration),
+        )
+        .route("/node/docker/image/build", post(docker_image_build_by_node))
+        .route("/node/docker/image/build/list", post(list_image_build))
+        .route("/node/docker/image/build/log", post(image_build_log))
+        .route(
+            "/node/docker/container/archive/stat",
+            post(docker_container_archive_stat_by_node),
+        )
+        .route(
+            "/node/docker/container/archive/download",
+            post(docker_container_archive_download_by_node),
+        )
+        .route(
+            "/node/docker/container/archive/upload",
+            post(docker_container_archive_upload_by_node),
+        )
         .with_state(Arc::clone(&ctx))
         .layer(middleware::from_fn(token_auth))
 }
 
+/// the registry api of the pull through cache, docker daemons use it as a mirror and
+/// can not send the rekcod token, so only the nodes are let in
+pub fn registry_routers() -> Router {
+    Router::new()
+        .route("/v2", get(registry_v2_base))
+        .route("/v2/", get(registry_v2_base))
+        .route("/v2/*path", get(registry_v2))
+        .layer(middleware::from_fn(registry_auth))
+}
+
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct RegisterNodeResponse {}
@@ -0,0 +1,316 @@
+use std::path::{Component, Path, PathBuf};
+
+use clap::Args;
+use futures::TryStreamExt as _;
+use rekcod_core::{
+    api::{
+        req::DockerContainerArchiveRequest,
+        resp::{ApiJsonResponse, ContainerPathStat},
+    },
+    client::get_client,
+};
+use tokio::io::AsyncWriteExt as _;
+use tokio_util::io::{ReaderStream, StreamReader, SyncIoBridge};
+
+use crate::config::rekcod_cli_config;
+
+#[derive(Debug, Args)]
+#[command(author, version, about = "copy files between a container and the local filesystem", long_about = None)]
+pub struct CpArgs {
+    /// `<node>:<container>:<path>` or a local path, `-` writes a tar to stdout
+    pub src: String,
+    /// `<node>:<container>:<path>` or a local path
+    pub dst: String,
+}
+
+/// a path in a container of a node
+#[derive(Debug, PartialEq)]
+struct RemotePath {
+    node_name: String,
+    container: String,
+    path: String,
+}
+
+pub(crate) async fn run(args: CpArgs) -> anyhow::Result<()> {
+    match (parse_remote(&args.src), parse_remote(&args.dst)) {
+        (Some(src), None) => download(src, &args.dst).await,
+        (None, Some(dst)) => upload(&args.src, dst).await,
+        _ => Err(anyhow::anyhow!(
+            "exactly one of src and dst must be <node>:<container>:<path>"
+        )),
+    }
+}
+
+async fn download(src: RemotePath, local: &str) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+
+    let req = DockerContainerArchiveRequest {
+        node_name: src.node_name,
+        container: src.container,
+        path: src.path,
+        file_name: None,
+    };
+    let resp = get_client()?
+        .post(format!(
+            "{}/node/docker/container/archive/download",
+            config.http_server_host()
+        ))
+        .json(&req)
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut reader = StreamReader::new(resp.bytes_stream().map_err(std::io::Error::other));
+
+    if local == "-" {
+        let mut stdout = tokio::io::stdout();
+        tokio::io::copy(&mut reader, &mut stdout).await?;
+        stdout.flush().await?;
+        return Ok(());
+    }
+
+    let local = PathBuf::from(local);
+    let reader = SyncIoBridge::new(reader);
+    tokio::task::spawn_blocking(move || unpack(reader, &local)).await?
+}
+
+/// unpack into `local` if it is a directory, otherwise the archive root is renamed to it
+fn unpack(reader: impl std::io::Read, local: &Path) -> anyhow::Result<()> {
+    if local.is_dir() {
+        return unpack_in(reader, local).map(|_| ());
+    }
+
+    let name = local
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("invalid local path {}", local.display()))?;
+    let dir = parent_dir(local);
+    // unpacked aside first so the root can be renamed, the same filesystem makes it a move
+    let tmp = dir.join(format!(".rekcod-cp-{}", std::process::id()));
+    std::fs::create_dir(&tmp)?;
+    let res = unpack_in(reader, &tmp).and_then(|root| {
+        let root = root.ok_or_else(|| anyhow::anyhow!("empty archive"))?;
+        std::fs::rename(tmp.join(root), dir.join(name))?;
+        Ok(())
+    });
+    let _ = std::fs::remove_dir_all(&tmp);
+    res
+}
+
+/// unpack the archive in `dir`, links of the archive are never followed out of it,
+/// return the archive root
+fn unpack_in(reader: impl std::io::Read, dir: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let mut root = None;
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        if root.is_none() {
+            root = Some(archive_root(&path)?);
+        }
+        if !entry.unpack_in(dir)? {
+            return Err(anyhow::anyhow!("unsafe path {} in archive", path.display()));
+        }
+    }
+    Ok(root)
+}
+
+fn archive_root(path: &Path) -> anyhow::Result<PathBuf> {
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(anyhow::anyhow!("unsafe path {} in archive", path.display()));
+    }
+
+    path.components()
+        .find(|c| matches!(c, Component::Normal(_)))
+        .map(|c| PathBuf::from(c.as_os_str()))
+        .ok_or_else(|| anyhow::anyhow!("invalid path {} in archive", path.display()))
+}
+
+async fn upload(local: &str, dst: RemotePath) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+    let local = PathBuf::from(local);
+    let metadata = tokio::fs::metadata(&local).await?;
+
+    // like docker cp: into the directory if it exists, otherwise as the path itself
+    let (path, name) = match stat(&dst).await {
+        Ok(stat) if stat.is_dir() => (
+            dst.path.clone(),
+            local
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("invalid local path {}", local.display()))?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        _ => {
+            let dst_path = Path::new(&dst.path);
+            let name = dst_path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("invalid container path {}", dst.path))?;
+            (
+                parent_dir(dst_path).to_string_lossy().to_string(),
+                name.to_string_lossy().to_string(),
+            )
+        }
+    };
+
+    let mut req = DockerContainerArchiveRequest {
+        node_name: dst.node_name,
+        container: dst.container,
+        path,
+        file_name: None,
+    };
+    let request = get_client()?.post(format!(
+        "{}/node/docker/container/archive/upload",
+        config.http_server_host()
+    ));
+
+    let resp = if metadata.is_dir() {
+        // build the tar while it is uploaded
+        let (reader, writer) = tokio::io::duplex(64 * 1024);
+        let writer = SyncIoBridge::new(writer);
+        let tar = tokio::task::spawn_blocking(move || {
+            let mut builder = tar::Builder::new(writer);
+            builder.append_dir_all(&name, &local)?;
+            builder.into_inner()?.shutdown()?;
+            anyhow::Ok(())
+        });
+
+        let resp = request
+            .query(&req)
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(reader)))
+            .send()
+            .await?;
+        tar.await??;
+        resp
+    } else {
+        req.file_name = Some(name);
+        let file = tokio::fs::File::open(&local).await?;
+        request
+            .query(&req)
+            .header(reqwest::header::CONTENT_LENGTH, metadata.len())
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await?
+    };
+
+    let resp = resp
+        .error_for_status()?
+        .json::<ApiJsonResponse<()>>()
+        .await?;
+    if resp.code() != 0 {
+        return Err(anyhow::anyhow!("{}", resp.msg()));
+    }
+    Ok(())
+}
+
+async fn stat(remote: &RemotePath) -> anyhow::Result<ContainerPathStat> {
+    let config = rekcod_cli_config();
+
+    let req = DockerContainerArchiveRequest {
+        node_name: remote.node_name.clone(),
+        container: remote.container.clone(),
+        path: remote.path.clone(),
+        file_name: None,
+    };
+    let resp = get_client()?
+        .post(format!(
+            "{}/node/docker/container/archive/stat",
+            config.http_server_host()
+        ))
+        .json(&req)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiJsonResponse<ContainerPathStat>>()
+        .await?;
+
+    if resp.code() != 0 {
+        return Err(anyhow::anyhow!("{}", resp.msg()));
+    }
+    resp.data()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no stat of {}", remote.path))
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `<node>:<container>:<path>`, the path must be absolute
+fn parse_remote(s: &str) -> Option<RemotePath> {
+    let mut parts = s.splitn(3, ':');
+    let node_name = parts.next().filter(|x| !x.is_empty())?;
+    let container = parts.next().filter(|x| !x.is_empty())?;
+    let path = parts.next().filter(|x| x.starts_with('/'))?;
+    Some(RemotePath {
+        node_name: node_name.to_string(),
+        container: container.to_string(),
+        path: path.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote() {
+        assert_eq!(
+            parse_remote("n1:web:/etc/nginx"),
+            Some(RemotePath {
+                node_name: "n1".to_string(),
+                container: "web".to_string(),
+                path: "/etc/nginx".to_string(),
+            })
+        );
+        assert_eq!(parse_remote("./dump.hprof"), None);
+        assert_eq!(parse_remote("n1:web:tmp"), None);
+        assert_eq!(parse_remote(":web:/tmp"), None);
+    }
+
+    #[test]
+    fn test_archive_root() {
+        assert_eq!(
+            archive_root(Path::new("nginx/nginx.conf")).unwrap(),
+            Path::new("nginx")
+        );
+        assert_eq!(
+            archive_root(Path::new("./nginx")).unwrap(),
+            Path::new("nginx")
+        );
+        assert!(archive_root(Path::new("../etc/passwd")).is_err());
+        assert!(archive_root(Path::new("/etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn test_unpack_symlink_escape() {
+        let base = std::env::temp_dir().join(format!("rekcod-cp-test-{}", std::process::id()));
+        let (dst, outside) = (base.join("dst"), base.join("outside"));
+        std::fs::create_dir_all(&dst).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "root/link", &outside)
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        builder
+            .append_data(&mut header, "root/link/evil", &b"evil"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        assert!(unpack(archive.as_slice(), &dst).is_err());
+        assert!(!outside.join("evil").exists());
+        assert!(unpack(archive.as_slice(), &base.join("renamed")).is_err());
+        assert!(!outside.join("evil").exists());
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use clap::{Args, Subcommand};
+use futures::TryStreamExt as _;
+use rekcod_core::{
+    api::{req::ImageDistributeRequest, resp::ImageDistributeEvent},
+    client::get_client,
+};
+use tabled::{settings::Style, Table, Tabled};
+use tokio::io::AsyncBufReadExt as _;
+use tokio_util::io::StreamReader;
+
+use crate::config::rekcod_cli_config;
+
+#[derive(Subcommand, Debug)]
+#[command(author, version, about = "images of nodes", long_about = None)]
+pub enum ImageArgs {
+    Distribute(DistributeArgs),
+}
+
+#[derive(Debug, Args)]
+#[command(author, version, about = "make sure an image exists on every selected node", long_about = None)]
+pub struct DistributeArgs {
+    pub image: String,
+    /// every online node matching the selector if not set
+    #[arg(short, long)]
+    pub node: Vec<String>,
+    /// `key=value,...` of name, host_name, ip, arch or os, `name=edge-*` is a prefix
+    #[arg(short, long)]
+    pub selector: Option<String>,
+    /// nodes copied to at the same time
+    #[arg(short, long)]
+    pub concurrency: Option<usize>,
+    /// `layer` or `full`
+    #[arg(long)]
+    pub transfer: Option<String>,
+    /// bytes per second of each layer transfer
+    #[arg(long)]
+    pub bandwidth_limit: Option<u64>,
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+struct DistributeRow {
+    node: String,
+    status: String,
+    source: String,
+    error: String,
+}
+
+pub(crate) async fn run(args: ImageArgs) -> anyhow::Result<()> {
+    match args {
+        ImageArgs::Distribute(args) => distribute(args).await,
+    }
+}
+
+async fn distribute(args: DistributeArgs) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+
+    let req = ImageDistributeRequest {
+        image: args.image,
+        node_names: args.node,
+        selector: args.selector,
+        concurrency: args.concurrency,
+        transfer: args.transfer,
+        bandwidth_limit: args.bandwidth_limit,
+    };
+    let resp = get_client()?
+        .post(format!(
+            "{}/cluster/image/distribute",
+            config.http_server_host()
+        ))
+        .json(&req)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(anyhow::anyhow!("{}", resp.text().await?.trim()));
+    }
+
+    let mut lines = StreamReader::new(resp.bytes_stream().map_err(std::io::Error::other)).lines();
+    let mut rows = Vec::<DistributeRow>::new();
+    let mut summary = None;
+    while let Some(line) = lines.next_line().await? {
+        let event = serde_json::from_str::<ImageDistributeEvent>(&line)?;
+        match event.status.as_str() {
+            "pending" => continue,
+            "progress" => println!("{}: {}", event.node_name, event.message),
+            "finished" => summary = Some(event.message),
+            _ => rows.push(DistributeRow {
+                node: event.node_name,
+                status: event.status,
+                source: event.source.unwrap_or_else(|| match event.error {
+                    Some(_) => String::new(),
+                    None => "-".to_string(),
+                }),
+                error: event.error.unwrap_or_default(),
+            }),
+        }
+    }
+
+    rows.sort_by(|a, b| a.node.cmp(&b.node));
+    let failed = rows.iter().filter(|r| r.status == "failed").count();
+    let mut table = Table::new(rows);
+    table.with(Style::blank());
+    println!("{}", table);
+
+    match summary {
+        Some(summary) if failed == 0 => {
+            println!("{}", summary);
+            Ok(())
+        }
+        Some(summary) => Err(anyhow::anyhow!("{}", summary)),
+        None => Err(anyhow::anyhow!("distribute ended before it finished")),
+    }
+}
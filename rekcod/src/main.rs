@@ -12,9 +12,12 @@ use rekcod_core::{
 use tracing::{debug, error};
 
 mod config;
+mod cp;
 mod docker;
 mod docker_compose;
+mod image;
 mod node;
+mod system;
 
 #[derive(Parser)]
 #[command(name = "rekcod")]
@@ -35,6 +38,14 @@ enum RekcodSubCommand {
     Docker(DockerArgs),
 
     DockerCompose(docker_compose::DockerArgs),
+
+    Cp(cp::CpArgs),
+
+    #[command(subcommand)]
+    Image(image::ImageArgs),
+
+    #[command(subcommand)]
+    System(system::SystemArgs),
 }
 
 #[tokio::main]
@@ -62,6 +73,9 @@ async fn main() {
         RekcodSubCommand::Node(args) => node::run(args).await,
         RekcodSubCommand::Docker(args) => docker::run(args).await,
         RekcodSubCommand::DockerCompose(docker_args) => docker_compose::run(docker_args).await,
+        RekcodSubCommand::Cp(args) => cp::run(args).await,
+        RekcodSubCommand::Image(args) => image::run(args).await,
+        RekcodSubCommand::System(args) => system::run(args).await,
     } {
         error!("{:?}", e);
         std::process::exit(1);
@@ -1,8 +1,8 @@
 use clap::{arg, command, Args, Subcommand};
 use rekcod_core::{
     api::{
-        req::NodeListRequest,
-        resp::{ApiJsonResponse, NodeItemResponse},
+        req::{NodeListRequest, ProcessListRequest},
+        resp::{ApiJsonResponse, NodeItemResponse, ProcessItemResponse},
     },
     client::get_client,
 };
@@ -14,6 +14,7 @@ use crate::config::rekcod_cli_config;
 #[command(author, version, about = "node command", long_about = None)]
 pub enum NodeArgs {
     List(ListNodeArgs),
+    Top(TopNodeArgs),
 }
 
 #[derive(Debug, Args)]
@@ -23,9 +24,34 @@ pub struct ListNodeArgs {
     pub all: bool,
 }
 
+#[derive(Debug, Args)]
+#[command(author, version, about = "list processes of node", long_about = None)]
+pub struct TopNodeArgs {
+    pub node: String,
+    /// sort by cpu, mem, pid or name
+    #[arg(short, long, default_value = "cpu")]
+    pub sort: String,
+    #[arg(long, default_value_t = false)]
+    pub asc: bool,
+    /// match process name or command line
+    #[arg(short, long)]
+    pub keyword: Option<String>,
+    #[arg(short, long)]
+    pub user: Option<String>,
+    /// only processes of the container, an id prefix is allowed
+    #[arg(short, long)]
+    pub container: Option<String>,
+    /// only processes running in containers
+    #[arg(long, default_value_t = false)]
+    pub container_only: bool,
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub limit: usize,
+}
+
 pub(crate) async fn run(args: NodeArgs) -> anyhow::Result<()> {
     match args {
         NodeArgs::List(args) => list_node(args).await,
+        NodeArgs::Top(args) => top_node(args).await,
     }
 }
 
@@ -58,3 +84,40 @@ async fn list_node(args: ListNodeArgs) -> anyhow::Result<()> {
     println!("{}", table);
     Ok(())
 }
+
+async fn top_node(args: TopNodeArgs) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+
+    let req = ProcessListRequest {
+        node_name: args.node,
+        sort: Some(args.sort),
+        asc: args.asc,
+        keyword: args.keyword,
+        user: args.user,
+        container_id: args.container,
+        container_only: args.container_only,
+        limit: Some(args.limit),
+    };
+    let resp = get_client()?
+        .post(format!("{}/node/process/list", config.http_server_host()))
+        .json(&req)
+        .send()
+        .await?
+        .json::<ApiJsonResponse<Vec<ProcessItemResponse>>>()
+        .await?;
+
+    if resp.code() != 0 {
+        return Err(anyhow::anyhow!("{}", resp.msg()));
+    }
+
+    let mut table = if let Some(data) = resp.data() {
+        Table::new(data)
+    } else {
+        Table::default()
+    };
+
+    table.with(Style::blank());
+
+    println!("{}", table);
+    Ok(())
+}
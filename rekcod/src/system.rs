@@ -0,0 +1,170 @@
+use clap::{Args, Subcommand};
+use rekcod_core::{
+    api::{
+        req::{SystemDfRequest, SystemPruneRequest},
+        resp::{
+            display_bytes, ApiJsonResponse, ClusterDiskUsageResponse, ClusterNodeError,
+            ClusterPruneResponse, NodePruneReport,
+        },
+    },
+    client::get_client,
+};
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::config::rekcod_cli_config;
+
+#[derive(Subcommand, Debug)]
+#[command(author, version, about = "docker disk usage of nodes", long_about = None)]
+pub enum SystemArgs {
+    Df(DfArgs),
+    Prune(PruneArgs),
+}
+
+#[derive(Debug, Args)]
+#[command(author, version, about = "show docker disk usage", long_about = None)]
+pub struct DfArgs {
+    /// every online node matching the selector if not set
+    #[arg(short, long)]
+    pub node: Vec<String>,
+    /// `key=value,...` of name, host_name, ip, arch or os, `name=edge-*` is a prefix
+    #[arg(short, long)]
+    pub selector: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[command(author, version, about = "remove unused docker data", long_about = None)]
+pub struct PruneArgs {
+    /// every online node matching the selector if not set
+    #[arg(short, long)]
+    pub node: Vec<String>,
+    /// `key=value,...` of name, host_name, ip, arch or os, `name=edge-*` is a prefix
+    #[arg(short, long)]
+    pub selector: Option<String>,
+    /// only show what would be removed
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+    /// remove all unused images, not only dangling ones
+    #[arg(short, long, default_value_t = false)]
+    pub all: bool,
+    /// remove unused volumes too
+    #[arg(long, default_value_t = false)]
+    pub volumes: bool,
+    /// keep the build cache
+    #[arg(long, default_value_t = false)]
+    pub no_build_cache: bool,
+}
+
+#[derive(Tabled)]
+#[tabled(rename_all = "UPPERCASE")]
+struct PruneRow {
+    node: String,
+    containers: usize,
+    images: usize,
+    volumes: usize,
+    build_cache: usize,
+    reclaimed: String,
+}
+
+impl From<&NodePruneReport> for PruneRow {
+    fn from(report: &NodePruneReport) -> Self {
+        let count = |kind: &str| report.items.iter().filter(|x| x.kind == kind).count();
+        Self {
+            node: report.node_name.clone(),
+            containers: count("container"),
+            images: count("image"),
+            volumes: count("volume"),
+            build_cache: count("build_cache"),
+            reclaimed: display_bytes(&report.reclaimed),
+        }
+    }
+}
+
+pub(crate) async fn run(args: SystemArgs) -> anyhow::Result<()> {
+    match args {
+        SystemArgs::Df(args) => df(args).await,
+        SystemArgs::Prune(args) => prune(args).await,
+    }
+}
+
+async fn df(args: DfArgs) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+
+    let req = SystemDfRequest {
+        node_names: args.node,
+        selector: args.selector,
+        ..Default::default()
+    };
+    let resp = get_client()?
+        .post(format!("{}/cluster/system/df", config.http_server_host()))
+        .json(&req)
+        .send()
+        .await?
+        .json::<ApiJsonResponse<ClusterDiskUsageResponse>>()
+        .await?;
+
+    if resp.code() != 0 {
+        return Err(anyhow::anyhow!("{}", resp.msg()));
+    }
+    let data = resp.data().cloned().unwrap_or_default();
+
+    let mut table = Table::new(&data.items);
+    table.with(Style::blank());
+    println!("{}", table);
+    print_errors(&data.errors);
+    Ok(())
+}
+
+async fn prune(args: PruneArgs) -> anyhow::Result<()> {
+    let config = rekcod_cli_config();
+
+    let req = SystemPruneRequest {
+        node_names: args.node,
+        selector: args.selector,
+        dry_run: args.dry_run,
+        all_images: args.all,
+        volumes: args.volumes,
+        build_cache: !args.no_build_cache,
+        ..Default::default()
+    };
+    let resp = get_client()?
+        .post(format!(
+            "{}/cluster/system/prune",
+            config.http_server_host()
+        ))
+        .json(&req)
+        .send()
+        .await?
+        .json::<ApiJsonResponse<ClusterPruneResponse>>()
+        .await?;
+
+    if resp.code() != 0 {
+        return Err(anyhow::anyhow!("{}", resp.msg()));
+    }
+    let data = resp.data().cloned().unwrap_or_default();
+
+    if args.dry_run {
+        for report in &data.nodes {
+            for item in &report.items {
+                println!(
+                    "would remove {} {} {} ({}) on {}",
+                    item.kind,
+                    item.id,
+                    item.name,
+                    display_bytes(&item.size),
+                    report.node_name
+                );
+            }
+        }
+    }
+    let mut table = Table::new(data.nodes.iter().map(PruneRow::from));
+    table.with(Style::blank());
+    println!("{}", table);
+    print_errors(&data.errors);
+    Ok(())
+}
+
+fn print_errors(errors: &[ClusterNodeError]) {
+    for e in errors {
+        eprintln!("node {}: {}", e.node_name, e.error);
+    }
+}
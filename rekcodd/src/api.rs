@@ -1,3 +1,5 @@
+use std::net::SocketAddr;
+
 use axum::{routing::get, Router};
 use tokio_util::sync::CancellationToken;
 use tracing::info;
@@ -17,14 +19,18 @@ pub(crate) async fn start(cancel: CancellationToken) -> anyhow::Result<()> {
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.api_port)).await?;
     info!("listening on {}", listener.local_addr()?);
-    axum::serve(listener, app)
-        .with_graceful_shutdown(async move {
-            tokio::select! {
-                _ = cancel.cancelled() => {
-                    info!("api server shutdown");
-                },
-            }
-        })
-        .await
-        .map_err(|e| e.into())
+    // the registry cache lets the nodes in by their address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                info!("api server shutdown");
+            },
+        }
+    })
+    .await
+    .map_err(|e| e.into())
 }